@@ -3,29 +3,91 @@
 //! 使用 SQLite 缓存历史记录元数据，避免每次都扫描文件系统。
 //! 完全复刻 DEV 版 (Tauri) 的缓存机制。
 
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use lru::LruCache;
 use std::num::NonZeroUsize;
 
-use crate::types::{SessionInfo, Project};
+use crate::paths::{canonicalize_cwd, codex_project_id};
+use crate::provider::sort_by_last_activity;
+use crate::timestamp::parse_timestamp;
+use crate::error::HistoryError;
+use crate::types::{CacheStats, SessionInfo, Project, ProjectStats, TrashItem};
 
 lazy_static::lazy_static! {
-    /// 按 CLI 类型分开的数据库连接
-    static ref DB_CONNECTIONS: Mutex<HashMap<String, Connection>> = Mutex::new(HashMap::new());
+    /// 按 CLI 类型分开的数据库连接池
+    static ref DB_POOLS: Mutex<HashMap<String, Arc<DbPool>>> = Mutex::new(HashMap::new());
     /// LRU 内存缓存（会话详情）- 增大到 200
     static ref SESSION_CACHE: Mutex<LruCache<String, CachedSessionDetail>> =
         Mutex::new(LruCache::new(NonZeroUsize::new(200).unwrap()));
 }
 
+/// SQLite 忙等待超时（毫秒），默认 5 秒
+static BUSY_TIMEOUT_MS: AtomicU64 = AtomicU64::new(5000);
+
+/// 每个连接池最多保留的空闲连接数
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// 单个 CLI 类型的数据库连接池
+///
+/// WAL 模式下多个连接可以并发读，因此读操作各自取一个连接；
+/// 写操作额外持有 `write_lock`，在进程内串行化，避免写写竞争触发 `database is locked`。
+struct DbPool {
+    cli_type: String,
+    idle: Mutex<Vec<Connection>>,
+    write_lock: Mutex<()>,
+}
+
+impl DbPool {
+    /// 取出一个空闲连接，没有时新建
+    /// 每次取出都重新应用当前的忙等待超时：set_busy_timeout 调用时被借出的连接归还后仍是旧值
+    fn checkout(&self) -> rusqlite::Result<Connection> {
+        let idle = self.idle.lock().unwrap().pop();
+        match idle {
+            Some(conn) => {
+                conn.busy_timeout(busy_timeout())?;
+                Ok(conn)
+            }
+            None => open_connection(&self.cli_type),
+        }
+    }
+
+    /// 归还连接，空闲连接过多时直接关闭
+    fn checkin(&self, conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(conn);
+        }
+    }
+
+    fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+        let conn = self.checkout()?;
+        let result = f(&conn);
+        self.checkin(conn);
+        result
+    }
+}
+
 /// 缓存的会话详情
 #[derive(Clone)]
 pub struct CachedSessionDetail {
+    pub info_json: String,
     pub messages_json: String,
     pub tool_stats_json: String,
+    /// 缓存时源文件的版本，用于判断是否失效
+    pub file_version: FileVersion,
+}
+
+/// 源文件版本：纳秒精度的修改时间加文件长度
+/// 同一秒内多次追加写入时秒级修改时间不变，只用于 SQLite 缓存行；内存缓存以此判断是否失效
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileVersion {
+    pub mtime_nanos: u128,
+    pub len: u64,
 }
 
 /// 获取数据目录
@@ -45,8 +107,8 @@ fn get_data_dir() -> PathBuf {
         .unwrap_or_else(|| PathBuf::from("."))
 }
 
-/// 初始化数据库连接
-fn init_db(cli_type: &str) -> rusqlite::Result<Connection> {
+/// 打开数据库连接
+fn open_connection(cli_type: &str) -> rusqlite::Result<Connection> {
     let data_dir = get_data_dir();
     std::fs::create_dir_all(&data_dir).ok();
 
@@ -55,8 +117,24 @@ fn init_db(cli_type: &str) -> rusqlite::Result<Connection> {
 
     // 优化设置
     conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")?;
+    conn.busy_timeout(busy_timeout())?;
+    Ok(conn)
+}
+
+fn busy_timeout() -> Duration {
+    Duration::from_millis(BUSY_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+/// 初始化数据库（建表、迁移），返回可直接放入连接池的连接
+fn init_db(cli_type: &str) -> rusqlite::Result<Connection> {
+    let conn = open_connection(cli_type)?;
+    create_base_schema(&conn)?;
+    migrate(&conn)?;
+    Ok(conn)
+}
 
-    // 创建表结构（与 DEV 版完全一致）
+/// 创建版本 0 的表结构（与 DEV 版完全一致），之后的变化都写成迁移
+fn create_base_schema(conn: &Connection) -> rusqlite::Result<()> {
     conn.execute_batch(
         "
         CREATE TABLE IF NOT EXISTS history_cache (
@@ -72,6 +150,9 @@ fn init_db(cli_type: &str) -> rusqlite::Result<Connection> {
             project_cwd TEXT,
             messages_json TEXT,
             tool_stats_json TEXT,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            file_size INTEGER NOT NULL DEFAULT 0,
             cached_at TEXT DEFAULT CURRENT_TIMESTAMP
         );
         CREATE TABLE IF NOT EXISTS trash (
@@ -88,55 +169,203 @@ fn init_db(cli_type: &str) -> rusqlite::Result<Connection> {
         CREATE INDEX IF NOT EXISTS idx_history_mtime ON history_cache(file_mtime);
         CREATE INDEX IF NOT EXISTS idx_history_cwd ON history_cache(project_cwd);
         "
-    )?;
+    )
+}
+
+/// 按顺序排列的结构迁移：第 i 项把版本 i 升级到 i + 1
+/// 新增字段时在末尾追加一项，不要修改已有迁移
+const MIGRATIONS: &[fn(&Connection) -> rusqlite::Result<()>] = &[
+    migrate_legacy_columns,
+    migrate_trash_items,
+    migrate_model,
+    migrate_text_stats,
+    migrate_file_cwd,
+    migrate_codex_project_ids,
+];
 
-    // 添加 user_turn_count 列（如果不存在）- 兼容旧数据库
+/// 当前结构版本，保存在 kv_store 的 schema_version 中
+const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// 读取结构版本（没有记录的旧数据库为 0）
+fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM kv_store WHERE key = 'schema_version'", [], |row| row.get(0))
+        .optional()?;
+    Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
+}
+
+/// 依次执行尚未执行的迁移，每项迁移与版本号更新在同一事务中提交
+/// 数据库版本高于当前代码（被新版写过）时不做任何改动
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let current = schema_version(conn)?;
+    if current >= SCHEMA_VERSION {
+        return Ok(());
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current.max(0) as usize) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO kv_store (key, value) VALUES ('schema_version', ?)",
+            [(version + 1).to_string()],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// 表中是否已有该列
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 列不存在时添加，返回是否新增
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<bool> {
+    if has_column(conn, table, column)? {
+        return Ok(false);
+    }
+    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    Ok(true)
+}
+
+/// 1: DEV 版早期数据库缺少的轮次、token 用量和文件大小列
+fn migrate_legacy_columns(conn: &Connection) -> rusqlite::Result<()> {
+    add_column(conn, "history_cache", "user_turn_count", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "history_cache", "input_tokens", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "history_cache", "output_tokens", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "history_cache", "file_size", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+/// 2: 回收站表扩展为与 TrashItem 对应：project_id 存 project_name，session_path 存 original_file，
+/// deleted_at 改存 Unix 秒；同一回收站目录下 dir_name 唯一
+fn migrate_trash_items(conn: &Connection) -> rusqlite::Result<()> {
+    add_column(conn, "trash", "session_id", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(conn, "trash", "dir_name", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(conn, "trash", "original_file_history", "TEXT")?;
+    add_column(conn, "trash", "trash_dir", "TEXT NOT NULL DEFAULT ''")?;
     conn.execute(
-        "ALTER TABLE history_cache ADD COLUMN user_turn_count INTEGER NOT NULL DEFAULT 0",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_trash_dir_name ON trash(trash_dir, dir_name)",
         [],
-    ).ok();
+    )?;
+    Ok(())
+}
 
-    Ok(conn)
+/// 3: 模型列；旧行没有模型信息，清零 file_mtime 让下次刷新时重新解析
+fn migrate_model(conn: &Connection) -> rusqlite::Result<()> {
+    if add_column(conn, "history_cache", "model", "TEXT")? {
+        conn.execute("UPDATE history_cache SET file_mtime = 0", [])?;
+    }
+    Ok(())
 }
 
-/// 获取或创建数据库连接
-pub fn get_db(cli_type: &str) -> rusqlite::Result<()> {
-    let mut conns = DB_CONNECTIONS.lock().unwrap();
-    if !conns.contains_key(cli_type) {
-        let conn = init_db(cli_type)?;
-        conns.insert(cli_type.to_string(), conn);
+/// 4: 正文字数与预览列，同样让旧行在下次刷新时重新解析
+fn migrate_text_stats(conn: &Connection) -> rusqlite::Result<()> {
+    let added_chars = add_column(conn, "history_cache", "total_chars", "INTEGER NOT NULL DEFAULT 0")?;
+    let added_words = add_column(conn, "history_cache", "word_count", "INTEGER NOT NULL DEFAULT 0")?;
+    let added_preview = add_column(conn, "history_cache", "preview", "TEXT")?;
+    if added_chars || added_words || added_preview {
+        conn.execute("UPDATE history_cache SET file_mtime = 0", [])?;
+    }
+    Ok(())
+}
+
+/// 5: 会话文件 -> cwd 记录（未写入 history_cache 的文件）
+fn migrate_file_cwd(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS file_cwd (
+            file_path TEXT PRIMARY KEY,
+            file_mtime INTEGER NOT NULL,
+            cwd TEXT
+        );"
+    )
+}
+
+/// 6: Codex 项目 ID 由规范化 cwd 改为 codex_project_id，重写已缓存行的 project_id
+fn migrate_codex_project_ids(conn: &Connection) -> rusqlite::Result<()> {
+    let rows: Vec<(String, String)> = {
+        let mut stmt = conn.prepare("SELECT file_path, project_id FROM history_cache WHERE cli_type = 'codex'")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (file_path, legacy_id) in rows {
+        conn.execute(
+            "UPDATE history_cache SET project_id = ? WHERE file_path = ?",
+            params![codex_project_id(&legacy_id), file_path],
+        )?;
     }
     Ok(())
 }
 
+/// 获取或创建连接池（首次创建时初始化数据库）
+fn get_pool(cli_type: &str) -> rusqlite::Result<Arc<DbPool>> {
+    let mut pools = DB_POOLS.lock().unwrap();
+    if let Some(pool) = pools.get(cli_type) {
+        return Ok(pool.clone());
+    }
+    let conn = init_db(cli_type)?;
+    let pool = Arc::new(DbPool {
+        cli_type: cli_type.to_string(),
+        idle: Mutex::new(vec![conn]),
+        write_lock: Mutex::new(()),
+    });
+    pools.insert(cli_type.to_string(), pool.clone());
+    Ok(pool)
+}
+
+/// 确保数据库已初始化
+pub fn get_db(cli_type: &str) -> rusqlite::Result<()> {
+    get_pool(cli_type).map(|_| ())
+}
+
+/// 用连接池中的连接执行读操作（可与其他读操作并发）
+fn with_db<T>(cli_type: &str, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+    get_pool(cli_type)?.with_conn(f)
+}
+
+/// 执行写操作（同一 CLI 类型的写入串行执行）
+fn with_db_write<T>(cli_type: &str, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+    let pool = get_pool(cli_type)?;
+    let _guard = pool.write_lock.lock().unwrap();
+    pool.with_conn(f)
+}
+
+/// 设置 SQLite 忙等待超时（毫秒），同时作用于连接池中已打开的连接（下次取出时生效）
+///
+/// 数据库被其他连接锁定时，写入方最多等待该时长再返回 `database is locked`。
+/// 超时越长，并发写入越不容易失败，但锁竞争时调用方阻塞也越久；设为 0 则立即报错。
+pub fn set_busy_timeout(ms: u64) {
+    BUSY_TIMEOUT_MS.store(ms, Ordering::Relaxed);
+}
+
 /// 从缓存查找匹配 cwd 的项目
 pub fn find_project_by_cwd_cached(cli_type: &str, cwd: &str) -> Option<Project> {
-    get_db(cli_type).ok()?;
-    let conns = DB_CONNECTIONS.lock().ok()?;
-    let conn = conns.get(cli_type)?;
-
     // 标准化路径
-    let cwd_normalized = cwd.to_lowercase().replace('\\', "/");
-
-    let mut stmt = conn.prepare(
-        "SELECT project_id, project_cwd, COUNT(*) as session_count, MAX(last_timestamp) as last_activity
-         FROM history_cache
-         WHERE project_cwd IS NOT NULL
-         GROUP BY project_id"
-    ).ok()?;
+    let cwd_normalized = canonicalize_cwd(cwd);
 
-    let projects: Vec<(String, Option<String>, usize, Option<String>)> = stmt
-        .query_map([], |row| {
+    let projects: Vec<(String, Option<String>, usize, Option<String>)> = with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT project_id, project_cwd, COUNT(*) as session_count, MAX(last_timestamp) as last_activity
+             FROM history_cache
+             WHERE project_cwd IS NOT NULL
+             GROUP BY project_id"
+        )?;
+        let rows = stmt.query_map([], |row| {
             Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-        })
-        .ok()?
-        .filter_map(|r| r.ok())
-        .collect();
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    })
+    .ok()?;
 
     for (project_id, project_cwd, session_count, last_activity) in projects {
         if let Some(ref pcwd) = project_cwd {
-            let pcwd_normalized = pcwd.to_lowercase().replace('\\', "/");
-            if pcwd_normalized == cwd_normalized {
+            if canonicalize_cwd(pcwd) == cwd_normalized {
                 return Some(Project {
                     id: project_id.clone(),
                     cwd: project_cwd,
@@ -151,175 +380,512 @@ pub fn find_project_by_cwd_cached(cli_type: &str, cwd: &str) -> Option<Project>
     None
 }
 
-/// 从缓存加载项目会话列表
-/// 复刻 DEV 版的完整过滤规则
-pub fn load_project_from_cache(cli_type: &str, project_id: &str) -> Vec<SessionInfo> {
-    if get_db(cli_type).is_err() {
-        return Vec::new();
-    }
+/// 读取已记录的会话文件 cwd：file_path -> (file_mtime, cwd)
+/// 来源为 history_cache 的 project_cwd 列和 file_cwd 表（未写入缓存的文件），后者优先
+pub fn load_file_cwds(cli_type: &str) -> HashMap<String, (i64, Option<String>)> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT file_path, file_mtime, project_cwd FROM history_cache WHERE project_cwd IS NOT NULL
+             UNION ALL
+             SELECT file_path, file_mtime, cwd FROM file_cwd"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    })
+    .unwrap_or_default()
+}
 
-    let conns = match DB_CONNECTIONS.lock() {
-        Ok(c) => c,
-        Err(_) => return Vec::new(),
-    };
+/// 写入会话文件的 cwd 记录 (file_path, file_mtime, cwd)，并删除 `removed` 中已不存在的文件
+pub fn save_file_cwds(cli_type: &str, entries: &[(String, i64, Option<String>)], removed: &[String]) -> rusqlite::Result<()> {
+    with_db_write(cli_type, |conn| {
+        let tx = conn.unchecked_transaction()?;
+        for (file_path, file_mtime, cwd) in entries {
+            tx.execute(
+                "INSERT OR REPLACE INTO file_cwd (file_path, file_mtime, cwd) VALUES (?, ?, ?)",
+                params![file_path, file_mtime, cwd],
+            )?;
+        }
+        for file_path in removed {
+            tx.execute("DELETE FROM file_cwd WHERE file_path = ?", [file_path])?;
+        }
+        tx.commit()
+    })
+}
 
-    let conn = match conns.get(cli_type) {
-        Some(c) => c,
-        None => return Vec::new(),
-    };
+/// 项目的缓存行：文件路径 -> (缓存时的文件修改时间, 是否计入会话数)
+/// 计入规则同 load_project_from_cache，不构建 SessionInfo
+pub fn project_cache_rows(cli_type: &str, project_id: &str, min_turns: usize) -> rusqlite::Result<HashMap<String, (i64, bool)>> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT file_path, file_mtime,
+                    message_count > 1
+                      AND user_turn_count >= ?2
+                      AND (first_timestamp IS NOT NULL OR last_timestamp IS NOT NULL)
+             FROM history_cache
+             WHERE project_id = ?1",
+        )?;
+        let rows = stmt.query_map(params![project_id, min_turns.max(1)], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?;
+        rows.collect()
+    })
+}
 
+/// 从缓存加载项目会话列表
+/// 复刻 DEV 版的完整过滤规则，轮次阈值与 CliHistoryProvider::load_project 一致
+pub fn load_project_from_cache(
+    cli_type: &str,
+    project_id: &str,
+    min_turns: usize,
+    min_messages: usize,
+    model: Option<&str>,
+) -> Vec<SessionInfo> {
     // 复刻 DEV 版过滤规则：
-    // 1. message_count > 1 (过滤空会话)
-    // 2. user_turn_count > 0 (过滤无用户消息的会话)
+    // 1. message_count > 1 (过滤空会话)，且不少于 min_messages
+    // 2. user_turn_count >= min_turns (0 轮会话始终过滤)
     // 3. 有有效时间戳
-    let mut stmt = match conn.prepare(
-        "SELECT session_id, file_path, message_count, first_timestamp, last_timestamp, project_cwd, user_turn_count
-         FROM history_cache
-         WHERE project_id = ?
-           AND message_count > 1
-           AND user_turn_count > 0
-           AND (first_timestamp IS NOT NULL OR last_timestamp IS NOT NULL)
-         ORDER BY last_timestamp DESC"
-    ) {
-        Ok(s) => s,
-        Err(_) => return Vec::new(),
-    };
-
-    stmt.query_map([project_id], |row| {
-        Ok(SessionInfo {
-            id: row.get(0)?,
-            file_path: row.get(1)?,
-            message_count: row.get(2)?,
-            first_timestamp: row.get(3)?,
-            last_timestamp: row.get(4)?,
-            cwd: row.get(5)?,
-            user_turn_count: row.get(6)?,
-            file_size: 0,
-        })
+    // 4. 指定 model 时模型名包含该字符串（不区分大小写）
+    let mut sessions: Vec<SessionInfo> = with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT session_id, file_path, message_count, first_timestamp, last_timestamp, project_cwd, user_turn_count,
+                    input_tokens, output_tokens, file_size, model, total_chars, word_count, preview
+             FROM history_cache
+             WHERE project_id = ?
+               AND message_count > 1
+               AND user_turn_count >= ?
+               AND (first_timestamp IS NOT NULL OR last_timestamp IS NOT NULL)
+               AND message_count >= ?4
+               AND (?3 IS NULL OR instr(lower(model), lower(?3)) > 0)
+             ORDER BY last_timestamp DESC"
+        )?;
+        let rows = stmt.query_map(params![project_id, min_turns.max(1), model, min_messages], |row| {
+            let first_timestamp: Option<String> = row.get(3)?;
+            let last_timestamp: Option<String> = row.get(4)?;
+            Ok(SessionInfo {
+                id: row.get(0)?,
+                file_path: row.get(1)?,
+                message_count: row.get(2)?,
+                first_timestamp_unix: first_timestamp.as_deref().and_then(parse_timestamp),
+                last_timestamp_unix: last_timestamp.as_deref().and_then(parse_timestamp),
+                first_timestamp,
+                last_timestamp,
+                cwd: row.get(5)?,
+                user_turn_count: row.get(6)?,
+                file_size: row.get(9)?,
+                input_tokens: row.get(7)?,
+                output_tokens: row.get(8)?,
+                model: row.get(10)?,
+                total_chars: row.get(11)?,
+                word_count: row.get(12)?,
+                preview: row.get(13)?,
+            })
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
     })
-    .map(|iter| iter.filter_map(|r| r.ok()).collect())
-    .unwrap_or_default()
+    .unwrap_or_default();
+
+    // SQL 按字符串排序，时间格式不一致时会错位，这里按解析后的时间重新排序
+    sort_by_last_activity(&mut sessions);
+    sessions
 }
 
 /// 更新缓存条目
 pub fn update_cache_entry(
     cli_type: &str,
-    file_path: &str,
     project_id: &str,
-    session_id: &str,
-    message_count: usize,
-    user_turn_count: usize,
-    first_timestamp: Option<&str>,
-    last_timestamp: Option<&str>,
+    session: &SessionInfo,
+    tool_stats: &HashMap<String, usize>,
     file_mtime: i64,
-    project_cwd: Option<&str>,
 ) -> rusqlite::Result<()> {
-    get_db(cli_type)?;
-    let conns = DB_CONNECTIONS.lock().unwrap();
-    let conn = conns.get(cli_type).ok_or(rusqlite::Error::InvalidQuery)?;
-
-    conn.execute(
-        "INSERT OR REPLACE INTO history_cache
-         (file_path, cli_type, project_id, session_id, message_count, user_turn_count, first_timestamp, last_timestamp, file_mtime, project_cwd)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-        params![file_path, cli_type, project_id, session_id, message_count, user_turn_count, first_timestamp, last_timestamp, file_mtime, project_cwd],
-    )?;
-
-    Ok(())
+    let tool_stats_json = serde_json::to_string(tool_stats).ok();
+    with_db_write(cli_type, |conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO history_cache
+             (file_path, cli_type, project_id, session_id, message_count, user_turn_count, first_timestamp, last_timestamp, file_mtime, project_cwd,
+              input_tokens, output_tokens, tool_stats_json, file_size, model, total_chars, word_count, preview)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                session.file_path,
+                cli_type,
+                project_id,
+                session.id,
+                session.message_count,
+                session.user_turn_count,
+                session.first_timestamp,
+                session.last_timestamp,
+                file_mtime,
+                session.cwd,
+                session.input_tokens,
+                session.output_tokens,
+                tool_stats_json,
+                session.file_size,
+                session.model,
+                session.total_chars,
+                session.word_count,
+                session.preview,
+            ],
+        )?;
+        Ok(())
+    })
 }
 
 /// 检查缓存是否有效（文件未修改）
 pub fn is_cache_valid(cli_type: &str, file_path: &str, file_mtime: i64) -> bool {
-    if get_db(cli_type).is_err() {
-        return false;
-    }
-
-    let conns = match DB_CONNECTIONS.lock() {
-        Ok(c) => c,
-        Err(_) => return false,
-    };
-
-    let conn = match conns.get(cli_type) {
-        Some(c) => c,
-        None => return false,
-    };
-
-    let cached_mtime: Option<i64> = conn
-        .query_row(
+    let cached_mtime: Option<i64> = with_db(cli_type, |conn| {
+        conn.query_row(
             "SELECT file_mtime FROM history_cache WHERE file_path = ?",
             [file_path],
             |row| row.get(0),
         )
-        .ok();
+    })
+    .ok();
 
-    cached_mtime.map_or(false, |m| m >= file_mtime)
+    cached_mtime.is_some_and(|m| m >= file_mtime)
 }
 
 /// 获取上次启动时间
 pub fn get_last_startup_time(cli_type: &str) -> i64 {
-    if get_db(cli_type).is_err() {
-        return 0;
-    }
-
-    let conns = match DB_CONNECTIONS.lock() {
-        Ok(c) => c,
-        Err(_) => return 0,
-    };
-
-    let conn = match conns.get(cli_type) {
-        Some(c) => c,
-        None => return 0,
-    };
-
-    conn.query_row(
-        "SELECT value FROM kv_store WHERE key = 'last_startup_time'",
-        [],
-        |row| row.get::<_, String>(0).map(|s| s.parse().unwrap_or(0)),
-    )
+    with_db(cli_type, |conn| {
+        conn.query_row(
+            "SELECT value FROM kv_store WHERE key = 'last_startup_time'",
+            [],
+            |row| row.get::<_, String>(0).map(|s| s.parse().unwrap_or(0)),
+        )
+    })
     .unwrap_or(0)
 }
 
 /// 更新启动时间
 pub fn update_startup_time(cli_type: &str) -> rusqlite::Result<()> {
-    get_db(cli_type)?;
-    let conns = DB_CONNECTIONS.lock().unwrap();
-    let conn = conns.get(cli_type).ok_or(rusqlite::Error::InvalidQuery)?;
-
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
+    set_startup_time(cli_type, now)
+}
 
-    conn.execute(
-        "INSERT OR REPLACE INTO kv_store (key, value) VALUES ('last_startup_time', ?)",
-        [now.to_string()],
-    )?;
+/// 写入启动时间（刷新被取消时用于恢复上次的值）
+pub fn set_startup_time(cli_type: &str, timestamp: i64) -> rusqlite::Result<()> {
+    with_db_write(cli_type, |conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO kv_store (key, value) VALUES ('last_startup_time', ?)",
+            [timestamp.to_string()],
+        )?;
+        Ok(())
+    })
+}
 
-    Ok(())
+/// 读取 kv_store 中的值（不存在时返回 None）
+pub fn get_kv(cli_type: &str, key: &str) -> Option<String> {
+    with_db(cli_type, |conn| {
+        conn.query_row("SELECT value FROM kv_store WHERE key = ?", [key], |row| row.get(0))
+    })
+    .ok()
+}
+
+/// 写入 kv_store
+pub fn set_kv(cli_type: &str, key: &str, value: &str) -> rusqlite::Result<()> {
+    with_db_write(cli_type, |conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO kv_store (key, value) VALUES (?, ?)",
+            [key, value],
+        )?;
+        Ok(())
+    })
+}
+
+/// 统计缓存概况：行数、项目数、最早/最新缓存时间、源文件总大小
+pub fn cache_stats(cli_type: &str) -> rusqlite::Result<CacheStats> {
+    with_db(cli_type, |conn| {
+        conn.query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT project_id), MIN(cached_at), MAX(cached_at), COALESCE(SUM(file_size), 0)
+             FROM history_cache",
+            [],
+            |row| {
+                Ok(CacheStats {
+                    row_count: row.get(0)?,
+                    project_count: row.get(1)?,
+                    oldest_cached_at: row.get(2)?,
+                    newest_cached_at: row.get(3)?,
+                    total_file_size: row.get(4)?,
+                })
+            },
+        )
+    })
+}
+
+/// 从缓存汇总项目统计（过滤规则同 load_project_from_cache）
+/// 同时返回缓存中还没有工具统计的会话文件，由调用方解析补齐
+pub fn project_stats(cli_type: &str, project_id: &str, min_turns: usize) -> rusqlite::Result<(ProjectStats, Vec<String>)> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT file_path, message_count, user_turn_count, file_size, input_tokens, output_tokens, tool_stats_json
+             FROM history_cache
+             WHERE project_id = ?
+               AND message_count > 1
+               AND user_turn_count >= ?
+               AND (first_timestamp IS NOT NULL OR last_timestamp IS NOT NULL)"
+        )?;
+        let mut rows = stmt.query(params![project_id, min_turns.max(1)])?;
+
+        let mut stats = ProjectStats::default();
+        let mut missing_tool_stats = Vec::new();
+        while let Some(row) = rows.next()? {
+            let file_path: String = row.get(0)?;
+            stats.session_count += 1;
+            stats.message_count += row.get::<_, usize>(1)?;
+            stats.user_turn_count += row.get::<_, usize>(2)?;
+            stats.total_bytes += row.get::<_, u64>(3)?;
+            stats.input_tokens += row.get::<_, i64>(4)?;
+            stats.output_tokens += row.get::<_, i64>(5)?;
+            let tool_stats = row
+                .get::<_, Option<String>>(6)?
+                .and_then(|json| serde_json::from_str::<HashMap<String, usize>>(&json).ok());
+            match tool_stats {
+                Some(usage) => stats.add_tool_usage(usage),
+                None => missing_tool_stats.push(file_path),
+            }
+        }
+        Ok((stats, missing_tool_stats))
+    })
+}
+
+/// 读取缓存的工具调用统计（缓存缺失或已过期时返回 None）
+pub fn get_cached_tool_stats(cli_type: &str, file_path: &str, file_mtime: i64) -> Option<HashMap<String, usize>> {
+    let (cached_mtime, json): (i64, Option<String>) = with_db(cli_type, |conn| {
+        conn.query_row(
+            "SELECT file_mtime, tool_stats_json FROM history_cache WHERE file_path = ?",
+            [file_path],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    })
+    .ok()?;
+    if cached_mtime < file_mtime {
+        return None;
+    }
+    serde_json::from_str(&json?).ok()
+}
+
+/// 获取所有缓存行的文件路径及缓存时的修改时间
+pub fn cached_file_mtimes(cli_type: &str) -> rusqlite::Result<HashMap<String, i64>> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare("SELECT file_path, file_mtime FROM history_cache")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    })
+}
+
+/// 获取项目的所有缓存行文件路径
+pub fn cached_project_files(cli_type: &str, project_id: &str) -> rusqlite::Result<Vec<String>> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare("SELECT file_path FROM history_cache WHERE project_id = ?")?;
+        let rows = stmt.query_map([project_id], |row| row.get(0))?;
+        rows.collect()
+    })
 }
 
 /// 删除缓存条目
 pub fn delete_cache_entry(cli_type: &str, file_path: &str) -> rusqlite::Result<()> {
-    get_db(cli_type)?;
-    let conns = DB_CONNECTIONS.lock().unwrap();
-    let conn = conns.get(cli_type).ok_or(rusqlite::Error::InvalidQuery)?;
-
-    conn.execute("DELETE FROM history_cache WHERE file_path = ?", [file_path])?;
-    Ok(())
+    with_db_write(cli_type, |conn| {
+        conn.execute("DELETE FROM history_cache WHERE file_path = ?", [file_path])?;
+        Ok(())
+    })
 }
 
 /// 清空缓存
 pub fn clear_cache(cli_type: &str) -> rusqlite::Result<usize> {
-    get_db(cli_type)?;
-    let conns = DB_CONNECTIONS.lock().unwrap();
-    let conn = conns.get(cli_type).ok_or(rusqlite::Error::InvalidQuery)?;
+    with_db_write(cli_type, |conn| conn.execute("DELETE FROM history_cache", []))
+}
+
+const TRASH_COLUMNS: &str =
+    "session_id, project_id, deleted_at, dir_name, session_path, original_file_history";
+
+/// 查询列；deleted_at 列声明为 TEXT，整数写入后会按文本存储，读取时转回整数
+const TRASH_SELECT: &str =
+    "session_id, project_id, CAST(deleted_at AS INTEGER), dir_name, session_path, original_file_history";
+
+fn trash_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<TrashItem> {
+    Ok(TrashItem {
+        session_id: row.get(0)?,
+        project_name: row.get(1)?,
+        deleted_at: row.get(2)?,
+        dir_name: row.get(3)?,
+        original_file: row.get(4)?,
+        original_file_history: row.get(5)?,
+        size_bytes: 0,
+    })
+}
+
+fn insert_trash_row(conn: &Connection, trash_dir: &str, item: &TrashItem) -> rusqlite::Result<usize> {
+    conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO trash (trash_dir, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            TRASH_COLUMNS
+        ),
+        params![
+            trash_dir,
+            item.session_id,
+            item.project_name,
+            item.deleted_at,
+            item.dir_name,
+            item.original_file,
+            item.original_file_history,
+        ],
+    )
+}
+
+/// 记录回收站项
+pub fn insert_trash_item(cli_type: &str, trash_dir: &str, item: &TrashItem) -> rusqlite::Result<()> {
+    with_db_write(cli_type, |conn| insert_trash_row(conn, trash_dir, item).map(|_| ()))
+}
+
+/// 批量导入回收站项（单个事务，已存在的 dir_name 跳过），返回新增条数
+pub fn import_trash_items(cli_type: &str, trash_dir: &str, items: &[TrashItem]) -> rusqlite::Result<usize> {
+    with_db_write(cli_type, |conn| {
+        let tx = conn.unchecked_transaction()?;
+        let mut imported = 0;
+        for item in items {
+            imported += insert_trash_row(&tx, trash_dir, item)?;
+        }
+        tx.commit()?;
+        Ok(imported)
+    })
+}
+
+/// 列出回收站目录下的所有回收站项（按删除顺序）
+pub fn list_trash_items(cli_type: &str, trash_dir: &str) -> rusqlite::Result<Vec<TrashItem>> {
+    with_db(cli_type, |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM trash WHERE trash_dir = ? ORDER BY id",
+            TRASH_SELECT
+        ))?;
+        let rows = stmt.query_map([trash_dir], trash_item_from_row)?;
+        rows.collect()
+    })
+}
+
+/// 在写事务中取出回收站项并执行 `apply`，成功后才删除记录，失败时记录保持不变
+/// 回收站项不存在时返回 `Ok(false)`
+pub fn take_trash_item(
+    cli_type: &str,
+    trash_dir: &str,
+    dir_name: &str,
+    apply: impl FnOnce(&TrashItem) -> Result<(), HistoryError>,
+) -> Result<bool, HistoryError> {
+    let pool = get_pool(cli_type)?;
+    let _guard = pool.write_lock.lock().unwrap();
+    let conn = pool.checkout()?;
+    let result = (|| {
+        let tx = conn.unchecked_transaction()?;
+        let item = tx
+            .query_row(
+                &format!("SELECT {} FROM trash WHERE trash_dir = ? AND dir_name = ?", TRASH_SELECT),
+                params![trash_dir, dir_name],
+                trash_item_from_row,
+            )
+            .optional()?;
+        let item = match item {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+        apply(&item)?;
+        tx.execute(
+            "DELETE FROM trash WHERE trash_dir = ? AND dir_name = ?",
+            params![trash_dir, dir_name],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    })();
+    pool.checkin(conn);
+    result
+}
 
-    conn.execute("DELETE FROM history_cache", [])
+/// 删除回收站记录（单个事务），返回删除条数
+pub fn remove_trash_items(cli_type: &str, trash_dir: &str, dir_names: &[String]) -> rusqlite::Result<usize> {
+    with_db_write(cli_type, |conn| {
+        let tx = conn.unchecked_transaction()?;
+        let mut removed = 0;
+        for dir_name in dir_names {
+            removed += tx.execute(
+                "DELETE FROM trash WHERE trash_dir = ? AND dir_name = ?",
+                params![trash_dir, dir_name],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    })
+}
+
+/// 删除回收站目录下的全部回收站记录，返回删除条数
+pub fn clear_trash_items(cli_type: &str, trash_dir: &str) -> rusqlite::Result<usize> {
+    with_db_write(cli_type, |conn| conn.execute("DELETE FROM trash WHERE trash_dir = ?", [trash_dir]))
+}
+
+/// 从另一份缓存数据库导入记录（用于合并多台机器的缓存）
+/// 只导入本地仍存在对应文件的行，返回导入行数
+pub fn import_cache(cli_type: &str, other_db_path: &str) -> rusqlite::Result<usize> {
+    with_db_write(cli_type, |conn| {
+        conn.execute("ATTACH DATABASE ? AS other", [other_db_path])?;
+        let result = import_attached(conn);
+        conn.execute("DETACH DATABASE other", []).ok();
+        result
+    })
+}
+
+fn import_attached(conn: &Connection) -> rusqlite::Result<usize> {
+    // 对方可能是旧版本数据库，只复制两边都有的列
+    let table_columns = |schema: &str| -> rusqlite::Result<Vec<String>> {
+        let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info(history_cache)", schema))?;
+        let cols = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(cols)
+    };
+    let local_cols = table_columns("main")?;
+    let columns: Vec<String> = table_columns("other")?
+        .into_iter()
+        .filter(|c| local_cols.contains(c))
+        .collect();
+    if !columns.iter().any(|c| c == "file_path") {
+        return Ok(0);
+    }
+
+    let file_paths: Vec<String> = {
+        let mut stmt = conn.prepare("SELECT file_path FROM other.history_cache")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.filter_map(|r| r.ok()).collect()
+    };
+
+    let column_list = columns.join(", ");
+    let tx = conn.unchecked_transaction()?;
+    let mut imported = 0;
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT OR REPLACE INTO history_cache ({cols}) SELECT {cols} FROM other.history_cache WHERE file_path = ?",
+            cols = column_list
+        ))?;
+        for file_path in file_paths.iter().filter(|p| std::path::Path::new(p).exists()) {
+            imported += stmt.execute([file_path])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(imported)
 }
 
 /// LRU 内存缓存操作
-pub fn get_session_from_memory(key: &str) -> Option<CachedSessionDetail> {
-    SESSION_CACHE.lock().ok()?.get(key).cloned()
+/// 文件修改时间与缓存时不一致的条目视为失效并移除
+pub fn get_session_from_memory(key: &str, file_version: FileVersion) -> Option<CachedSessionDetail> {
+    let mut cache = SESSION_CACHE.lock().ok()?;
+    match cache.get(key) {
+        Some(detail) if detail.file_version == file_version => Some(detail.clone()),
+        Some(_) => {
+            cache.pop(key);
+            None
+        }
+        None => None,
+    }
 }
 
 pub fn set_session_to_memory(key: String, detail: CachedSessionDetail) {
@@ -334,7 +900,17 @@ pub fn clear_memory_cache() {
     }
 }
 
-/// 获取文件修改时间
+/// 获取文件版本（内存缓存失效判断用），文件不存在时返回 None
+pub fn get_file_version(path: &str) -> Option<FileVersion> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?;
+    Some(FileVersion {
+        mtime_nanos: modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos(),
+        len: metadata.len(),
+    })
+}
+
+/// 获取文件修改时间（秒，SQLite 缓存行使用）
 pub fn get_file_mtime(path: &str) -> i64 {
     std::fs::metadata(path)
         .ok()
@@ -342,3 +918,134 @@ pub fn get_file_mtime(path: &str) -> i64 {
         .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64)
         .unwrap_or(0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 加入版本记录之前的数据库：history_cache 只有 DEV 版最初的列，回收站表也是旧结构
+    fn open_v0_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE history_cache (
+                file_path TEXT PRIMARY KEY,
+                cli_type TEXT NOT NULL,
+                project_id TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                message_count INTEGER NOT NULL,
+                first_timestamp TEXT,
+                last_timestamp TEXT,
+                file_mtime INTEGER NOT NULL,
+                project_cwd TEXT,
+                messages_json TEXT,
+                tool_stats_json TEXT,
+                cached_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE trash (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id TEXT NOT NULL,
+                session_path TEXT NOT NULL,
+                deleted_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE kv_store (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+            INSERT INTO history_cache (file_path, cli_type, project_id, session_id, message_count, file_mtime, project_cwd)
+                VALUES ('/p/s1.jsonl', 'claude', 'proj', 's1', 4, 1700000000, '/p');
+            INSERT INTO trash (project_id, session_path) VALUES ('proj', '/p/old.jsonl');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrates_v0_database() {
+        let conn = open_v0_db();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        create_base_schema(&conn).unwrap();
+        migrate(&conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        for column in ["user_turn_count", "input_tokens", "output_tokens", "file_size", "model", "total_chars", "word_count", "preview"] {
+            assert!(has_column(&conn, "history_cache", column).unwrap(), "缺少列 {}", column);
+        }
+        for column in ["session_id", "dir_name", "original_file_history", "trash_dir"] {
+            assert!(has_column(&conn, "trash", column).unwrap(), "缺少列 {}", column);
+        }
+        conn.execute("INSERT INTO file_cwd (file_path, file_mtime, cwd) VALUES ('/p/s1.jsonl', 1, '/p')", [])
+            .unwrap();
+
+        // 旧行保留，file_mtime 清零以便下次刷新时重新解析
+        let (session_id, file_mtime): (String, i64) = conn
+            .query_row("SELECT session_id, file_mtime FROM history_cache", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((session_id.as_str(), file_mtime), ("s1", 0));
+        let trash_rows: i64 = conn.query_row("SELECT COUNT(*) FROM trash", [], |row| row.get(0)).unwrap();
+        assert_eq!(trash_rows, 1);
+    }
+
+    #[test]
+    fn rewrites_codex_project_ids() {
+        let conn = open_v0_db();
+        conn.execute(
+            "INSERT INTO history_cache (file_path, cli_type, project_id, session_id, message_count, file_mtime, project_cwd)
+             VALUES ('/c/rollout-a.jsonl', 'codex', '/home/me/cx', 'a', 4, 1, '/home/me/cx')",
+            [],
+        )
+        .unwrap();
+
+        create_base_schema(&conn).unwrap();
+        migrate(&conn).unwrap();
+
+        let project_ids: Vec<(String, String)> = conn
+            .prepare("SELECT cli_type, project_id FROM history_cache ORDER BY cli_type")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            project_ids,
+            vec![
+                ("claude".to_string(), "proj".to_string()),
+                ("codex".to_string(), codex_project_id("/home/me/cx")),
+            ]
+        );
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_base_schema(&conn).unwrap();
+        migrate(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO history_cache (file_path, cli_type, project_id, session_id, message_count, file_mtime)
+             VALUES ('/p/s1.jsonl', 'claude', 'proj', 's1', 4, 42)",
+            [],
+        )
+        .unwrap();
+
+        create_base_schema(&conn).unwrap();
+        migrate(&conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        let file_mtime: i64 = conn.query_row("SELECT file_mtime FROM history_cache", [], |row| row.get(0)).unwrap();
+        assert_eq!(file_mtime, 42);
+    }
+
+    #[test]
+    fn newer_schema_is_left_untouched() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_base_schema(&conn).unwrap();
+        conn.execute("INSERT INTO kv_store (key, value) VALUES ('schema_version', '999')", []).unwrap();
+
+        migrate(&conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), 999);
+        assert!(!has_column(&conn, "history_cache", "model").unwrap());
+    }
+}
@@ -0,0 +1,179 @@
+//! 重复会话聚类
+//!
+//! 按轮次累积计算正文哈希：第 k 个哈希覆盖前 k 轮的用户与助手文本。
+//! 两个会话的全文哈希相同，或一个会话的全文是另一个会话的前 k 轮，即视为重复。
+
+use crate::provider::sort_by_last_activity;
+use crate::types::{Session, SessionInfo};
+use std::collections::HashMap;
+
+/// 每轮结束时的累积正文哈希（文本按空白折叠，忽略思考过程、工具调用和空消息）
+pub fn turn_prefix_hashes(session: &Session) -> Vec<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut hashes = Vec::new();
+    for turn in session.turns() {
+        for msg in turn.iter().filter(|m| m.role == "user" || m.role == "assistant") {
+            let text = msg.get_text();
+            let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if normalized.is_empty() {
+                continue;
+            }
+            hasher.update(msg.role.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(normalized.as_bytes());
+            hasher.update(b"\0");
+        }
+        hashes.push(hasher.finalize());
+    }
+    hashes
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[i] = root;
+    root
+}
+
+/// 将会话聚类为重复组，只返回包含两个及以上会话的组（组内按最后活动时间倒序，组按大小降序）
+/// `prefix_turns` 为 None 时按全文或前缀包含关系判定；为 Some(n) 时前 n 轮相同即归为一组，
+/// 不足 n 轮的会话不参与
+pub fn cluster_duplicates(sessions: Vec<(SessionInfo, Vec<blake3::Hash>)>, prefix_turns: Option<usize>) -> Vec<Vec<SessionInfo>> {
+    let mut parent: Vec<usize> = (0..sessions.len()).collect();
+    let mut first_with_key: HashMap<blake3::Hash, usize> = HashMap::new();
+    let mut union_key = |parent: &mut Vec<usize>, key: blake3::Hash, i: usize| match first_with_key.get(&key) {
+        Some(&j) => {
+            let (a, b) = (find(parent, i), find(parent, j));
+            parent[a] = b;
+        }
+        None => {
+            first_with_key.insert(key, i);
+        }
+    };
+
+    match prefix_turns {
+        Some(n) => {
+            for (i, (_, hashes)) in sessions.iter().enumerate() {
+                if let Some(&key) = hashes.get(n.max(1) - 1) {
+                    union_key(&mut parent, key, i);
+                }
+            }
+        }
+        None => {
+            // 先登记每个会话的所有前缀，再用全文哈希查找：全文相同或为他人前缀的会话归为一组
+            let mut prefix_owners: HashMap<blake3::Hash, Vec<usize>> = HashMap::new();
+            for (i, (_, hashes)) in sessions.iter().enumerate() {
+                for &h in hashes {
+                    prefix_owners.entry(h).or_default().push(i);
+                }
+            }
+            for (i, (_, hashes)) in sessions.iter().enumerate() {
+                let full = match hashes.last() {
+                    Some(&h) => h,
+                    None => continue,
+                };
+                union_key(&mut parent, full, i);
+                for &j in prefix_owners.get(&full).into_iter().flatten() {
+                    let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                    parent[a] = b;
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<SessionInfo>> = HashMap::new();
+    for (i, (info, _)) in sessions.into_iter().enumerate() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(info);
+    }
+
+    let mut clusters: Vec<Vec<SessionInfo>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    for cluster in clusters.iter_mut() {
+        sort_by_last_activity(cluster);
+    }
+    clusters.sort_by(|a, b| {
+        b.len().cmp(&a.len()).then_with(|| a[0].file_path.cmp(&b[0].file_path))
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{msg, session, text};
+
+    /// 按 (用户输入, 助手回复) 逐轮构造会话，返回聚类所需的 (会话信息, 前缀哈希)
+    fn convo(id: &str, turns: &[(&str, &str)]) -> (SessionInfo, Vec<blake3::Hash>) {
+        let messages = turns
+            .iter()
+            .flat_map(|(q, a)| [msg("user", vec![text(q)]), msg("assistant", vec![text(a)])])
+            .collect();
+        let session = session(id, messages);
+        let hashes = turn_prefix_hashes(&session);
+        (session.info, hashes)
+    }
+
+    fn ids(clusters: &[Vec<SessionInfo>]) -> Vec<Vec<&str>> {
+        clusters
+            .iter()
+            .map(|c| c.iter().map(|s| s.id.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn identical_sessions_are_grouped() {
+        let clusters = cluster_duplicates(
+            vec![
+                convo("a", &[("fix the bug", "done"), ("thanks", "ok")]),
+                convo("b", &[("fix  the\nbug", "done"), ("thanks", "ok")]),
+                convo("c", &[("write docs", "sure")]),
+            ],
+            None,
+        );
+        assert_eq!(ids(&clusters), vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn prefix_session_joins_longer_one() {
+        let clusters = cluster_duplicates(
+            vec![
+                convo("a", &[("fix the bug", "done")]),
+                convo("b", &[("fix the bug", "done"), ("now add a test", "added")]),
+                convo("c", &[("fix the bug", "cannot")]),
+            ],
+            None,
+        );
+        assert_eq!(ids(&clusters), vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn prefix_turns_groups_by_first_n_and_skips_short_sessions() {
+        let sessions = vec![
+            convo("a", &[("q1", "a1"), ("q2", "a2"), ("q3", "a3")]),
+            convo("b", &[("q1", "a1"), ("q2", "a2"), ("other", "x")]),
+            convo("c", &[("q1", "a1")]),
+            convo("d", &[("q1", "a1"), ("different", "a2")]),
+        ];
+        assert_eq!(ids(&cluster_duplicates(sessions.clone(), Some(2))), vec![vec!["a", "b"]]);
+        // 只看第一轮时四个会话都相同
+        assert_eq!(ids(&cluster_duplicates(sessions.clone(), Some(1))), vec![vec!["a", "b", "c", "d"]]);
+        // 不按前缀轮数时 a、b 全文不同，只有 c 是其他会话的前缀
+        assert_eq!(ids(&cluster_duplicates(sessions, None)), vec![vec!["a", "b", "c", "d"]]);
+    }
+
+    #[test]
+    fn unrelated_sessions_stay_separate() {
+        let clusters = cluster_duplicates(
+            vec![
+                convo("a", &[("fix the bug", "done")]),
+                convo("b", &[("write docs", "sure")]),
+                convo("c", &[("deploy", "deployed")]),
+            ],
+            None,
+        );
+        assert!(clusters.is_empty());
+        assert!(cluster_duplicates(vec![convo("a", &[("q", "a")]), convo("b", &[("q", "b")])], Some(1)).is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+//! 结构化错误类型
+
+use pyo3::exceptions::{
+    PyFileExistsError, PyFileNotFoundError, PyIOError, PyNotImplementedError, PyPermissionError, PyRuntimeError, PyValueError,
+};
+use pyo3::PyErr;
+use std::fmt;
+use std::io;
+
+/// 历史记录操作错误，转换为 Python 异常时按变体区分异常类型
+#[derive(Debug)]
+pub enum HistoryError {
+    /// 会话或文件不存在（FileNotFoundError）
+    NotFound(String),
+    /// 文件系统错误（按 io::ErrorKind 映射为 FileNotFoundError / FileExistsError / PermissionError / IOError）
+    Io(io::Error),
+    /// 内容无法解析（ValueError）
+    Parse(String),
+    /// 回收站清单、归档等数据损坏（RuntimeError）
+    Corrupt(String),
+    /// 当前来源不支持该操作，如只读归档（NotImplementedError）
+    Unsupported(String),
+    /// 缓存数据库读写失败（RuntimeError）
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(msg) | Self::Parse(msg) | Self::Corrupt(msg) | Self::Unsupported(msg) => f.write_str(msg),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Database(e) => write!(f, "缓存数据库错误: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for HistoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<HistoryError> for PyErr {
+    fn from(e: HistoryError) -> Self {
+        let msg = e.to_string();
+        match e {
+            HistoryError::NotFound(_) => PyFileNotFoundError::new_err(msg),
+            HistoryError::Io(io) => match io.kind() {
+                io::ErrorKind::NotFound => PyFileNotFoundError::new_err(msg),
+                io::ErrorKind::AlreadyExists => PyFileExistsError::new_err(msg),
+                io::ErrorKind::PermissionDenied => PyPermissionError::new_err(msg),
+                _ => PyIOError::new_err(msg),
+            },
+            HistoryError::Parse(_) => PyValueError::new_err(msg),
+            HistoryError::Corrupt(_) | HistoryError::Database(_) => PyRuntimeError::new_err(msg),
+            HistoryError::Unsupported(_) => PyNotImplementedError::new_err(msg),
+        }
+    }
+}
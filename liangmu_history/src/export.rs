@@ -0,0 +1,584 @@
+//! 会话导出渲染
+
+use crate::types::*;
+
+/// HTML 导出的内联样式（导出文件不依赖外部资源）
+const HTML_STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", "Microsoft YaHei", sans-serif; max-width: 960px; margin: 0 auto; padding: 24px; background: #f6f7f9; color: #1f2328; }
+header { border-bottom: 1px solid #d0d7de; margin-bottom: 16px; }
+header .meta { color: #656d76; font-size: 13px; }
+.msg { border-radius: 8px; padding: 12px 16px; margin: 12px 0; background: #fff; border: 1px solid #d0d7de; }
+.msg-user { border-left: 4px solid #0969da; }
+.msg-assistant { border-left: 4px solid #1a7f37; }
+.msg-system { border-left: 4px solid #8c959f; background: #f6f8fa; }
+.msg-context { opacity: 0.7; }
+.role { font-weight: 600; font-size: 13px; text-transform: uppercase; color: #656d76; }
+.time { font-weight: normal; margin-left: 8px; }
+.text p { margin: 8px 0; white-space: pre-wrap; word-wrap: break-word; }
+pre { background: #f6f8fa; border: 1px solid #d0d7de; border-radius: 6px; padding: 10px; overflow-x: auto; font-size: 13px; }
+code { font-family: ui-monospace, "Cascadia Code", Consolas, monospace; }
+.tool-name { font-family: ui-monospace, Consolas, monospace; font-size: 13px; color: #8250df; margin-top: 8px; }
+"#;
+
+/// 转义 HTML 特殊字符
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// 工具输入格式化为缩进 JSON，无法解析时原样返回
+pub fn pretty_tool_input(input: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(input)
+        .and_then(|v| serde_json::to_string_pretty(&v))
+        .unwrap_or_else(|_| input.to_string())
+}
+
+/// 渲染单条消息为 Markdown 段落，无可输出内容时返回 None（思考过程不导出）
+/// `include_tools` 为 true 时把工具调用渲染为带工具名的 json 代码块；
+/// `include_timestamps` 为 true 时在标题中加上消息时间
+pub fn markdown_message(msg: &Message, include_tools: bool, include_timestamps: bool) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    for block in &msg.content_blocks {
+        if block.block_type == "tool_use" {
+            if !include_tools {
+                continue;
+            }
+            let name = block.tool_name.as_deref().unwrap_or("tool");
+            let input = block.tool_input.as_deref().map(pretty_tool_input).unwrap_or_default();
+            parts.push(format!("**🔧 {}**\n\n```json\n{}\n```", name, input));
+        } else if block.block_type == "thinking" {
+            continue;
+        } else if let Some(text) = block.text.as_deref().filter(|t| !t.is_empty()) {
+            parts.push(text.to_string());
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+
+    let heading = match msg.timestamp.as_deref().filter(|_| include_timestamps) {
+        Some(ts) => format!("## [{}] {}", ts, msg.role.to_uppercase()),
+        None => format!("## {}", msg.role.to_uppercase()),
+    };
+    Some(format!("{}\n\n{}\n\n---\n\n", heading, parts.join("\n\n")))
+}
+
+/// Markdown 文档头（标题 + 路径）
+pub fn markdown_header(cli_name: &str, session_id: &str, cwd: Option<&str>) -> String {
+    format!("# {} 会话: {}\n路径: {}\n\n---\n\n", cli_name, session_id, cwd.unwrap_or("未知"))
+}
+
+/// 渲染为 Markdown 文档（标题 + 路径 + 各条消息）
+pub fn session_to_markdown(session: &Session, cli_name: &str, include_tools: bool, include_timestamps: bool) -> String {
+    let mut lines = vec![markdown_header(cli_name, &session.info.id, session.info.cwd.as_deref())];
+    lines.extend(
+        session
+            .messages
+            .iter()
+            .filter_map(|m| markdown_message(m, include_tools, include_timestamps)),
+    );
+    lines.join("")
+}
+
+/// 追加一段文本：上一条是同角色且不含工具调用的消息时合并，否则新建消息
+fn push_openai_text(out: &mut Vec<serde_json::Value>, role: &str, text: &str) {
+    if let Some(last) = out.last_mut() {
+        if last["role"] == role && last.get("tool_calls").is_none() {
+            if let Some(content) = last["content"].as_str() {
+                last["content"] = serde_json::Value::String(format!("{}\n\n{}", content, text));
+                return;
+            }
+        }
+    }
+    out.push(serde_json::json!({ "role": role, "content": text }));
+}
+
+/// 渲染为 OpenAI Chat Completions 的 `messages` 数组
+/// 相邻同角色的文本合并为一条，思考过程不导出；`include_tools` 为 true 时工具调用
+/// 并入 assistant 消息的 `tool_calls`，工具结果输出为 `role: "tool"` 消息，否则两者都丢弃
+pub fn session_to_openai_messages(session: &Session, include_tools: bool) -> Vec<serde_json::Value> {
+    use serde_json::{json, Value};
+
+    let mut out: Vec<Value> = Vec::new();
+    // 缺少调用 ID 时按顺序生成，工具结果缺少 ID 时对应到最近一次调用
+    let mut generated_ids = 0;
+    let mut last_call_id = String::new();
+
+    for msg in &session.messages {
+        let role = match msg.role.as_str() {
+            "user" => "user",
+            "system" => "system",
+            _ => "assistant",
+        };
+        for block in &msg.content_blocks {
+            match block.block_type.as_str() {
+                "thinking" => {}
+                "tool_use" => {
+                    if !include_tools {
+                        continue;
+                    }
+                    last_call_id = block.tool_use_id.clone().unwrap_or_else(|| {
+                        generated_ids += 1;
+                        format!("call_{}", generated_ids)
+                    });
+                    let call = json!({
+                        "id": last_call_id,
+                        "type": "function",
+                        "function": {
+                            "name": block.tool_name.as_deref().unwrap_or("tool"),
+                            "arguments": block.tool_input.as_deref().unwrap_or("{}"),
+                        },
+                    });
+                    // 并入上一条 assistant 消息（先说明再调用、或一次发起多个调用）
+                    match out.last_mut() {
+                        Some(last) if last["role"] == "assistant" => match last["tool_calls"].as_array_mut() {
+                            Some(calls) => calls.push(call),
+                            None => last["tool_calls"] = json!([call]),
+                        },
+                        _ => out.push(json!({ "role": "assistant", "content": Value::Null, "tool_calls": [call] })),
+                    }
+                }
+                "tool_result" => {
+                    if !include_tools {
+                        continue;
+                    }
+                    out.push(json!({
+                        "role": "tool",
+                        "tool_call_id": block.tool_use_id.as_deref().unwrap_or(&last_call_id),
+                        "content": block.tool_result.as_deref().unwrap_or(""),
+                    }));
+                }
+                _ => {
+                    if let Some(text) = block.text.as_deref().filter(|t| !t.is_empty()) {
+                        push_openai_text(&mut out, role, text);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// 渲染为 Anthropic Messages API 的 `messages` 数组，保留 text / tool_use / tool_result 块
+/// 相邻同角色的消息合并为一条，使 user 与 assistant 交替出现；工具结果总是放在 user 消息中。
+/// system 消息不导出（API 的 system 是独立参数），思考过程缺少签名无法回传，同样不导出
+pub fn session_to_anthropic_messages(session: &Session) -> Vec<serde_json::Value> {
+    use serde_json::{json, Value};
+
+    let mut out: Vec<Value> = Vec::new();
+    let mut generated_ids = 0;
+    let mut last_tool_use_id = String::new();
+
+    for msg in &session.messages {
+        let msg_role = match msg.role.as_str() {
+            "system" => continue,
+            "user" => "user",
+            _ => "assistant",
+        };
+        for block in &msg.content_blocks {
+            let (role, content) = match block.block_type.as_str() {
+                "thinking" => continue,
+                "tool_use" => {
+                    last_tool_use_id = block.tool_use_id.clone().unwrap_or_else(|| {
+                        generated_ids += 1;
+                        format!("toolu_{}", generated_ids)
+                    });
+                    // input 必须是对象：非对象的 JSON 或无法解析的文本包一层 {"input": ...}
+                    let input = match block.tool_input.as_deref().map(serde_json::from_str::<Value>) {
+                        Some(Ok(v @ Value::Object(_))) => v,
+                        Some(Ok(v)) => json!({ "input": v }),
+                        Some(Err(_)) => json!({ "input": block.tool_input }),
+                        None => json!({}),
+                    };
+                    let block = json!({
+                        "type": "tool_use",
+                        "id": last_tool_use_id,
+                        "name": block.tool_name.as_deref().unwrap_or("tool"),
+                        "input": input,
+                    });
+                    ("assistant", block)
+                }
+                "tool_result" => {
+                    let block = json!({
+                        "type": "tool_result",
+                        "tool_use_id": block.tool_use_id.as_deref().unwrap_or(&last_tool_use_id),
+                        "content": block.tool_result.as_deref().unwrap_or(""),
+                    });
+                    ("user", block)
+                }
+                _ => match block.text.as_deref().filter(|t| !t.is_empty()) {
+                    Some(text) => (msg_role, json!({ "type": "text", "text": text })),
+                    None => continue,
+                },
+            };
+
+            match out.last_mut() {
+                Some(last) if last["role"] == role => {
+                    if let Some(blocks) = last["content"].as_array_mut() {
+                        blocks.push(content);
+                    }
+                }
+                _ => out.push(json!({ "role": role, "content": [content] })),
+            }
+        }
+    }
+    out
+}
+
+/// 批量导出时的条目文件名（不含扩展名）：`<首条消息时间>_<会话 ID>`
+/// 时间格式为 `YYYYMMDD-HHMMSS`（UTC），没有时间戳时只用会话 ID
+pub fn archive_entry_stem(info: &SessionInfo) -> String {
+    let id: String = info
+        .id
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let time = info
+        .first_timestamp_unix
+        .or(info.last_timestamp_unix)
+        .and_then(|t| chrono::DateTime::from_timestamp(t, 0));
+    match time {
+        Some(t) => format!("{}_{}", t.format("%Y%m%d-%H%M%S"), id),
+        None => id,
+    }
+}
+
+/// 渲染消息文本：``` 围起的代码块输出为 `<pre><code class="language-xx">`，其余按段落输出
+fn render_text_html(text: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+
+    fn flush_paragraph(out: &mut String, paragraph: &mut Vec<&str>) {
+        let text = paragraph.join("\n");
+        if !text.trim().is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(text.trim())));
+        }
+        paragraph.clear();
+    }
+
+    for line in text.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut code, fence) {
+            (Some((lang, lines)), Some(_)) => {
+                let class = if lang.is_empty() { "code".to_string() } else { format!("language-{}", escape_html(lang)) };
+                out.push_str(&format!(
+                    "<pre><code class=\"{}\">{}</code></pre>\n",
+                    class,
+                    escape_html(&lines.join("\n"))
+                ));
+                code = None;
+            }
+            (Some((_, lines)), None) => lines.push(line),
+            (None, Some(lang)) => {
+                flush_paragraph(&mut out, &mut paragraph);
+                code = Some((lang.trim().to_string(), Vec::new()));
+            }
+            (None, None) => paragraph.push(line),
+        }
+    }
+
+    // 未闭合的代码块按代码输出
+    if let Some((_, lines)) = code {
+        out.push_str(&format!("<pre><code class=\"code\">{}</code></pre>\n", escape_html(&lines.join("\n"))));
+    }
+    flush_paragraph(&mut out, &mut paragraph);
+    out
+}
+
+/// 渲染单条消息为 HTML 片段，无可输出内容时返回 None（思考过程不导出）
+pub fn render_message_html(msg: &Message) -> Option<String> {
+    let mut body = String::new();
+    for block in &msg.content_blocks {
+        match block.block_type.as_str() {
+            "tool_use" => {
+                body.push_str(&format!(
+                    "<div class=\"tool-name\">🔧 {}</div>\n",
+                    escape_html(block.tool_name.as_deref().unwrap_or("tool"))
+                ));
+                if let Some(input) = &block.tool_input {
+                    body.push_str(&format!("<pre>{}</pre>\n", escape_html(&pretty_tool_input(input))));
+                }
+            }
+            // 思考过程不导出
+            "thinking" => {}
+            _ => {
+                if let Some(text) = block.text.as_deref().filter(|t| !t.trim().is_empty()) {
+                    body.push_str(&render_text_html(text));
+                }
+            }
+        }
+    }
+    if body.is_empty() {
+        return None;
+    }
+
+    let role_class = match msg.role.as_str() {
+        "user" | "assistant" => msg.role.as_str(),
+        _ => "system",
+    };
+    let context_class = if msg.is_context { " msg-context" } else { "" };
+    let time = msg
+        .timestamp
+        .as_deref()
+        .map(|t| format!("<span class=\"time\">{}</span>", escape_html(t)))
+        .unwrap_or_default();
+
+    Some(format!(
+        "<div class=\"msg msg-{}{}\">\n<div class=\"role\">{}{}</div>\n<div class=\"text\">\n{}</div>\n</div>\n",
+        role_class,
+        context_class,
+        escape_html(&msg.role),
+        time,
+        body
+    ))
+}
+
+/// HTML 文档结尾
+pub const HTML_FOOTER: &str = "</main>\n</body>\n</html>\n";
+
+/// HTML 文档开头（到 `<main>` 为止）；`time_range` 为 (首条时间, 末条时间)
+pub fn html_header(cli_name: &str, session_id: &str, cwd: Option<&str>, time_range: Option<(&str, &str)>) -> String {
+    let title = format!("{} 会话: {}", cli_name, session_id);
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", HTML_STYLE));
+    html.push_str(&format!("<header>\n<h1>{}</h1>\n", escape_html(&title)));
+    html.push_str(&format!(
+        "<div class=\"meta\">路径: {}</div>\n",
+        escape_html(cwd.unwrap_or("未知"))
+    ));
+    if let Some((first, last)) = time_range {
+        html.push_str(&format!(
+            "<div class=\"meta\">时间: {} — {}</div>\n",
+            escape_html(first),
+            escape_html(last)
+        ));
+    }
+    html.push_str("</header>\n<main>\n");
+    html
+}
+
+/// 渲染为独立的 HTML 文档
+pub fn session_to_html(session: &Session, cli_name: &str) -> String {
+    let time_range = session
+        .info
+        .first_timestamp
+        .as_deref()
+        .zip(session.info.last_timestamp.as_deref());
+    let mut html = html_header(cli_name, &session.info.id, session.info.cwd.as_deref(), time_range);
+    for msg in &session.messages {
+        if let Some(rendered) = render_message_html(msg) {
+            html.push_str(&rendered);
+        }
+    }
+    html.push_str(HTML_FOOTER);
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{block, msg, session, text};
+    use serde_json::json;
+
+    fn thinking(t: &str) -> ContentBlock {
+        ContentBlock { text: Some(t.to_string()), ..block("thinking") }
+    }
+
+    fn tool_use(id: Option<&str>, name: &str, input: Option<&str>) -> ContentBlock {
+        ContentBlock {
+            tool_name: Some(name.to_string()),
+            tool_input: input.map(String::from),
+            tool_use_id: id.map(String::from),
+            ..block("tool_use")
+        }
+    }
+
+    fn tool_result(id: Option<&str>, output: &str) -> ContentBlock {
+        ContentBlock {
+            tool_result: Some(output.to_string()),
+            tool_use_id: id.map(String::from),
+            ..block("tool_result")
+        }
+    }
+
+    /// 说明后连续两次调用（无 ID），结果缺少 ID，随后是思考和两段回复
+    fn tool_session() -> Session {
+        session("s1", vec![
+            msg("user", vec![text("list files")]),
+            msg(
+                "assistant",
+                vec![
+                    text("Let me look."),
+                    tool_use(None, "Bash", Some(r#"{"command":"ls"}"#)),
+                    tool_use(None, "Read", Some(r#"{"path":"a"}"#)),
+                ],
+            ),
+            msg("user", vec![tool_result(None, "a\nb")]),
+            msg("assistant", vec![thinking("hmm"), text("Done.")]),
+            msg("assistant", vec![text("Anything else?")]),
+        ])
+    }
+
+    #[test]
+    fn openai_messages_merge_tool_calls_and_pair_results() {
+        let out = session_to_openai_messages(&tool_session(), true);
+        assert_eq!(
+            serde_json::Value::Array(out),
+            json!([
+                { "role": "user", "content": "list files" },
+                {
+                    "role": "assistant",
+                    "content": "Let me look.",
+                    "tool_calls": [
+                        { "id": "call_1", "type": "function", "function": { "name": "Bash", "arguments": r#"{"command":"ls"}"# } },
+                        { "id": "call_2", "type": "function", "function": { "name": "Read", "arguments": r#"{"path":"a"}"# } },
+                    ],
+                },
+                { "role": "tool", "tool_call_id": "call_2", "content": "a\nb" },
+                { "role": "assistant", "content": "Done.\n\nAnything else?" },
+            ])
+        );
+    }
+
+    #[test]
+    fn openai_messages_without_tools_merge_text() {
+        let out = session_to_openai_messages(&tool_session(), false);
+        assert_eq!(
+            serde_json::Value::Array(out),
+            json!([
+                { "role": "user", "content": "list files" },
+                { "role": "assistant", "content": "Let me look.\n\nDone.\n\nAnything else?" },
+            ])
+        );
+    }
+
+    #[test]
+    fn openai_messages_keep_existing_call_ids() {
+        let out = session_to_openai_messages(
+            &session("s1", vec![
+                msg("assistant", vec![tool_use(Some("toolu_a"), "Bash", None)]),
+                msg("user", vec![tool_result(Some("toolu_a"), "ok")]),
+            ]),
+            true,
+        );
+        assert_eq!(
+            serde_json::Value::Array(out),
+            json!([
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{ "id": "toolu_a", "type": "function", "function": { "name": "Bash", "arguments": "{}" } }],
+                },
+                { "role": "tool", "tool_call_id": "toolu_a", "content": "ok" },
+            ])
+        );
+    }
+
+    #[test]
+    fn anthropic_messages_alternate_roles_and_wrap_inputs() {
+        let out = session_to_anthropic_messages(&session("s1", vec![
+            msg("system", vec![text("be brief")]),
+            msg("user", vec![text("hi")]),
+            msg(
+                "assistant",
+                vec![
+                    text("checking"),
+                    tool_use(None, "Bash", Some(r#""ls""#)),
+                    tool_use(Some("toolu_x"), "Read", Some("not json")),
+                    tool_use(None, "Grep", None),
+                ],
+            ),
+            msg("user", vec![tool_result(None, "out"), tool_result(Some("toolu_x"), "file")]),
+            msg("user", vec![text("thanks")]),
+            msg("assistant", vec![thinking("hmm"), text("ok")]),
+        ]));
+        assert_eq!(
+            serde_json::Value::Array(out),
+            json!([
+                { "role": "user", "content": [{ "type": "text", "text": "hi" }] },
+                {
+                    "role": "assistant",
+                    "content": [
+                        { "type": "text", "text": "checking" },
+                        { "type": "tool_use", "id": "toolu_1", "name": "Bash", "input": { "input": "ls" } },
+                        { "type": "tool_use", "id": "toolu_x", "name": "Read", "input": { "input": "not json" } },
+                        { "type": "tool_use", "id": "toolu_2", "name": "Grep", "input": {} },
+                    ],
+                },
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "tool_result", "tool_use_id": "toolu_2", "content": "out" },
+                        { "type": "tool_result", "tool_use_id": "toolu_x", "content": "file" },
+                        { "type": "text", "text": "thanks" },
+                    ],
+                },
+                { "role": "assistant", "content": [{ "type": "text", "text": "ok" }] },
+            ])
+        );
+    }
+
+    #[test]
+    fn anthropic_tool_input_objects_are_kept() {
+        let out = session_to_anthropic_messages(&session("s1", vec![msg(
+            "assistant",
+            vec![
+                tool_use(Some("t1"), "Bash", Some(r#"{"command":"ls"}"#)),
+                tool_use(Some("t2"), "Sum", Some("[1,2]")),
+            ],
+        )]));
+        assert_eq!(out[0]["content"][0]["input"], json!({ "command": "ls" }));
+        assert_eq!(out[0]["content"][1]["input"], json!({ "input": [1, 2] }));
+    }
+
+    #[test]
+    fn html_message_escapes_text_and_code() {
+        let mut message = msg("user", vec![text("a < b & \"c\"\n```rust\nlet x = 1 < 2;\n```\nafter")]);
+        message.timestamp = Some("t<1>".to_string());
+        assert_eq!(
+            render_message_html(&message).unwrap(),
+            concat!(
+                "<div class=\"msg msg-user\">\n",
+                "<div class=\"role\">user<span class=\"time\">t&lt;1&gt;</span></div>\n",
+                "<div class=\"text\">\n",
+                "<p>a &lt; b &amp; &quot;c&quot;</p>\n",
+                "<pre><code class=\"language-rust\">let x = 1 &lt; 2;</code></pre>\n",
+                "<p>after</p>\n",
+                "</div>\n</div>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn html_unclosed_fence_is_rendered_as_code() {
+        assert_eq!(
+            render_text_html("intro\n```py\nprint('<hi>')"),
+            "<p>intro</p>\n<pre><code class=\"code\">print(&#39;&lt;hi&gt;&#39;)</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn html_fence_language_and_tool_name_are_escaped() {
+        assert_eq!(
+            render_text_html("```\"><script>\nx\n```"),
+            "<pre><code class=\"language-&quot;&gt;&lt;script&gt;\">x</code></pre>\n"
+        );
+        let rendered = render_message_html(&msg("assistant", vec![tool_use(None, "<b>", None), thinking("hidden")])).unwrap();
+        assert!(rendered.contains("<div class=\"tool-name\">🔧 &lt;b&gt;</div>\n"));
+        assert!(!rendered.contains("hidden"));
+        assert!(render_message_html(&msg("assistant", vec![thinking("only")])).is_none());
+    }
+}
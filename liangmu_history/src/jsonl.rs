@@ -0,0 +1,300 @@
+//! JSONL 行读取工具
+
+use crate::types::{Message, SessionTail};
+use encoding_rs::Encoding;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+const JSONL_SUFFIX: &str = ".jsonl";
+const GZIP_SUFFIX: &str = ".jsonl.gz";
+
+fn is_gzip(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(GZIP_SUFFIX))
+}
+
+/// 是否为会话文件：`.jsonl` 或 gzip 压缩的 `.jsonl.gz`
+pub fn is_session_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(JSONL_SUFFIX) || n.ends_with(GZIP_SUFFIX))
+}
+
+/// 会话文件名去掉 `.jsonl` / `.jsonl.gz` 后缀
+pub fn session_stem(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    Some(
+        name.strip_suffix(GZIP_SUFFIX)
+            .or_else(|| name.strip_suffix(JSONL_SUFFIX))
+            .unwrap_or(name),
+    )
+}
+
+/// 打开会话文件，`.jsonl.gz` 透明解压；同时返回磁盘上的文件大小
+pub fn open_session(path: &Path) -> io::Result<(Box<dyn BufRead + Send>, u64)> {
+    let file = File::open(path)?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let reader: Box<dyn BufRead + Send> = if is_gzip(path) {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    Ok((reader, size))
+}
+
+/// 带字节偏移的 JSONL 行迭代器
+/// 产出 (行起始字节偏移, 行内容)，跳过空行和无法解码的行
+///
+/// 默认按 UTF-8 解码；某行不是合法 UTF-8 时（如 GBK 写入的旧日志），
+/// 用 chardetng 探测编码并转码，探测结果在同一文件的后续行中复用。
+pub struct JsonlLines<R> {
+    reader: R,
+    offset: u64,
+    buf: Vec<u8>,
+    fallback: Option<&'static Encoding>,
+}
+
+impl<R: BufRead> JsonlLines<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            buf: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    fn decode(&mut self, line_offset: u64) -> Option<String> {
+        let mut bytes: &[u8] = &self.buf;
+        if line_offset == 0 {
+            bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+        }
+
+        if let Ok(s) = std::str::from_utf8(bytes) {
+            return Some(s.to_string());
+        }
+
+        let encoding = *self.fallback.get_or_insert_with(|| {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, false)
+        });
+        let (decoded, _, had_errors) = encoding.decode(bytes);
+        if had_errors {
+            return None;
+        }
+        Some(decoded.into_owned())
+    }
+}
+
+impl<R: BufRead> Iterator for JsonlLines<R> {
+    type Item = (u64, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            let n = self.reader.read_until(b'\n', &mut self.buf).ok()?;
+            if n == 0 {
+                return None;
+            }
+            let line_offset = self.offset;
+            self.offset += n as u64;
+
+            let line = match self.decode(line_offset) {
+                Some(l) => l,
+                None => continue,
+            };
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            return Some((line_offset, trimmed.to_string()));
+        }
+    }
+}
+
+/// 解析时被跳过的行：(行号（从 1 开始）, 原因)
+pub type MalformedLines = Vec<(usize, String)>;
+
+/// 找出会话文件中被解析时跳过的行
+/// 包括无法识别编码的行和不是合法 JSON 的行（如写入中断留下的半行）；空行不算
+pub fn scan_malformed_lines(path: &Path) -> io::Result<MalformedLines> {
+    let (reader, _) = open_session(path)?;
+    let mut lines = JsonlLines::new(reader);
+    let mut malformed = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        lines.buf.clear();
+        let n = lines.reader.read_until(b'\n', &mut lines.buf)?;
+        if n == 0 {
+            break;
+        }
+        line_number += 1;
+        let line_offset = lines.offset;
+        lines.offset += n as u64;
+
+        match lines.decode(line_offset) {
+            None => malformed.push((line_number, "无法识别的字符编码".to_string())),
+            Some(line) => {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    continue;
+                }
+                if let Err(e) = serde_json::from_str::<Value>(trimmed) {
+                    malformed.push((line_number, e.to_string()));
+                }
+            }
+        }
+    }
+    Ok(malformed)
+}
+
+/// 从 `byte_offset` 处继续读取文件，解析新增的完整行
+/// 末尾尚未写完（没有换行符）的行留到下次读取；偏移超出文件长度或不在行首时，
+/// 说明文件被截断或重写，返回 `needs_reload` 要求调用方重新加载整个会话
+pub fn read_messages_since(
+    path: &Path,
+    byte_offset: u64,
+    parse: impl Fn(&Value) -> Option<Message>,
+) -> Option<SessionTail> {
+    // 压缩文件无法按偏移定位，也不会再被追加写入
+    if is_gzip(path) {
+        return None;
+    }
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let at_line_start = byte_offset == 0 || {
+        let mut prev = [0u8; 1];
+        byte_offset <= len
+            && file.seek(SeekFrom::Start(byte_offset - 1)).is_ok()
+            && file.read_exact(&mut prev).is_ok()
+            && prev[0] == b'\n'
+    };
+    if byte_offset > len || !at_line_start {
+        return Some(SessionTail {
+            messages: Vec::new(),
+            end_offset: 0,
+            needs_reload: true,
+        });
+    }
+
+    file.seek(SeekFrom::Start(byte_offset)).ok()?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok()?;
+    let complete = buf.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+
+    let messages = JsonlLines::new(&buf[..complete])
+        .filter_map(|(offset, line)| {
+            let data: Value = serde_json::from_str(&line).ok()?;
+            let mut msg = parse(&data)?;
+            msg.byte_offset = Some(byte_offset + offset);
+            Some(msg)
+        })
+        .collect();
+
+    Some(SessionTail {
+        messages,
+        end_offset: byte_offset + complete as u64,
+        needs_reload: false,
+    })
+}
+
+/// 逐行解析会话文件并逐条产出消息，已产出的消息不再保留；`emit` 返回 false 时停止
+pub fn stream_messages(
+    path: &Path,
+    parse: impl Fn(&Value) -> Option<Message>,
+    emit: &mut dyn FnMut(Message) -> bool,
+) -> io::Result<()> {
+    let (reader, _) = open_session(path)?;
+    for (offset, line) in JsonlLines::new(reader) {
+        let data: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(mut msg) = parse(&data) {
+            msg.byte_offset = Some(offset);
+            if !emit(msg) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 查找第一条包含 `"cwd"` 且 `extract` 能从中取出 cwd 的记录
+/// 未压缩文件先读取文件头部，直接搜索字节模式，只解析命中的完整行；
+/// 头部未找到时（如压缩文件、cwd 出现得较晚、非 UTF-8 编码的旧日志）回退到逐行读取
+pub fn find_cwd(path: &Path, extract: impl Fn(&Value) -> Option<String>) -> Option<String> {
+    if !is_gzip(path) {
+        if let Some(cwd) = find_cwd_in_head(path, &extract) {
+            return Some(cwd);
+        }
+    }
+
+    let (reader, _) = open_session(path).ok()?;
+    JsonlLines::new(reader)
+        .filter(|(_, line)| line.contains(CWD_PATTERN))
+        .find_map(|(_, line)| extract(&serde_json::from_str(&line).ok()?))
+}
+
+const CWD_PATTERN: &str = "\"cwd\"";
+
+/// find_cwd 快速路径读取的文件头部字节数
+const CWD_HEAD_BYTES: u64 = 64 * 1024;
+
+fn find_cwd_in_head(path: &Path, extract: &impl Fn(&Value) -> Option<String>) -> Option<String> {
+    let mut bytes = Vec::new();
+    File::open(path).ok()?.take(CWD_HEAD_BYTES).read_to_end(&mut bytes).ok()?;
+
+    let mut searched_to = 0;
+    for hit in memchr::memmem::find_iter(&bytes, CWD_PATTERN.as_bytes()) {
+        // 同一行可能有多处命中，只解析一次
+        if hit < searched_to {
+            continue;
+        }
+        let start = memchr::memrchr(b'\n', &bytes[..hit]).map(|i| i + 1).unwrap_or(0);
+        // 行被头部截断时交给逐行读取
+        let end = hit + memchr::memchr(b'\n', &bytes[hit..])?;
+        searched_to = end;
+
+        let line = bytes[start..end].strip_prefix(UTF8_BOM).unwrap_or(&bytes[start..end]);
+        if let Some(cwd) = serde_json::from_slice::<Value>(line).ok().and_then(|data| extract(&data)) {
+            return Some(cwd);
+        }
+    }
+    None
+}
+
+/// 读取文件末尾最后一条带 timestamp 的记录的时间戳
+/// 只读取文件尾部，避免为取最后活动时间解析整个会话
+pub fn read_last_timestamp(path: &Path) -> Option<String> {
+    const TAIL_BYTES: u64 = 64 * 1024;
+
+    if is_gzip(path) {
+        let (reader, _) = open_session(path).ok()?;
+        return JsonlLines::new(reader)
+            .filter_map(|(_, line)| serde_json::from_str::<Value>(&line).ok())
+            .filter_map(|data| data.get("timestamp").and_then(|v| v.as_str()).map(String::from))
+            .last();
+    }
+
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES))).ok()?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok()?;
+
+    String::from_utf8_lossy(&buf)
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find_map(|data| data.get("timestamp").and_then(|v| v.as_str()).map(String::from))
+}
@@ -3,34 +3,110 @@
 //! 为 AI CLI 工具（Claude、Codex 等）提供高性能的历史记录解析功能。
 //! 通过 PyO3 暴露给 Python 使用。
 
+// PyO3 0.22 为返回 PyResult 的 #[pyfunction] / #[pymethods] 生成的包装函数会把 PyErr 再 into 成 PyErr，
+// 触发 useless_conversion 误报。包装函数是宏生成的同级条目，不继承函数上的 #[allow]，只能在这里统一放行
+#![allow(clippy::useless_conversion)]
+
 mod cache;
+mod cancel;
+mod dedup;
+mod error;
+mod export;
+mod jsonl;
+mod matcher;
+mod paths;
 mod provider;
 mod providers;
+mod snippet;
+mod stream;
+#[cfg(test)]
+mod test_util;
+mod timestamp;
+mod tokens;
+mod trash;
 mod types;
+mod watch;
 
+use parking_lot::RwLock;
 use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
-use std::sync::OnceLock;
-
-pub use provider::{CliHistoryProvider, ProviderRegistry};
-pub use providers::{ClaudeProvider, CodexProvider};
+use std::sync::Arc;
+
+pub use error::HistoryError;
+pub use provider::{CliHistoryProvider, ProviderRegistry, DEFAULT_MIN_TURNS};
+use cancel::CancelToken;
+use matcher::KeywordMatcher;
+use provider::{sort_projects, sort_sessions, ProjectSort, SearchScope, SessionSort};
+pub use providers::{AiderProvider, ArchiveProvider, ClaudeProvider, CodexProvider, GenericJsonlProvider};
 pub use types::*;
 
-// 全局 Provider 实例（懒加载）
-static CLAUDE_PROVIDER: OnceLock<Option<ClaudeProvider>> = OnceLock::new();
-static CODEX_PROVIDER: OnceLock<Option<CodexProvider>> = OnceLock::new();
+// 全局 Provider 注册表（懒加载；缺失的内置 Provider 在访问时自动重新探测，也可通过 reinitialize_providers 强制重建）
+lazy_static::lazy_static! {
+    /// Claude、Codex 启动时自动探测；Aider、归档与自定义类型在运行时注册
+    static ref PROVIDERS: RwLock<ProviderRegistry> = RwLock::new(default_registry());
+}
+
+lazy_static::lazy_static! {
+    /// 通过 set_provider_base_dir 指定的内置 Provider 目录，优先于默认位置
+    static ref BASE_DIR_OVERRIDES: RwLock<HashMap<String, std::path::PathBuf>> = RwLock::new(HashMap::new());
+}
+
+/// 注册能在默认位置找到的内置 Provider
+fn default_registry() -> ProviderRegistry {
+    let mut registry = ProviderRegistry::new();
+    detect_builtin_providers(&mut registry);
+    registry
+}
+
+fn detect_builtin_providers(registry: &mut ProviderRegistry) {
+    let overrides = BASE_DIR_OVERRIDES.read();
+    let claude = match overrides.get("claude") {
+        Some(dir) => Some(ClaudeProvider::new(dir.clone())),
+        None => ClaudeProvider::default(),
+    };
+    let codex = match overrides.get("codex") {
+        Some(dir) => Some(CodexProvider::new(dir.clone())),
+        None => CodexProvider::default(),
+    };
+    match claude {
+        Some(p) => registry.register(Arc::new(p)),
+        None => registry.unregister("claude"),
+    }
+    match codex {
+        Some(p) => registry.register(Arc::new(p)),
+        None => registry.unregister("codex"),
+    }
+}
 
-fn get_claude_provider() -> Option<&'static ClaudeProvider> {
-    CLAUDE_PROVIDER
-        .get_or_init(|| ClaudeProvider::default())
-        .as_ref()
+/// `cli_types` 中的内置 Provider 尚未注册时重新探测一次（CLI 在进程运行期间安装后自动识别）
+/// 取得写锁后再检查，避免多个线程同时探测
+fn detect_missing_builtin_providers(cli_types: &[&str]) {
+    let is_missing = |registry: &ProviderRegistry| cli_types.iter().any(|t| registry.get(t).is_none());
+    if !is_missing(&PROVIDERS.read()) {
+        return;
+    }
+    let mut registry = PROVIDERS.write();
+    if is_missing(&registry) {
+        detect_builtin_providers(&mut registry);
+    }
 }
 
-fn get_codex_provider() -> Option<&'static CodexProvider> {
-    CODEX_PROVIDER
-        .get_or_init(|| CodexProvider::default())
-        .as_ref()
+/// 按 CLI 类型获取 Provider
+fn get_provider(cli_type: &str) -> PyResult<Arc<dyn CliHistoryProvider>> {
+    if matches!(cli_type, "claude" | "codex") {
+        detect_missing_builtin_providers(&[cli_type]);
+    }
+    PROVIDERS.read().get(cli_type).ok_or_else(|| match cli_type {
+        "claude" => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"),
+        "codex" => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"),
+        "archive" => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("未打开归档文件"),
+        "aider" => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("未配置 Aider 根目录"),
+        _ => PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("不支持的 CLI 类型: {}", cli_type)),
+    })
 }
 
 // ==================== Python 绑定函数 ====================
@@ -38,95 +114,375 @@ fn get_codex_provider() -> Option<&'static CodexProvider> {
 /// 列出支持的 CLI 类型
 #[pyfunction]
 fn list_cli_types() -> Vec<&'static str> {
-    let mut types = Vec::new();
-    if get_claude_provider().is_some() {
-        types.push("claude");
+    detect_missing_builtin_providers(&["claude", "codex"]);
+    PROVIDERS.read().list_types()
+}
+
+/// 重新探测 Provider（CLI 在程序运行期间安装后无需重启即可识别）
+#[pyfunction]
+fn reinitialize_providers() -> Vec<&'static str> {
+    detect_builtin_providers(&mut PROVIDERS.write());
+    list_cli_types()
+}
+
+/// 指定 Claude / Codex 的数据目录（如挂载的备份），传 None 恢复默认位置
+/// 设置后立即重新初始化对应 Provider，之后的调用都使用新目录
+#[pyfunction]
+#[pyo3(signature = (cli_type, path=None))]
+fn set_provider_base_dir(cli_type: &str, path: Option<String>) -> PyResult<()> {
+    if cli_type != "claude" && cli_type != "codex" {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("不支持的 CLI 类型: {}", cli_type),
+        ));
+    }
+    {
+        let mut overrides = BASE_DIR_OVERRIDES.write();
+        match path {
+            Some(p) => {
+                let dir = std::path::PathBuf::from(p);
+                if !dir.is_dir() {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        format!("目录不存在: {}", dir.display()),
+                    ));
+                }
+                overrides.insert(cli_type.to_string(), dir);
+            }
+            None => {
+                overrides.remove(cli_type);
+            }
+        }
     }
-    if get_codex_provider().is_some() {
-        types.push("codex");
+    detect_builtin_providers(&mut PROVIDERS.write());
+    Ok(())
+}
+
+/// 设置 Aider 历史的扫描根目录（仓库目录或包含多个仓库的目录），传空列表禁用
+#[pyfunction]
+fn set_aider_roots(roots: Vec<String>) -> PyResult<()> {
+    let mut registry = PROVIDERS.write();
+    if roots.is_empty() {
+        registry.unregister("aider");
+    } else {
+        let roots = roots.into_iter().map(std::path::PathBuf::from).collect();
+        registry.register(Arc::new(AiderProvider::new(roots)));
     }
-    types
+    Ok(())
 }
 
-/// 列出项目
+/// 打开 zip 归档，之后可用 cli_type="archive" 只读浏览和搜索其中的会话
+/// 传 None 关闭当前归档
 #[pyfunction]
-#[pyo3(signature = (cli_type, limit=50))]
-fn list_projects(cli_type: &str, limit: usize) -> PyResult<Vec<Project>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.list_projects(limit))
+#[pyo3(signature = (zip_path=None))]
+fn open_archive(zip_path: Option<String>) -> PyResult<()> {
+    let provider = zip_path
+        .map(|p| ArchiveProvider::open(std::path::PathBuf::from(p)))
+        .transpose()?;
+    let mut registry = PROVIDERS.write();
+    match provider {
+        Some(p) => registry.register(Arc::new(p)),
+        None => registry.unregister("archive"),
+    }
+    Ok(())
+}
+
+/// 注册自定义 JSONL 日志目录，之后可用 `cli_type` 浏览和搜索其中的会话
+/// `field_map` 指定记录中各字段的键名（支持 `a.b` 嵌套路径）：
+/// `role`、`text` 必填，`timestamp`、`cwd` 可选；同名类型重复注册时替换
+/// `cli_type` 会用作缓存数据库文件名，只允许字母、数字、`_` 和 `-`
+#[pyfunction]
+fn register_custom_provider(cli_type: &str, base_dir: &str, field_map: HashMap<String, String>) -> PyResult<()> {
+    let valid_name = !cli_type.is_empty()
+        && cli_type.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("CLI 类型名只能包含字母、数字、_ 和 -: {:?}", cli_type),
+        ));
+    }
+    if matches!(cli_type, "claude" | "codex" | "archive" | "aider") {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("不能覆盖内置 CLI 类型: {}", cli_type),
+        ));
+    }
+    let base_dir = std::path::PathBuf::from(base_dir);
+    if !base_dir.is_dir() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("目录不存在: {}", base_dir.display()),
+        ));
+    }
+    let fields = providers::FieldMap::from_map(&field_map)
+        .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+
+    // Provider 的 cli_type 为 'static：自定义类型首次注册时泄漏一次名称，重复注册时沿用已有名称
+    let mut registry = PROVIDERS.write();
+    let name: &'static str = match registry.get(cli_type) {
+        Some(existing) => existing.cli_type(),
+        None => Box::leak(cli_type.to_string().into_boxed_str()),
+    };
+    registry.register(Arc::new(GenericJsonlProvider::new(name, base_dir, fields)));
+    Ok(())
+}
+
+/// 预热：立即初始化指定的 Provider 并打开缓存数据库
+/// `warm_cache` 为 true 时同时扫描所有项目写入缓存；返回初始化成功的 CLI 类型
+#[pyfunction]
+#[pyo3(signature = (cli_types, warm_cache=false))]
+fn init(cli_types: Vec<String>, warm_cache: bool) -> Vec<String> {
+    let mut initialized = Vec::new();
+    for cli_type in cli_types {
+        let provider = match get_provider(&cli_type) {
+            Ok(p) if p.supports_cache() => p,
+            _ => continue,
+        };
+        if cache::get_db(&cli_type).is_err() {
+            continue;
+        }
+
+        if warm_cache {
+            for project in provider.list_projects(0) {
+                let sessions = provider.load_project(&project.id, None, DEFAULT_MIN_TURNS);
+                cache_sessions(provider.as_ref(), &project.id, &sessions);
+            }
         }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.list_projects(limit))
+        initialized.push(cli_type);
+    }
+    initialized
+}
+
+/// 列出项目
+/// `active_within_days` 不为空时，只返回最近 N 天内有活动的项目
+/// `sort_by` 可选 `recent`（默认，按修改时间）、`name`（按 cwd）、`session_count`，`ascending` 控制升降序
+/// `min_messages` 不为空时只统计消息数不少于该值的会话（不看轮次），没有这类会话的项目不返回
+#[pyfunction]
+#[pyo3(signature = (cli_type, limit=50, active_within_days=None, sort_by="recent", ascending=false, min_messages=None))]
+fn list_projects(
+    cli_type: &str,
+    limit: usize,
+    active_within_days: Option<i64>,
+    sort_by: &str,
+    ascending: bool,
+    min_messages: Option<usize>,
+) -> PyResult<Vec<Project>> {
+    let sort = ProjectSort::parse(sort_by).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+    // 按活动时间、消息数过滤或非默认排序时先取全部项目，处理后再截断
+    let full_scan = active_within_days.is_some() || min_messages.is_some() || sort != ProjectSort::Recent || ascending;
+    let scan_limit = if full_scan { 0 } else { limit };
+    let provider = get_provider(cli_type)?;
+    let mut projects = provider.list_projects(scan_limit);
+
+    if let Some(days) = active_within_days {
+        let cutoff = chrono::Utc::now().timestamp() - days * 24 * 3600;
+        projects.retain(|p| {
+            // 无法解析最后活动时间时，回退到目录修改时间
+            let last = p
+                .last_activity
+                .as_deref()
+                .and_then(timestamp::parse_timestamp)
+                .unwrap_or(p.last_modified as i64);
+            last >= cutoff
+        });
+    }
+
+    if let Some(min_messages) = min_messages {
+        // 逐个项目扫描会话，会话数改为达标会话的数量
+        projects = projects
+            .into_par_iter()
+            .filter_map(|mut p| {
+                p.session_count = provider
+                    .all_session_infos(&p.id)
+                    .iter()
+                    .filter(|s| s.message_count >= min_messages)
+                    .count();
+                (p.session_count > 0).then_some(p)
+            })
+            .collect();
+    }
+
+    if full_scan {
+        sort_projects(&mut projects, sort, ascending);
+        if limit > 0 {
+            projects.truncate(limit);
         }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
     }
+
+    Ok(projects)
+}
+
+/// 分页列出项目，返回当前页与项目总数
+/// `sort_by`、`ascending` 含义同 list_projects
+#[pyfunction]
+#[pyo3(signature = (cli_type, offset=0, count=50, sort_by="recent", ascending=false))]
+fn list_projects_paged(cli_type: &str, offset: usize, count: usize, sort_by: &str, ascending: bool) -> PyResult<ProjectPage> {
+    let sort = ProjectSort::parse(sort_by).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+    let provider = get_provider(cli_type)?;
+    let (projects, total_projects) = provider.list_projects_paged(offset, count, sort, ascending);
+    Ok(ProjectPage {
+        projects,
+        offset,
+        total_projects,
+    })
 }
 
 /// 根据工作目录查找项目
 #[pyfunction]
 fn find_project_by_cwd(cli_type: &str, cwd: &str) -> PyResult<Option<Project>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.find_project_by_cwd(cwd))
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.find_project_by_cwd(cwd))
-        }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
+    let provider = get_provider(cli_type)?;
+    Ok(provider.find_project_by_cwd(cwd))
+}
+
+/// 模糊查找项目：cwd 包含 `substring`（不区分大小写，`\\` 与 `/` 视为相同）的项目，按最近修改排序
+#[pyfunction]
+#[pyo3(signature = (cli_type, substring, limit=20))]
+fn find_projects_matching(cli_type: &str, substring: &str, limit: usize) -> PyResult<Vec<Project>> {
+    let fold = |s: &str| s.replace('\\', "/").to_lowercase();
+    let needle = fold(substring);
+    let provider = get_provider(cli_type)?;
+    let mut projects: Vec<Project> = provider
+        .list_projects(0)
+        .into_iter()
+        .filter(|p| p.cwd.as_deref().is_some_and(|cwd| fold(cwd).contains(&needle)))
+        .collect();
+    sort_projects(&mut projects, ProjectSort::Recent, false);
+    if limit > 0 {
+        projects.truncate(limit);
     }
+    Ok(projects)
 }
 
 /// 加载项目的会话列表
+/// `name_glob` 按文件名过滤（如 `"*2025-01-*"`），在解析前生效
+/// `min_user_turns` 为最少真实用户轮次数，默认 1（保留单轮会话）
+/// `model` 只保留模型名包含该字符串的会话（不区分大小写）
+/// `sort` 可选 `recent`（默认，最后时间倒序）、`oldest`（最后时间正序）、`start_asc`（开始时间正序）
+/// `min_messages` 为最少消息数，与轮次过滤同时生效，默认不限
 #[pyfunction]
-fn load_project(cli_type: &str, project_id: &str) -> PyResult<Vec<SessionInfo>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.load_project(project_id))
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.load_project(project_id))
-        }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
+#[pyo3(signature = (cli_type, project_id, name_glob=None, min_user_turns=None, model=None, sort="recent", min_messages=None))]
+#[allow(clippy::too_many_arguments)]
+fn load_project(
+    cli_type: &str,
+    project_id: &str,
+    name_glob: Option<String>,
+    min_user_turns: Option<usize>,
+    model: Option<String>,
+    sort: &str,
+    min_messages: Option<usize>,
+) -> PyResult<Vec<SessionInfo>> {
+    let sort = parse_session_sort(sort)?;
+    let min_turns = min_user_turns.unwrap_or(DEFAULT_MIN_TURNS);
+    let pattern = name_glob
+        .map(|g| glob::Pattern::new(&g))
+        .transpose()
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("无效的 glob 模式: {}", e)))?;
+
+    let provider = get_provider(cli_type)?;
+    let mut sessions = provider.load_project(project_id, pattern.as_ref(), min_turns);
+    if let Some(model) = model {
+        sessions.retain(|s| s.model_matches(&model));
+    }
+    if let Some(min_messages) = min_messages {
+        sessions.retain(|s| s.message_count >= min_messages);
+    }
+    if sort != SessionSort::Recent {
+        sort_sessions(&mut sessions, sort);
     }
+    Ok(sessions)
+}
+
+fn parse_session_sort(value: &str) -> PyResult<SessionSort> {
+    SessionSort::parse(value).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)
+}
+
+/// 加载项目的所有会话，不过滤单轮或 0 轮会话，轮次数如实返回（用于统计分析）
+#[pyfunction]
+fn all_session_infos(cli_type: &str, project_id: &str) -> PyResult<Vec<SessionInfo>> {
+    let provider = get_provider(cli_type)?;
+    Ok(provider.all_session_infos(project_id))
 }
 
 /// 加载完整会话
+/// `separate_context` 为 true 时，注入的上下文消息移到 `context_messages`
+/// `include_thinking` 为 false 时去掉思考过程（block_type 为 `thinking` 的内容块）
+/// `include_subagents` 为 true 时按时间插入子任务（Claude 的 agent-*.jsonl）消息，
+/// 这些消息的 `subagent` 为子任务文件名，便于界面缩进显示
 #[pyfunction]
-fn load_session(cli_type: &str, file_path: &str) -> PyResult<Option<Session>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.load_session(file_path))
+#[pyo3(signature = (cli_type, file_path, separate_context=false, include_thinking=true, include_subagents=false))]
+fn load_session(
+    cli_type: &str,
+    file_path: &str,
+    separate_context: bool,
+    include_thinking: bool,
+    include_subagents: bool,
+) -> PyResult<Option<Session>> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path);
+
+    Ok(session.map(|mut s| {
+        if include_subagents {
+            s.merge_subagent_messages(provider.load_subagent_messages(file_path));
         }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.load_session(file_path))
+        if !include_thinking {
+            s.strip_thinking();
+        }
+        if separate_context {
+            s.separate_context();
+        }
+        s
+    }))
+}
+
+/// 加载会话并报告被跳过的行，返回 (会话, [(行号, 原因)])
+/// 解析本身与 load_session 相同（坏行仍会跳过），用于排查消息数少于预期的会话；
+/// 非本地 JSONL 文件（如归档条目、Aider 历史）不做逐行检查，报告为空
+#[pyfunction]
+fn load_session_strict(cli_type: &str, file_path: &str) -> PyResult<(Option<Session>, jsonl::MalformedLines)> {
+    let provider = get_provider(cli_type)?;
+    let path = Path::new(file_path);
+    let warnings = if jsonl::is_session_file(path) && path.is_file() {
+        jsonl::scan_malformed_lines(path).map_err(HistoryError::from)?
+    } else {
+        Vec::new()
+    };
+    Ok((load_session_cached(provider.as_ref(), file_path), warnings))
+}
+
+fn memory_cache_key(cli_type: &str, file_path: &str) -> String {
+    format!("{}:{}", cli_type, file_path)
+}
+
+/// 加载会话，优先使用内存 LRU 缓存（文件修改后自动失效）
+/// 非本地文件的会话（如归档条目）取不到修改时间，不做缓存
+fn load_session_cached<P: CliHistoryProvider + ?Sized>(provider: &P, file_path: &str) -> Option<Session> {
+    let file_version = match cache::get_file_version(file_path) {
+        Some(v) => v,
+        None => return provider.load_session(file_path),
+    };
+
+    let key = memory_cache_key(provider.cli_type(), file_path);
+    if let Some(detail) = cache::get_session_from_memory(&key, file_version) {
+        let info = serde_json::from_str(&detail.info_json).ok();
+        let messages = serde_json::from_str(&detail.messages_json).ok();
+        if let (Some(info), Some(messages)) = (info, messages) {
+            return Some(Session {
+                info,
+                messages,
+                context_messages: Vec::new(),
+            });
         }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
     }
+
+    let session = provider.load_session(file_path)?;
+    if let (Ok(info_json), Ok(messages_json), Ok(tool_stats_json)) = (
+        serde_json::to_string(&session.info),
+        serde_json::to_string(&session.messages),
+        serde_json::to_string(&session.tool_usage()),
+    ) {
+        cache::set_session_to_memory(key, cache::CachedSessionDetail {
+            info_json,
+            messages_json,
+            tool_stats_json,
+            file_version,
+        });
+    }
+    Some(session)
 }
 
 /// 分页加载会话
@@ -138,248 +494,384 @@ fn load_session_paginated(
     first_turns: usize,
     last_turns: usize,
 ) -> PyResult<Option<PaginatedMessages>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.load_session_paginated(file_path, first_turns, last_turns))
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.load_session_paginated(file_path, first_turns, last_turns))
-        }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    }
+    let provider = get_provider(cli_type)?;
+    Ok(provider.load_session_paginated(file_path, first_turns, last_turns))
 }
 
-/// 搜索会话
+/// 按消息偏移分页加载会话，返回从 `offset` 开始的 `count` 条消息及消息总数
+/// 与 load_session_paginated（首尾若干轮）互补，用于滚动到长会话中间位置
 #[pyfunction]
-#[pyo3(signature = (cli_type, keyword, limit=1000))]
-fn search(cli_type: &str, keyword: &str, limit: usize) -> PyResult<Vec<SessionInfo>> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            Ok(provider.search(keyword, limit))
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            Ok(provider.search(keyword, limit))
-        }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    }
+fn load_session_range(cli_type: &str, file_path: &str, offset: usize, count: usize) -> PyResult<Option<MessageRange>> {
+    let provider = get_provider(cli_type)?;
+    Ok(load_session_cached(provider.as_ref(), file_path).map(|s| s.message_range(offset, count)))
 }
 
-/// 删除会话（移动到回收站）
+/// 加载会话中的单个轮次（从 0 开始，分组规则同 load_session_paginated）
+/// 返回 (该轮消息, 总轮次数)；索引越界时消息为 None，会话不存在时总轮次数为 0
 #[pyfunction]
-fn delete_session(cli_type: &str, file_path: &str) -> PyResult<()> {
-    match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.delete_session(file_path)
-                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e))
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.delete_session(file_path)
-                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e))
-        }
-        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    }
+fn load_turn(cli_type: &str, file_path: &str, turn_index: usize) -> PyResult<(Option<Vec<Message>>, usize)> {
+    let provider = get_provider(cli_type)?;
+    let mut turns = match load_session_cached(provider.as_ref(), file_path) {
+        Some(s) => s.turns(),
+        None => return Ok((None, 0)),
+    };
+    let total_turns = turns.len();
+    let turn = (turn_index < total_turns).then(|| turns.swap_remove(turn_index));
+    Ok((turn, total_turns))
 }
 
-/// 获取回收站项目列表
+/// 从字节偏移处增量读取会话，只返回新增消息及新的结束偏移
+/// 首次调用传 0；`needs_reload` 为 true 时文件已被截断或重写，应重新调用 load_session
 #[pyfunction]
-fn get_trash_items(cli_type: &str) -> PyResult<Vec<TrashItem>> {
-    let trash_dir = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.trash_dir()
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.trash_dir()
-        }
-        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
+fn load_session_since(cli_type: &str, file_path: &str, byte_offset: u64) -> PyResult<SessionTail> {
+    let provider = get_provider(cli_type)?;
+    provider.load_messages_since(file_path, byte_offset).ok_or_else(||
+        PyErr::new::<pyo3::exceptions::PyValueError, _>("无法增量读取该会话"))
+}
+
+/// 监听会话目录，`.jsonl` 文件创建或修改时以文件路径调用 `callback`
+/// 返回 WatchHandle，调用其 `stop()` 停止监听
+#[pyfunction]
+fn start_watch(cli_type: &str, callback: PyObject) -> PyResult<watch::WatchHandle> {
+    let provider = get_provider(cli_type)?;
+    let dir = provider.watch_dir().ok_or_else(||
+        PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>("该来源不支持监听"))?;
+    watch::start(&dir, callback)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 跨来源搜索：在所有可用 CLI 中并行搜索，返回 (cli_type, 会话) 列表
+/// 合并后按最后时间倒序，再截断到 `limit`
+#[pyfunction]
+#[pyo3(signature = (keyword, limit=1000))]
+fn search_all(keyword: &str, limit: usize) -> Vec<(&'static str, SessionInfo)> {
+    let keyword = KeywordMatcher::new(keyword, false);
+    let providers: Vec<Arc<dyn CliHistoryProvider>> = {
+        let registry = PROVIDERS.read();
+        registry.list_types().into_iter().filter_map(|t| registry.get(t)).collect()
     };
 
-    let manifest_path = trash_dir.join("manifest.json");
-    if !manifest_path.exists() {
-        return Ok(Vec::new());
+    let mut hits: Vec<(&'static str, SessionInfo)> = providers
+        .par_iter()
+        .flat_map_iter(|p| {
+            let cli_type = p.cli_type();
+            p.search(&keyword, limit).into_iter().map(move |info| (cli_type, info))
+        })
+        .collect();
+
+    hits.sort_by(|(_, a), (_, b)| {
+        b.last_timestamp_unix
+            .cmp(&a.last_timestamp_unix)
+            .then_with(|| a.file_path.cmp(&b.file_path))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+/// 统计包含关键词的会话数（行扫描，不构建 SessionInfo）
+#[pyfunction]
+fn count_matches(cli_type: &str, keyword: &str) -> PyResult<usize> {
+    Ok(get_provider(cli_type)?.count_matches(&KeywordMatcher::new(keyword, false)))
+}
+
+/// 统计项目会话数，结果与 load_project 一致
+/// 支持缓存的来源：缓存行仍有效（文件未修改）的会话直接用缓存计数，未缓存或已修改的文件逐个解析
+/// `min_user_turns` 含义同 load_project
+#[pyfunction]
+#[pyo3(signature = (cli_type, project_id, min_user_turns=None))]
+fn count_sessions(cli_type: &str, project_id: &str, min_user_turns: Option<usize>) -> PyResult<usize> {
+    let provider = get_provider(cli_type)?;
+    let min_turns = min_user_turns.unwrap_or(DEFAULT_MIN_TURNS);
+    if !provider.supports_cache() {
+        return Ok(provider.load_project(project_id, None, min_turns).len());
     }
 
-    let content = fs::read_to_string(&manifest_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    let manifest: TrashManifest = serde_json::from_str(&content)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    let cached = cache::project_cache_rows(cli_type, project_id, min_turns).map_err(HistoryError::from)?;
+    let mut count = 0;
+    for file in provider.project_files(project_id) {
+        let file_path = file.to_string_lossy();
+        count += match cached.get(file_path.as_ref()) {
+            Some(&(mtime, counted)) if mtime >= cache::get_file_mtime(&file_path) => usize::from(counted),
+            _ => exact_file_pattern(&file)
+                .map_or(0, |pattern| provider.load_project(project_id, Some(&pattern), min_turns).len()),
+        };
+    }
+    Ok(count)
+}
 
-    Ok(manifest.items)
+/// 流式搜索：返回迭代器，后台扫描到匹配会话即产出（不排序）
+/// 迭代器被回收时后台扫描随之停止；`fold_diacritics` 同 search
+#[pyfunction]
+#[pyo3(signature = (cli_type, keyword, fold_diacritics=false))]
+fn search_iter(cli_type: &str, keyword: &str, fold_diacritics: bool) -> PyResult<stream::SearchIterator> {
+    let provider = get_provider(cli_type)?;
+    Ok(stream::SearchIterator::start(provider, KeywordMatcher::new(keyword, fold_diacritics)))
 }
 
-/// 从回收站恢复会话
+/// 搜索会话
+/// `after`/`before` 为 ISO-8601 时间，只返回时间跨度与该范围有交集的会话
+/// `model` 只保留模型名包含该字符串的会话（不区分大小写）
+/// `project_id` 不为空时只扫描该项目的会话文件
+/// `scope` 可选 `all`（默认，匹配整个文件）、`user`（只匹配用户输入）、`assistant`（只匹配助手回复）
+/// 关键词与文本均按 Unicode NFC 规范化后忽略大小写比较；`fold_diacritics` 为 True 时忽略变音符号（café 匹配 cafe）
+/// 扫描期间释放 GIL；`cancel` 被取消后不再扫描新文件，返回已找到的部分结果
 #[pyfunction]
-fn restore_from_trash(cli_type: &str, dir_name: &str) -> PyResult<()> {
-    let trash_dir = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.trash_dir()
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.trash_dir()
+#[pyo3(signature = (cli_type, keyword, limit=1000, after=None, before=None, model=None, project_id=None, scope="all", fold_diacritics=false, cancel=None))]
+#[allow(clippy::too_many_arguments)]
+fn search(
+    py: Python<'_>,
+    cli_type: &str,
+    keyword: &str,
+    limit: usize,
+    after: Option<String>,
+    before: Option<String>,
+    model: Option<String>,
+    project_id: Option<String>,
+    scope: &str,
+    fold_diacritics: bool,
+    cancel: Option<CancelToken>,
+) -> PyResult<Vec<SessionInfo>> {
+    let scope = SearchScope::parse(scope).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+    let keyword = KeywordMatcher::new(keyword, fold_diacritics)
+        .with_scope(scope)
+        .with_cancel(cancel);
+    let after = parse_range_bound(after)?;
+    let before = parse_range_bound(before)?;
+    // 按时间或模型过滤时先取全部结果，过滤后再截断
+    let filtered = after.is_some() || before.is_some() || model.is_some();
+    let scan_limit = if filtered { usize::MAX } else { limit };
+
+    let provider = get_provider(cli_type)?;
+    Ok(py.allow_threads(|| {
+        let mut sessions = match &project_id {
+            Some(id) => provider.search_project(id, &keyword, scan_limit),
+            None => provider.search(&keyword, scan_limit),
+        };
+
+        if filtered {
+            sessions.retain(|s| timestamp::session_in_range(s, after, before));
+            if let Some(model) = &model {
+                sessions.retain(|s| s.model_matches(model));
+            }
+            sessions.truncate(limit);
         }
-        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    };
-
-    let manifest_path = trash_dir.join("manifest.json");
-    if !manifest_path.exists() {
-        return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>("回收站清单不存在"));
-    }
+        sessions
+    }))
+}
 
-    let content = fs::read_to_string(&manifest_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    let mut manifest: TrashManifest = serde_json::from_str(&content)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+/// 搜索会话并返回命中位置的文本摘录
+/// 每条摘录在关键词前后各保留 `context_chars` 个字符，`highlights` 给出关键词在摘录中的位置
+/// `fold_diacritics` 同 search，摘录与搜索使用同一匹配规则
+#[pyfunction]
+#[pyo3(signature = (cli_type, keyword, limit=100, context_chars=40, fold_diacritics=false))]
+fn search_with_snippets(
+    cli_type: &str,
+    keyword: &str,
+    limit: usize,
+    context_chars: usize,
+    fold_diacritics: bool,
+) -> PyResult<Vec<SearchHit>> {
+    const MAX_SNIPPETS_PER_HIT: usize = 5;
+
+    let provider = get_provider(cli_type)?;
+    let hits = provider
+        .search(&KeywordMatcher::new(keyword, fold_diacritics), limit)
+        .into_iter()
+        .map(|info| {
+            let mut snippets = Vec::new();
+            let mut highlights = Vec::new();
+            if let Some(session) = load_session_cached(provider.as_ref(), &info.file_path) {
+                for msg in session.messages.iter().filter(|m| !m.is_context) {
+                    let remaining = MAX_SNIPPETS_PER_HIT - snippets.len();
+                    if remaining == 0 {
+                        break;
+                    }
+                    for (snippet, range) in snippet::extract_snippets(&msg.get_text(), keyword, fold_diacritics, context_chars, remaining) {
+                        snippets.push(snippet);
+                        highlights.push(range);
+                    }
+                }
+            }
+            SearchHit { info, snippets, highlights }
+        })
+        .collect();
+    Ok(hits)
+}
 
-    let item = manifest.items.iter().find(|i| i.dir_name == dir_name)
-        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("回收站项不存在"))?
-        .clone();
+/// 列出时间跨度与 [start, end] 有交集的所有会话（ISO-8601，None 表示不限）
+/// 没有时间戳的会话不会返回
+#[pyfunction]
+#[pyo3(signature = (cli_type, start=None, end=None))]
+fn list_sessions_in_range(cli_type: &str, start: Option<String>, end: Option<String>) -> PyResult<Vec<SessionInfo>> {
+    let start = parse_range_bound(start)?;
+    let end = parse_range_bound(end)?;
+
+    let provider = get_provider(cli_type)?;
+
+    let mut sessions: Vec<SessionInfo> = provider
+        .list_projects(0)
+        .iter()
+        .flat_map(|p| provider.load_project(&p.id, None, DEFAULT_MIN_TURNS))
+        .filter(|s| s.first_timestamp.is_some() || s.last_timestamp.is_some())
+        .filter(|s| timestamp::session_in_range(s, start, end))
+        .collect();
+
+    provider::sort_by_last_activity(&mut sessions);
+    Ok(sessions)
+}
 
-    let item_dir = trash_dir.join(&item.dir_name);
-    if !item_dir.exists() {
-        return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>("回收站目录不存在"));
-    }
+/// 解析时间范围边界，无法解析时报错
+fn parse_range_bound(value: Option<String>) -> PyResult<Option<i64>> {
+    value
+        .map(|v| {
+            timestamp::parse_timestamp(&v).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("无效的时间: {}", v))
+            })
+        })
+        .transpose()
+}
 
-    // 恢复会话文件
-    let original_path = Path::new(&item.original_file);
-    if let Some(parent) = original_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    }
+/// 按项目分组统计搜索结果，返回 (项目 ID, 匹配会话数)
+#[pyfunction]
+fn search_facets(cli_type: &str, keyword: &str) -> PyResult<Vec<(String, usize)>> {
+    let provider = get_provider(cli_type)?;
+    Ok(provider.search_facets(&KeywordMatcher::new(keyword, false)))
+}
 
-    for entry in fs::read_dir(&item_dir)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?
-    {
-        let entry = entry.map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-        let path = entry.path();
-        if path.extension().map(|e| e == "jsonl").unwrap_or(false) {
-            fs::rename(&path, original_path)
-                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-            break;
-        }
+/// 删除会话（移动到回收站）
+#[pyfunction]
+fn delete_session(cli_type: &str, file_path: &str) -> PyResult<()> {
+    let provider = get_provider(cli_type)?;
+    provider.delete_session(file_path)?;
+    if provider.supports_cache() {
+        cache::delete_cache_entry(cli_type, file_path).ok();
     }
+    Ok(())
+}
 
-    // 恢复 file-history（如果存在）
-    if let Some(ref fh_path) = item.original_file_history {
-        let fh_src = item_dir.join("file-history");
-        if fh_src.exists() {
-            fs::rename(&fh_src, fh_path).ok();
+/// 批量删除会话（移动到回收站），回收站记录在最后一次性写入
+/// 返回与 `file_paths` 顺序一致的逐个结果，单个文件失败不影响其他文件
+#[pyfunction]
+fn delete_sessions(py: Python<'_>, cli_type: &str, file_paths: Vec<String>) -> PyResult<Vec<DeleteResult>> {
+    let provider = get_provider(cli_type)?;
+    let results = py.allow_threads(|| provider.delete_sessions(&file_paths));
+    if provider.supports_cache() {
+        for (file_path, result) in file_paths.iter().zip(&results) {
+            if result.is_ok() {
+                cache::delete_cache_entry(cli_type, file_path).ok();
+            }
         }
     }
 
-    // 删除回收站目录
-    fs::remove_dir_all(&item_dir).ok();
+    Ok(file_paths
+        .into_iter()
+        .zip(results)
+        .map(|(file_path, result)| DeleteResult {
+            file_path,
+            success: result.is_ok(),
+            error: result.err(),
+        })
+        .collect())
+}
 
-    // 更新 manifest
-    manifest.items.retain(|i| i.dir_name != dir_name);
-    let manifest_json = serde_json::to_string_pretty(&manifest)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
-    fs::write(&manifest_path, manifest_json)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+/// 设置回收站目录（传 None 恢复默认的 base_dir/trash）
+#[pyfunction]
+#[pyo3(signature = (cli_type, path=None))]
+fn set_trash_dir(cli_type: &str, path: Option<String>) -> PyResult<()> {
+    get_provider(cli_type)?;
 
+    let dir = path.map(std::path::PathBuf::from);
+    if let Some(ref d) = dir {
+        fs::create_dir_all(d)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    }
+    trash::set_dir_override(cli_type, dir);
     Ok(())
 }
 
-/// 永久删除回收站项
+/// 获取回收站项目列表
 #[pyfunction]
-fn permanently_delete(cli_type: &str, dir_name: &str) -> PyResult<()> {
-    let trash_dir = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.trash_dir()
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.trash_dir()
-        }
-        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    };
+fn get_trash_items(cli_type: &str) -> PyResult<Vec<TrashItem>> {
+    let trash_dir = get_provider(cli_type)?.trash_dir();
+    Ok(trash::list_items(cli_type, &trash_dir)?)
+}
 
-    let item_dir = trash_dir.join(dir_name);
-    if item_dir.exists() {
-        fs::remove_dir_all(&item_dir)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+/// 从回收站恢复会话，返回恢复后的文件路径
+/// 文件移回原位成功后才删除回收站记录，任一步失败时记录保留。
+/// 原路径已被占用时默认抛出 FileExistsError；`rename_on_conflict=True` 时改为恢复到
+/// `<stem>-restored-<n>.jsonl`，不会覆盖已有文件（file-history 目录同样处理）
+#[pyfunction]
+#[pyo3(signature = (cli_type, dir_name, rename_on_conflict=false))]
+fn restore_from_trash(cli_type: &str, dir_name: &str, rename_on_conflict: bool) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let trash_dir = provider.trash_dir();
+    let (project_id, restored_path) = trash::restore_item(cli_type, &trash_dir, dir_name, rename_on_conflict)?
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("回收站项不存在"))?;
+    if provider.supports_cache() {
+        recache_session(provider.as_ref(), &project_id, &restored_path);
     }
+    Ok(restored_path.to_string_lossy().to_string())
+}
 
-    // 更新 manifest
-    let manifest_path = trash_dir.join("manifest.json");
-    if manifest_path.exists() {
-        let content = fs::read_to_string(&manifest_path)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-        let mut manifest: TrashManifest = serde_json::from_str(&content)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+/// 只匹配该文件名的 glob 模式，用于通过 load_project 解析单个会话文件
+fn exact_file_pattern(path: &Path) -> Option<glob::Pattern> {
+    let file_name = path.file_name()?.to_str()?;
+    glob::Pattern::new(&glob::Pattern::escape(file_name)).ok()
+}
 
-        manifest.items.retain(|i| i.dir_name != dir_name);
-        let manifest_json = serde_json::to_string_pretty(&manifest)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
-        fs::write(&manifest_path, manifest_json)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+/// 重新解析单个会话文件并写入缓存（回收站项的 project_name 即项目 ID）
+fn recache_session(provider: &dyn CliHistoryProvider, project_id: &str, path: &Path) {
+    let pattern = match exact_file_pattern(path) {
+        Some(p) => p,
+        None => return,
+    };
+    for session in provider.load_project(project_id, Some(&pattern), DEFAULT_MIN_TURNS) {
+        let file_mtime = cache::get_file_mtime(&session.file_path);
+        write_cache_entry(provider, project_id, &session, file_mtime);
     }
+}
 
-    Ok(())
+/// 永久删除回收站项
+#[pyfunction]
+fn permanently_delete(cli_type: &str, dir_name: &str) -> PyResult<()> {
+    let trash_dir = get_provider(cli_type)?.trash_dir();
+    Ok(trash::delete_item(cli_type, &trash_dir, dir_name)?)
 }
 
-/// 清理过期回收站项
+/// 回收站占用的总字节数（所有回收站项目录下文件大小之和）
 #[pyfunction]
-#[pyo3(signature = (cli_type, retention_days=30))]
-fn cleanup_expired_trash(cli_type: &str, retention_days: i64) -> PyResult<usize> {
-    let trash_dir = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.trash_dir()
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.trash_dir()
-        }
-        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    };
+fn trash_size(cli_type: &str) -> PyResult<u64> {
+    let trash_dir = get_provider(cli_type)?.trash_dir();
+    Ok(trash::list_items(cli_type, &trash_dir)?
+        .iter()
+        .map(|item| item.size_bytes)
+        .sum())
+}
 
-    let manifest_path = trash_dir.join("manifest.json");
-    if !manifest_path.exists() {
-        return Ok(0);
+/// 清空回收站：删除所有回收站项目录并清空回收站记录，返回删除的项数
+/// 回收站已为空时返回 0
+#[pyfunction]
+fn empty_trash(cli_type: &str) -> PyResult<usize> {
+    let trash_dir = get_provider(cli_type)?.trash_dir();
+
+    for item in trash::list_items(cli_type, &trash_dir)? {
+        let item_dir = trash_dir.join(&item.dir_name);
+        if item_dir.exists() {
+            fs::remove_dir_all(&item_dir)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        }
     }
 
-    let content = fs::read_to_string(&manifest_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    let mut manifest: TrashManifest = serde_json::from_str(&content)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    Ok(cache::clear_trash_items(cli_type, &trash::dir_key(&trash_dir)).map_err(HistoryError::from)?)
+}
+
+/// 删除超过保留天数的回收站项
+fn cleanup_trash(cli_type: &str, trash_dir: &Path, retention_days: i64) -> Result<TrashCleanupReport, HistoryError> {
+    let mut report = TrashCleanupReport {
+        removed_count: 0,
+        bytes_reclaimed: 0,
+        removed_sessions: Vec::new(),
+    };
 
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -387,70 +879,424 @@ fn cleanup_expired_trash(cli_type: &str, retention_days: i64) -> PyResult<usize>
         .unwrap_or(0);
     let cutoff = now - (retention_days * 24 * 3600);
 
-    let mut removed = 0;
-    manifest.items.retain(|item| {
+    let mut removed_dirs = Vec::new();
+    for item in trash::list_items(cli_type, trash_dir)? {
         if item.deleted_at < cutoff {
-            let item_dir = trash_dir.join(&item.dir_name);
-            fs::remove_dir_all(&item_dir).ok();
-            removed += 1;
-            false
-        } else {
-            true
+            report.bytes_reclaimed += item.size_bytes;
+            fs::remove_dir_all(trash_dir.join(&item.dir_name)).ok();
+            report.removed_count += 1;
+            report.removed_sessions.push(item.session_id);
+            removed_dirs.push(item.dir_name);
         }
-    });
+    }
 
-    let manifest_json = serde_json::to_string_pretty(&manifest)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
-    fs::write(&manifest_path, manifest_json)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    cache::remove_trash_items(cli_type, &trash::dir_key(trash_dir), &removed_dirs)?;
+    Ok(report)
+}
 
-    Ok(removed)
+/// 清理过期回收站项；不传 retention_days 时使用 configure_trash 保存的天数
+#[pyfunction]
+#[pyo3(signature = (cli_type, retention_days=None))]
+fn cleanup_expired_trash(cli_type: &str, retention_days: Option<i64>) -> PyResult<TrashCleanupReport> {
+    let trash_dir = get_provider(cli_type)?.trash_dir();
+    let retention_days = retention_days.unwrap_or_else(|| trash::retention_settings(cli_type).retention_days);
+    Ok(cleanup_trash(cli_type, &trash_dir, retention_days)?)
 }
 
-/// 导出会话为 Markdown
+/// 设置回收站保留天数，以及启动刷新时是否自动清理过期项
+#[pyfunction]
+#[pyo3(signature = (cli_type, retention_days, auto_cleanup=true))]
+fn configure_trash(cli_type: &str, retention_days: i64, auto_cleanup: bool) -> PyResult<()> {
+    get_provider(cli_type)?;
+    if retention_days < 0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("retention_days 不能为负数"));
+    }
+    trash::save_retention_settings(cli_type, trash::RetentionSettings { retention_days, auto_cleanup })?;
+    Ok(())
+}
+
+/// 获取回收站保留策略：(保留天数, 是否自动清理)
+#[pyfunction]
+fn get_trash_config(cli_type: &str) -> PyResult<(i64, bool)> {
+    get_provider(cli_type)?;
+    let settings = trash::retention_settings(cli_type);
+    Ok((settings.retention_days, settings.auto_cleanup))
+}
+
+/// 计算会话指纹，用于去重和变更检测
+#[pyfunction]
+fn session_fingerprint(cli_type: &str, file_path: &str) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path);
+
+    session
+        .map(|s| s.fingerprint())
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))
+}
+
+/// 生成会话的一行标题：取首条用户输入，去掉代码块和 /命令，折叠空白后取第一句或前若干词
+/// 纯启发式、结果确定；没有用户输入时返回 None
+#[pyfunction]
+fn session_title(cli_type: &str, file_path: &str) -> PyResult<Option<String>> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+    Ok(session
+        .messages
+        .iter()
+        .filter(|m| m.is_real_user && !m.is_context)
+        .find_map(|m| snippet::heuristic_title(&m.get_text())))
+}
+
+/// 查找项目中的重复会话（分叉或被截断的副本），返回各重复组的会话列表
+/// 按轮次累积计算正文哈希：默认全文相同或一个会话是另一个的前若干轮即为重复；
+/// 指定 `prefix_turns` 时前 N 轮内容相同即归为一组
+#[pyfunction]
+#[pyo3(signature = (cli_type, project_id, prefix_turns=None))]
+fn find_duplicate_sessions(
+    py: Python<'_>,
+    cli_type: &str,
+    project_id: &str,
+    prefix_turns: Option<usize>,
+) -> PyResult<Vec<Vec<SessionInfo>>> {
+    let provider = get_provider(cli_type)?;
+    Ok(py.allow_threads(|| {
+        let hashed: Vec<(SessionInfo, Vec<blake3::Hash>)> = provider
+            .load_project(project_id, None, DEFAULT_MIN_TURNS)
+            .into_par_iter()
+            .filter_map(|info| {
+                let session = load_session_cached(provider.as_ref(), &info.file_path)?;
+                Some((info, dedup::turn_prefix_hashes(&session)))
+            })
+            .collect();
+        dedup::cluster_duplicates(hashed, prefix_turns)
+    }))
+}
+
+/// 估算会话的 token 数（用户与助手文本分别统计，近似值，适合批量预算）
+#[pyfunction]
+fn estimate_tokens(cli_type: &str, file_path: &str) -> PyResult<TokenEstimate> {
+    let provider = get_provider(cli_type)?;
+    load_session_cached(provider.as_ref(), file_path)
+        .map(|s| s.estimate_tokens())
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))
+}
+
+/// 项目汇总统计：会话数、轮次、消息数、文件大小、token 与工具调用次数
+/// 支持缓存的来源优先读取缓存（需先 init），缓存中没有工具统计的会话再解析补齐；
+/// 缓存为空或来源不支持缓存时解析整个项目
 #[pyfunction]
-fn export_to_markdown(cli_type: &str, file_path: &str) -> PyResult<String> {
-    let session = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.load_session(file_path)
+fn project_stats(cli_type: &str, project_id: &str) -> PyResult<ProjectStats> {
+    let provider = get_provider(cli_type)?;
+    if provider.supports_cache() {
+        if let Ok((mut stats, missing)) = cache::project_stats(cli_type, project_id, DEFAULT_MIN_TURNS) {
+            if stats.session_count > 0 {
+                let usages: Vec<_> = missing.par_iter().map(|f| provider.tool_stats(f)).collect();
+                usages.into_iter().for_each(|u| stats.add_tool_usage(u));
+                return Ok(stats);
+            }
         }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.load_session(file_path)
+    }
+
+    let sessions = provider.load_project(project_id, None, DEFAULT_MIN_TURNS);
+    let mut stats = ProjectStats::default();
+    sessions.iter().for_each(|s| stats.add_session(s));
+    let usages: Vec<_> = sessions.par_iter().map(|s| provider.tool_stats(&s.file_path)).collect();
+    usages.into_iter().for_each(|u| stats.add_tool_usage(u));
+    Ok(stats)
+}
+
+/// 统计会话的工具调用次数（优先读取缓存，只返回统计结果而不传递消息）
+#[pyfunction]
+fn session_tool_stats(cli_type: &str, file_path: &str) -> PyResult<HashMap<String, usize>> {
+    let provider = get_provider(cli_type)?;
+    if let Some(file_version) = cache::get_file_version(file_path) {
+        let key = memory_cache_key(cli_type, file_path);
+        let from_memory = cache::get_session_from_memory(&key, file_version)
+            .and_then(|d| serde_json::from_str(&d.tool_stats_json).ok());
+        let from_db = || cache::get_cached_tool_stats(cli_type, file_path, cache::get_file_mtime(file_path));
+        if let Some(stats) = from_memory.or_else(from_db) {
+            return Ok(stats);
         }
-        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-            format!("不支持的 CLI 类型: {}", cli_type),
-        )),
-    };
+    }
+    Ok(provider.tool_stats(file_path))
+}
+
+/// 导出会话为 Markdown
+/// `include_tools` 输出工具调用（工具名 + 格式化参数），`include_timestamps` 在每条消息标题前加时间
+#[pyfunction]
+#[pyo3(signature = (cli_type, file_path, include_tools=true, include_timestamps=true))]
+fn export_to_markdown(cli_type: &str, file_path: &str, include_tools: bool, include_timestamps: bool) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path);
 
     let session = session.ok_or_else(||
         PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
 
-    let cli_name = match cli_type {
-        "claude" => "Claude",
-        "codex" => "Codex",
-        _ => "Unknown",
-    };
+    Ok(export::session_to_markdown(
+        &session,
+        cli_display_name(cli_type),
+        include_tools,
+        include_timestamps,
+    ))
+}
+
+/// 导出会话中指定轮次范围（从 0 开始，闭区间）为 Markdown
+/// 超出范围的索引会被截断到有效范围内；`include_tools`/`include_timestamps` 同 export_to_markdown
+#[pyfunction]
+#[pyo3(signature = (cli_type, file_path, start_turn, end_turn, include_tools=true, include_timestamps=true))]
+fn export_turns_to_markdown(
+    cli_type: &str,
+    file_path: &str,
+    start_turn: usize,
+    end_turn: usize,
+    include_tools: bool,
+    include_timestamps: bool,
+) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path);
+
+    let session = session.ok_or_else(||
+        PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+
+    let cli_name = cli_display_name(cli_type);
+
+    let turns = session.turns();
+    let total = turns.len();
+    let end = end_turn.min(total.saturating_sub(1));
+    let start = start_turn.min(end);
 
     let mut lines = Vec::new();
     lines.push(format!("# {} 会话: {}\n", cli_name, session.info.id));
     lines.push(format!("路径: {}\n", session.info.cwd.as_deref().unwrap_or("未知")));
-    lines.push("\n---\n\n".to_string());
-
-    for msg in &session.messages {
-        let role = msg.role.to_uppercase();
-        let text = msg.get_text();
-        if !text.is_empty() {
-            lines.push(format!("## {}\n\n{}\n\n---\n\n", role, text));
+    if total > 0 {
+        lines.push(format!("\n> 节选第 {}-{} 轮（共 {} 轮）\n", start + 1, end + 1, total));
+        lines.push("\n---\n\n".to_string());
+        for turn in &turns[start..=end] {
+            lines.extend(
+                turn.iter()
+                    .filter_map(|m| export::markdown_message(m, include_tools, include_timestamps)),
+            );
         }
     }
 
     Ok(lines.join(""))
 }
 
+/// 导出会话为独立的 HTML 文档（内联样式，可直接用浏览器打开）
+#[pyfunction]
+fn export_to_html(cli_type: &str, file_path: &str) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+    Ok(export::session_to_html(&session, cli_display_name(cli_type)))
+}
+
+/// 导出会话为统一结构的 JSON（info + messages + content_blocks，与 CLI 原始格式无关）
+#[pyfunction]
+fn export_to_json(cli_type: &str, file_path: &str) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+    serde_json::to_string_pretty(&session)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 导出会话为 OpenAI Chat Completions 的 messages 数组（JSON 字符串，可直接 json.loads 后发送）
+/// 相邻同角色的文本会合并；`include_tools` 为 True 时保留工具调用（tool_calls）与工具结果（role: tool）
+#[pyfunction]
+#[pyo3(signature = (cli_type, file_path, include_tools=false))]
+fn export_to_openai_messages(cli_type: &str, file_path: &str, include_tools: bool) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+    serde_json::to_string(&export::session_to_openai_messages(&session, include_tools))
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 导出会话为 Anthropic Messages API 的 messages 数组（JSON 字符串）
+/// 保留 text / tool_use / tool_result 内容块，可直接用于继续对话
+#[pyfunction]
+fn export_to_anthropic_messages(cli_type: &str, file_path: &str) -> PyResult<String> {
+    let provider = get_provider(cli_type)?;
+    let session = load_session_cached(provider.as_ref(), file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))?;
+    serde_json::to_string(&export::session_to_anthropic_messages(&session))
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 流式导出：先写 `header`，再逐条写出 `render` 的结果，最后写 `footer`；返回写入的字节数
+/// 会话消息逐条解析、写出后即释放，内存占用与会话大小无关
+fn stream_export(
+    provider: &dyn CliHistoryProvider,
+    file_path: &str,
+    out_path: &str,
+    header: &str,
+    footer: &str,
+    render: impl Fn(&Message) -> Option<String>,
+) -> Result<u64, HistoryError> {
+    let mut writer = std::io::BufWriter::new(fs::File::create(out_path)?);
+    writer.write_all(header.as_bytes())?;
+    let mut written = header.len() as u64;
+    let mut write_error = None;
+
+    provider.stream_messages(file_path, &mut |msg| {
+        let Some(text) = render(&msg) else {
+            return true;
+        };
+        match writer.write_all(text.as_bytes()) {
+            Ok(()) => {
+                written += text.len() as u64;
+                true
+            }
+            Err(e) => {
+                write_error = Some(e);
+                false
+            }
+        }
+    })?;
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+
+    writer.write_all(footer.as_bytes())?;
+    writer.flush()?;
+    Ok(written + footer.len() as u64)
+}
+
+/// 取流式导出所需的会话 ID 与 cwd，会话不存在时报错（此时不创建输出文件）
+fn streaming_header(provider: &dyn CliHistoryProvider, file_path: &str) -> PyResult<(String, Option<String>)> {
+    provider
+        .session_header(file_path)
+        .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("会话不存在"))
+}
+
+/// 流式导出会话为 Markdown 文件（逐行解析、逐条写出，适合数百 MB 的超大会话），返回写入的字节数
+/// `include_tools`/`include_timestamps` 同 export_to_markdown
+#[pyfunction]
+#[pyo3(signature = (cli_type, file_path, out_path, include_tools=true, include_timestamps=true))]
+fn export_to_markdown_streaming(
+    py: Python<'_>,
+    cli_type: &str,
+    file_path: &str,
+    out_path: &str,
+    include_tools: bool,
+    include_timestamps: bool,
+) -> PyResult<u64> {
+    let provider = get_provider(cli_type)?;
+    let (session_id, cwd) = streaming_header(provider.as_ref(), file_path)?;
+    let header = export::markdown_header(cli_display_name(cli_type), &session_id, cwd.as_deref());
+    py.allow_threads(|| {
+        stream_export(provider.as_ref(), file_path, out_path, &header, "", |msg| {
+            export::markdown_message(msg, include_tools, include_timestamps)
+        })
+    })
+    .map_err(PyErr::from)
+}
+
+/// 流式导出会话为 HTML 文件，返回写入的字节数
+/// 与 export_to_html 相同，但文档头不含时间范围（写出文档头时尚未读到最后一条消息）
+#[pyfunction]
+fn export_to_html_streaming(py: Python<'_>, cli_type: &str, file_path: &str, out_path: &str) -> PyResult<u64> {
+    let provider = get_provider(cli_type)?;
+    let (session_id, cwd) = streaming_header(provider.as_ref(), file_path)?;
+    let header = export::html_header(cli_display_name(cli_type), &session_id, cwd.as_deref(), None);
+    py.allow_threads(|| {
+        stream_export(provider.as_ref(), file_path, out_path, &header, export::HTML_FOOTER, export::render_message_html)
+    })
+    .map_err(PyErr::from)
+}
+
+/// 流式导出会话为 JSON Lines 文件（每行一条消息，结构同 export_to_json 中的 messages），返回写入的字节数
+#[pyfunction]
+fn export_to_jsonl_streaming(py: Python<'_>, cli_type: &str, file_path: &str, out_path: &str) -> PyResult<u64> {
+    let provider = get_provider(cli_type)?;
+    streaming_header(provider.as_ref(), file_path)?;
+    py.allow_threads(|| {
+        stream_export(provider.as_ref(), file_path, out_path, "", "", |msg| {
+            serde_json::to_string(msg).ok().map(|line| line + "\n")
+        })
+    })
+    .map_err(PyErr::from)
+}
+
+/// 将项目的所有会话导出到 zip 归档，返回写入的会话数
+/// `format` 为 "markdown"、"html" 或 "json"，条目按 `<时间>_<会话 ID>.<扩展名>` 命名
+/// 导出期间释放 GIL；`cancel` 被取消后不再写入新的会话，已写入的条目仍保存为有效归档
+#[pyfunction]
+#[pyo3(signature = (cli_type, project_id, out_path, format="markdown", cancel=None))]
+fn export_project_archive(
+    py: Python<'_>,
+    cli_type: &str,
+    project_id: &str,
+    out_path: &str,
+    format: &str,
+    cancel: Option<CancelToken>,
+) -> PyResult<usize> {
+    let extension = match format {
+        "markdown" => "md",
+        "html" => "html",
+        "json" => "json",
+        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("不支持的导出格式: {}", format),
+        )),
+    };
+
+    let provider = get_provider(cli_type)?;
+    let cli_name = cli_display_name(cli_type);
+    py.allow_threads(|| {
+        let sessions = provider.load_project(project_id, None, DEFAULT_MIN_TURNS);
+
+        let file = fs::File::create(out_path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        let mut writer = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+
+        let mut written = 0;
+        for info in &sessions {
+            if cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                break;
+            }
+            let session = match load_session_cached(provider.as_ref(), &info.file_path) {
+                Some(s) => s,
+                None => continue,
+            };
+            let content = match format {
+                "html" => export::session_to_html(&session, cli_name),
+                "json" => serde_json::to_string_pretty(&session)
+                    .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?,
+                _ => export::session_to_markdown(&session, cli_name, true, true),
+            };
+
+            let entry_name = format!("{}.{}", export::archive_entry_stem(&session.info), extension);
+            writer
+                .start_file(entry_name, options)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+            writer
+                .write_all(content.as_bytes())
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+            written += 1;
+        }
+
+        writer
+            .finish()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(written)
+    })
+}
+
+/// 导出标题中使用的 CLI 名称（自定义类型直接使用注册名）
+fn cli_display_name(cli_type: &str) -> &str {
+    match cli_type {
+        "claude" => "Claude",
+        "codex" => "Codex",
+        "archive" => "Archive",
+        "aider" => "Aider",
+        other => other,
+    }
+}
+
 // ==================== 缓存相关 Python 绑定 ====================
 
 /// 从缓存查找匹配 cwd 的项目
@@ -460,119 +1306,283 @@ fn find_project_by_cwd_cached(cli_type: &str, cwd: &str) -> PyResult<Option<Proj
 }
 
 /// 从缓存加载项目会话列表
+/// `min_user_turns`、`model`、`sort`、`min_messages` 含义同 load_project
 #[pyfunction]
-fn load_project_from_cache(cli_type: &str, project_id: &str) -> PyResult<Vec<SessionInfo>> {
-    Ok(cache::load_project_from_cache(cli_type, project_id))
+#[pyo3(signature = (cli_type, project_id, min_user_turns=None, model=None, sort="recent", min_messages=None))]
+fn load_project_from_cache(
+    cli_type: &str,
+    project_id: &str,
+    min_user_turns: Option<usize>,
+    model: Option<&str>,
+    sort: &str,
+    min_messages: Option<usize>,
+) -> PyResult<Vec<SessionInfo>> {
+    let sort = parse_session_sort(sort)?;
+    let mut sessions = cache::load_project_from_cache(
+        cli_type,
+        project_id,
+        min_user_turns.unwrap_or(DEFAULT_MIN_TURNS),
+        min_messages.unwrap_or(0),
+        model,
+    );
+    if sort != SessionSort::Recent {
+        sort_sessions(&mut sessions, sort);
+    }
+    Ok(sessions)
 }
 
 /// 刷新缓存并加载会话（DEV 版核心功能）
 #[pyfunction]
-fn refresh_and_load_sessions(cli_type: &str, cwd: &str) -> PyResult<Vec<SessionInfo>> {
-    // 1. 先从文件系统找到匹配的项目
-    let project = match cli_type {
-        "claude" => {
-            let provider = get_claude_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Claude 目录不存在"))?;
-            provider.find_project_by_cwd(cwd)
-        }
-        "codex" => {
-            let provider = get_codex_provider()
-                .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Codex 目录不存在"))?;
-            provider.find_project_by_cwd(cwd)
-        }
-        _ => return Ok(Vec::new()),
+fn refresh_and_load_sessions(cli_type: &str, cwd: &str) -> PyResult<RefreshResult> {
+    let mut result = RefreshResult {
+        sessions: Vec::new(),
+        refreshed_count: 0,
+        from_cache_count: 0,
     };
 
-    let project = match project {
+    // 1. 先从文件系统找到匹配的项目
+    let provider = get_provider(cli_type)?;
+    if !provider.supports_cache() {
+        return Ok(result);
+    }
+    let project = match provider.find_project_by_cwd(cwd) {
         Some(p) => p,
-        None => return Ok(Vec::new()),
+        None => return Ok(result),
     };
 
     // 2. 刷新该项目的缓存（只刷新有变化的文件）
-    if cli_type == "claude" {
-        if let Some(provider) = get_claude_provider() {
-            let sessions = provider.load_project(&project.id);
-            for session in &sessions {
-                let file_mtime = cache::get_file_mtime(&session.file_path);
-                if !cache::is_cache_valid(cli_type, &session.file_path, file_mtime) {
-                    cache::update_cache_entry(
-                        cli_type,
-                        &session.file_path,
-                        &project.id,
-                        &session.id,
-                        session.message_count,
-                        session.user_turn_count,
-                        session.first_timestamp.as_deref(),
-                        session.last_timestamp.as_deref(),
-                        file_mtime,
-                        session.cwd.as_deref(),
-                    ).ok();
-                }
+    result.sessions = provider.load_project(&project.id, None, DEFAULT_MIN_TURNS);
+    let (refreshed, from_cache) = cache_sessions(provider.as_ref(), &project.id, &result.sessions);
+    result.refreshed_count = refreshed;
+    result.from_cache_count = from_cache;
+
+    Ok(result)
+}
+
+/// 强制重建项目的缓存：不检查修改时间，重新解析并写入每个会话，同时删除源文件已不存在的缓存行
+/// 用于“重新索引”按钮，或升级后缓存字段有变化时；返回写入的会话数
+#[pyfunction]
+fn index_project(py: Python<'_>, cli_type: &str, project_id: &str) -> PyResult<usize> {
+    let provider = get_provider(cli_type)?;
+    if !provider.supports_cache() {
+        return Err(PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>("该来源不支持缓存"));
+    }
+
+    py.allow_threads(|| {
+        for file_path in cache::cached_project_files(cli_type, project_id).map_err(HistoryError::from)? {
+            if !Path::new(&file_path).exists() {
+                cache::delete_cache_entry(cli_type, &file_path).ok();
             }
-            return Ok(sessions);
         }
-    } else if cli_type == "codex" {
-        if let Some(provider) = get_codex_provider() {
-            let sessions = provider.load_project(&project.id);
-            for session in &sessions {
-                let file_mtime = cache::get_file_mtime(&session.file_path);
-                if !cache::is_cache_valid(cli_type, &session.file_path, file_mtime) {
-                    cache::update_cache_entry(
-                        cli_type,
-                        &session.file_path,
-                        &project.id,
-                        &session.id,
-                        session.message_count,
-                        session.user_turn_count,
-                        session.first_timestamp.as_deref(),
-                        session.last_timestamp.as_deref(),
-                        file_mtime,
-                        session.cwd.as_deref(),
-                    ).ok();
-                }
-            }
-            return Ok(sessions);
+
+        let sessions = provider.load_project(project_id, None, DEFAULT_MIN_TURNS);
+        Ok(sessions
+            .iter()
+            .filter(|s| write_cache_entry(provider.as_ref(), project_id, s, cache::get_file_mtime(&s.file_path)))
+            .count())
+    })
+}
+
+/// 将缓存失效（文件已修改或未缓存）的会话写入缓存
+/// 返回 (重新写入数, 缓存有效数)
+fn cache_sessions(provider: &dyn CliHistoryProvider, project_id: &str, sessions: &[SessionInfo]) -> (usize, usize) {
+    let cli_type = provider.cli_type();
+    let mut refreshed = 0;
+    let mut from_cache = 0;
+    for session in sessions {
+        let file_mtime = cache::get_file_mtime(&session.file_path);
+        if cache::is_cache_valid(cli_type, &session.file_path, file_mtime) {
+            from_cache += 1;
+        } else if write_cache_entry(provider, project_id, session, file_mtime) {
+            refreshed += 1;
         }
     }
+    (refreshed, from_cache)
+}
 
-    Ok(Vec::new())
+/// 写入单个会话的缓存行（附带工具调用统计），返回是否成功
+fn write_cache_entry(provider: &dyn CliHistoryProvider, project_id: &str, session: &SessionInfo, file_mtime: i64) -> bool {
+    let tool_stats = provider.tool_stats(&session.file_path);
+    cache::update_cache_entry(provider.cli_type(), project_id, session, &tool_stats, file_mtime).is_ok()
 }
 
 /// 启动时增量刷新历史缓存
+/// `progress` 不为空时，每处理完一个会话以 `(已处理数, 会话总数)` 调用一次；
+/// 扫描期间释放 GIL，仅在回调时短暂获取，回调抛出的异常只打印不中断刷新
+/// `cancel` 被取消后停止处理剩余会话并返回已写入数，同时恢复上次启动时间，下次启动会重新检查
 #[pyfunction]
-fn refresh_history_on_startup(cli_type: &str) -> PyResult<usize> {
+#[pyo3(signature = (cli_type, progress=None, cancel=None))]
+fn refresh_history_on_startup(
+    py: Python<'_>,
+    cli_type: &str,
+    progress: Option<PyObject>,
+    cancel: Option<CancelToken>,
+) -> PyResult<usize> {
+    let provider = match get_provider(cli_type) {
+        Ok(p) if p.supports_cache() => p,
+        _ => return Ok(0),
+    };
+
     let last_startup = cache::get_last_startup_time(cli_type);
     cache::update_startup_time(cli_type).ok();
 
+    let settings = trash::retention_settings(cli_type);
+    if settings.auto_cleanup {
+        cleanup_trash(cli_type, &provider.trash_dir(), settings.retention_days).ok();
+    }
+
+    let updated = py.allow_threads(|| {
+        refresh_since(provider.as_ref(), last_startup, cancel.as_ref(), &mut |done, total| {
+            if let Some(callback) = &progress {
+                Python::with_gil(|py| {
+                    if let Err(e) = callback.call1(py, (done, total)) {
+                        e.print(py);
+                    }
+                });
+            }
+        })
+    });
+    if cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+        cache::set_startup_time(cli_type, last_startup).ok();
+    }
+    Ok(updated)
+}
+
+/// 将上次启动后修改过且缓存失效的会话写入缓存，返回写入数
+/// 先列出全部会话得到总数，之后每处理一个会话调用一次 `progress(已处理数, 总数)`
+/// `cancel` 被取消后不再解析新的项目或处理剩余会话
+fn refresh_since(
+    provider: &dyn CliHistoryProvider,
+    last_startup: i64,
+    cancel: Option<&CancelToken>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> usize {
+    let cli_type = provider.cli_type();
+    let is_cancelled = || cancel.is_some_and(|c| c.is_cancelled());
+    let sessions: Vec<(String, SessionInfo)> = provider
+        .list_projects(0)
+        .into_iter()
+        .take_while(|_| !is_cancelled())
+        .flat_map(|project| {
+            provider
+                .load_project(&project.id, None, DEFAULT_MIN_TURNS)
+                .into_iter()
+                .map(move |session| (project.id.clone(), session))
+        })
+        .collect();
+
+    let total = sessions.len();
     let mut updated_count = 0;
+    for (done, (project_id, session)) in sessions.iter().enumerate() {
+        if is_cancelled() {
+            break;
+        }
+        let file_mtime = cache::get_file_mtime(&session.file_path);
+        if file_mtime > last_startup
+            && !cache::is_cache_valid(cli_type, &session.file_path, file_mtime)
+            && write_cache_entry(provider, project_id, session, file_mtime)
+        {
+            updated_count += 1;
+        }
+        progress(done + 1, total);
+    }
+    updated_count
+}
 
-    if cli_type == "claude" {
-        if let Some(provider) = get_claude_provider() {
-            for project in provider.list_projects(0) {
-                let sessions = provider.load_project(&project.id);
-                for session in sessions {
-                    let file_mtime = cache::get_file_mtime(&session.file_path);
-                    if file_mtime > last_startup && !cache::is_cache_valid(cli_type, &session.file_path, file_mtime) {
-                        cache::update_cache_entry(
-                            cli_type,
-                            &session.file_path,
-                            &project.id,
-                            &session.id,
-                            session.message_count,
-                            session.user_turn_count,
-                            session.first_timestamp.as_deref(),
-                            session.last_timestamp.as_deref(),
-                            file_mtime,
-                            session.cwd.as_deref(),
-                        ).ok();
-                        updated_count += 1;
+/// 按日期汇总项目的 token 用量，返回 (日期, 输入 tokens, 输出 tokens)
+/// 优先读取缓存，缓存为空时回退到扫描文件；无用量数据的会话按 0 计入
+#[pyfunction]
+fn token_usage_by_day(cli_type: &str, project_id: &str) -> PyResult<Vec<(String, i64, i64)>> {
+    let mut sessions = cache::load_project_from_cache(cli_type, project_id, DEFAULT_MIN_TURNS, 0, None);
+    if sessions.is_empty() {
+        let provider = get_provider(cli_type)?;
+        sessions = provider.load_project(project_id, None, DEFAULT_MIN_TURNS);
+    }
+
+    let mut by_day: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+    for session in &sessions {
+        let day = match session.first_timestamp.as_deref().and_then(|t| t.get(..10)) {
+            Some(d) => d,
+            None => continue,
+        };
+        let entry = by_day.entry(day.to_string()).or_insert((0, 0));
+        entry.0 += session.input_tokens;
+        entry.1 += session.output_tokens;
+    }
+
+    Ok(by_day.into_iter().map(|(day, (input, output))| (day, input, output)).collect())
+}
+
+/// 检查缓存与文件系统的一致性并修复
+/// 删除孤立行、重新解析修改时间不一致的文件、补充未缓存的会话
+#[pyfunction]
+fn validate_and_repair_cache(cli_type: &str) -> PyResult<RepairReport> {
+    let provider = get_provider(cli_type)?;
+    if !provider.supports_cache() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            format!("不支持的 CLI 类型: {}", cli_type),
+        ));
+    }
+
+    let mut cached = cache::cached_file_mtimes(cli_type)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+    let mut report = RepairReport {
+        pruned_count: 0,
+        reparsed_count: 0,
+        added_count: 0,
+    };
+
+    for project in provider.list_projects(0) {
+        for session in provider.load_project(&project.id, None, DEFAULT_MIN_TURNS) {
+            let file_mtime = cache::get_file_mtime(&session.file_path);
+            match cached.remove(&session.file_path) {
+                Some(cached_mtime) if cached_mtime == file_mtime => {}
+                Some(_) => {
+                    if write_cache_entry(provider.as_ref(), &project.id, &session, file_mtime) {
+                        report.reparsed_count += 1;
+                    }
+                }
+                None => {
+                    if write_cache_entry(provider.as_ref(), &project.id, &session, file_mtime) {
+                        report.added_count += 1;
                     }
                 }
             }
         }
     }
 
-    Ok(updated_count)
+    // 剩余未匹配的行中，文件已不存在的为孤立行
+    for file_path in cached.keys() {
+        if !Path::new(file_path).exists() && cache::delete_cache_entry(cli_type, file_path).is_ok() {
+            report.pruned_count += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// 统计缓存概况（行数、项目数、缓存时间范围、源文件总大小）
+#[pyfunction]
+fn cache_stats(cli_type: &str) -> PyResult<CacheStats> {
+    cache::cache_stats(cli_type)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 从另一台机器的缓存数据库导入记录，返回导入行数
+#[pyfunction]
+fn import_cache(cli_type: &str, other_db_path: &str) -> PyResult<usize> {
+    if !Path::new(other_db_path).exists() {
+        return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>("数据库文件不存在"));
+    }
+    cache::import_cache(cli_type, other_db_path)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+/// 设置缓存数据库的忙等待超时（毫秒，默认 5000）
+/// 超时越长并发写入越不易报 `database is locked`，但锁竞争时调用阻塞越久
+#[pyfunction]
+fn set_cache_busy_timeout(ms: u64) {
+    cache::set_busy_timeout(ms);
 }
 
 /// 清空缓存
@@ -594,33 +1604,95 @@ fn clear_memory_cache() -> PyResult<()> {
 fn liangmu_history(m: &Bound<'_, PyModule>) -> PyResult<()> {
     // 注册数据类型
     m.add_class::<Project>()?;
+    m.add_class::<ProjectPage>()?;
+    m.add_class::<CancelToken>()?;
     m.add_class::<SessionInfo>()?;
     m.add_class::<ContentBlock>()?;
     m.add_class::<Message>()?;
     m.add_class::<Session>()?;
     m.add_class::<PaginatedMessages>()?;
+    m.add_class::<MessageRange>()?;
+    m.add_class::<CacheStats>()?;
+    m.add_class::<ProjectStats>()?;
+    m.add_class::<TokenEstimate>()?;
+    m.add_class::<SessionTail>()?;
+    m.add_class::<watch::WatchHandle>()?;
+    m.add_class::<stream::SearchIterator>()?;
+    m.add_class::<SearchHit>()?;
     m.add_class::<TrashItem>()?;
+    m.add_class::<TrashCleanupReport>()?;
+    m.add_class::<DeleteResult>()?;
+    m.add_class::<RefreshResult>()?;
+    m.add_class::<RepairReport>()?;
 
     // 注册函数 - 基础功能
     m.add_function(wrap_pyfunction!(list_cli_types, m)?)?;
+    m.add_function(wrap_pyfunction!(reinitialize_providers, m)?)?;
+    m.add_function(wrap_pyfunction!(set_provider_base_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(set_aider_roots, m)?)?;
+    m.add_function(wrap_pyfunction!(open_archive, m)?)?;
+    m.add_function(wrap_pyfunction!(register_custom_provider, m)?)?;
+    m.add_function(wrap_pyfunction!(init, m)?)?;
     m.add_function(wrap_pyfunction!(list_projects, m)?)?;
+    m.add_function(wrap_pyfunction!(list_projects_paged, m)?)?;
     m.add_function(wrap_pyfunction!(find_project_by_cwd, m)?)?;
+    m.add_function(wrap_pyfunction!(find_projects_matching, m)?)?;
     m.add_function(wrap_pyfunction!(load_project, m)?)?;
+    m.add_function(wrap_pyfunction!(all_session_infos, m)?)?;
     m.add_function(wrap_pyfunction!(load_session, m)?)?;
+    m.add_function(wrap_pyfunction!(load_session_strict, m)?)?;
     m.add_function(wrap_pyfunction!(load_session_paginated, m)?)?;
+    m.add_function(wrap_pyfunction!(load_session_range, m)?)?;
+    m.add_function(wrap_pyfunction!(load_turn, m)?)?;
+    m.add_function(wrap_pyfunction!(load_session_since, m)?)?;
+    m.add_function(wrap_pyfunction!(start_watch, m)?)?;
     m.add_function(wrap_pyfunction!(search, m)?)?;
+    m.add_function(wrap_pyfunction!(search_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(search_all, m)?)?;
+    m.add_function(wrap_pyfunction!(count_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(count_sessions, m)?)?;
+    m.add_function(wrap_pyfunction!(search_with_snippets, m)?)?;
+    m.add_function(wrap_pyfunction!(list_sessions_in_range, m)?)?;
+    m.add_function(wrap_pyfunction!(search_facets, m)?)?;
     m.add_function(wrap_pyfunction!(delete_session, m)?)?;
+    m.add_function(wrap_pyfunction!(delete_sessions, m)?)?;
+    m.add_function(wrap_pyfunction!(set_trash_dir, m)?)?;
     m.add_function(wrap_pyfunction!(get_trash_items, m)?)?;
     m.add_function(wrap_pyfunction!(restore_from_trash, m)?)?;
     m.add_function(wrap_pyfunction!(permanently_delete, m)?)?;
     m.add_function(wrap_pyfunction!(cleanup_expired_trash, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_trash, m)?)?;
+    m.add_function(wrap_pyfunction!(get_trash_config, m)?)?;
+    m.add_function(wrap_pyfunction!(empty_trash, m)?)?;
+    m.add_function(wrap_pyfunction!(trash_size, m)?)?;
+    m.add_function(wrap_pyfunction!(session_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicate_sessions, m)?)?;
+    m.add_function(wrap_pyfunction!(session_title, m)?)?;
+    m.add_function(wrap_pyfunction!(session_tool_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(project_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_tokens, m)?)?;
     m.add_function(wrap_pyfunction!(export_to_markdown, m)?)?;
+    m.add_function(wrap_pyfunction!(export_turns_to_markdown, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_html, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_markdown_streaming, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_html_streaming, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_jsonl_streaming, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_openai_messages, m)?)?;
+    m.add_function(wrap_pyfunction!(export_to_anthropic_messages, m)?)?;
+    m.add_function(wrap_pyfunction!(export_project_archive, m)?)?;
 
     // 注册函数 - 缓存功能（DEV 版核心）
     m.add_function(wrap_pyfunction!(find_project_by_cwd_cached, m)?)?;
     m.add_function(wrap_pyfunction!(load_project_from_cache, m)?)?;
     m.add_function(wrap_pyfunction!(refresh_and_load_sessions, m)?)?;
+    m.add_function(wrap_pyfunction!(index_project, m)?)?;
     m.add_function(wrap_pyfunction!(refresh_history_on_startup, m)?)?;
+    m.add_function(wrap_pyfunction!(token_usage_by_day, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_and_repair_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(cache_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(import_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(set_cache_busy_timeout, m)?)?;
     m.add_function(wrap_pyfunction!(clear_cache, m)?)?;
     m.add_function(wrap_pyfunction!(clear_memory_cache, m)?)?;
 
@@ -0,0 +1,120 @@
+//! 关键词匹配
+//!
+//! 关键词与被搜索的文本都先转小写再做 Unicode NFC 规范化，避免日志中的分解形式
+//! （如 `e` + U+0301）与输入法给出的组合形式匹配不上。`fold_diacritics` 模式下
+//! 进一步去掉变音符号，`café` 与 `cafe` 视为相同。
+
+use crate::cancel::CancelToken;
+use crate::provider::SearchScope;
+use crate::types::Message;
+use serde_json::Value;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// 规范化后的搜索关键词
+#[derive(Debug, Clone)]
+pub struct KeywordMatcher {
+    needle: String,
+    fold_diacritics: bool,
+    scope: SearchScope,
+    cancel: Option<CancelToken>,
+}
+
+impl KeywordMatcher {
+    pub fn new(keyword: &str, fold_diacritics: bool) -> Self {
+        Self {
+            needle: normalize(keyword, fold_diacritics),
+            fold_diacritics,
+            scope: SearchScope::All,
+            cancel: None,
+        }
+    }
+
+    /// 附带取消标记，取消后各 Provider 不再扫描新的文件
+    pub fn with_cancel(mut self, cancel: Option<CancelToken>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 按消息角色限定匹配范围
+    pub fn with_scope(mut self, scope: SearchScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// 搜索是否已被取消（逐文件扫描前检查）
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+
+    /// 文本是否包含关键词
+    pub fn matches(&self, text: &str) -> bool {
+        normalize(text, self.fold_diacritics).contains(&self.needle)
+    }
+
+    /// 消息是否命中：默认匹配消息全文；限定范围时只匹配该范围消息的正文块
+    pub fn message_matches(&self, msg: &Message) -> bool {
+        if self.scope == SearchScope::All {
+            return self.matches(&msg.get_text());
+        }
+        self.scope.includes(msg)
+            && msg
+                .content_blocks
+                .iter()
+                .filter(|b| b.block_type == "text")
+                .filter_map(|b| b.text.as_deref())
+                .any(|t| self.matches(t))
+    }
+
+    /// JSONL 行是否命中：先匹配整行，限定范围时再用 `parse` 解析出消息按角色复核
+    pub fn line_matches(&self, line: &str, parse: impl FnOnce(&Value) -> Option<Message>) -> bool {
+        if !self.matches(line) {
+            return false;
+        }
+        if self.scope == SearchScope::All {
+            return true;
+        }
+        serde_json::from_str::<Value>(line)
+            .ok()
+            .and_then(|data| parse(&data))
+            .is_some_and(|msg| self.message_matches(&msg))
+    }
+}
+
+/// 转小写并做 NFC 规范化；`fold_diacritics` 为 true 时先分解再去掉组合用变音符号
+/// 纯 ASCII 文本两种规范化都不改变，直接转小写
+pub fn normalize(text: &str, fold_diacritics: bool) -> String {
+    if text.is_ascii() {
+        return text.to_ascii_lowercase();
+    }
+    let lower = text.to_lowercase();
+    if fold_diacritics {
+        // 去掉变音符号后重新组合，使韩文等音节保持与 NFC 一致的形式
+        lower.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect()
+    } else {
+        lower.nfc().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposed_and_composed_text_match() {
+        let composed = "caf\u{e9} au lait";
+        let decomposed = "CAFE\u{301} au lait";
+        assert!(KeywordMatcher::new("caf\u{e9}", false).matches(decomposed));
+        assert!(KeywordMatcher::new("cafe\u{301}", false).matches(composed));
+        assert!(!KeywordMatcher::new("cafe", false).matches(composed));
+    }
+
+    #[test]
+    fn fold_diacritics_ignores_accents() {
+        let matcher = KeywordMatcher::new("cafe", true);
+        assert!(matcher.matches("Caf\u{e9}"));
+        assert!(matcher.matches("cafe\u{301}"));
+        assert!(KeywordMatcher::new("caf\u{e9}", true).matches("CAFE"));
+        assert_eq!(normalize("\u{d55c}\u{ae00}", true), "\u{d55c}\u{ae00}");
+    }
+}
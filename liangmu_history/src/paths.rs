@@ -0,0 +1,69 @@
+//! 路径规范化工具
+
+/// 当前平台的文件系统默认是否大小写不敏感（Windows、macOS）
+pub const CASE_INSENSITIVE_FS: bool = cfg!(any(windows, target_os = "macos"));
+
+/// 规范化 cwd 用于比较（按当前平台决定是否忽略大小写）
+pub fn canonicalize_cwd(cwd: &str) -> String {
+    canonicalize_cwd_with(cwd, CASE_INSENSITIVE_FS)
+}
+
+/// 规范化 cwd 用于比较：统一分隔符为 `/`，去掉末尾分隔符，
+/// `case_insensitive` 为 true 时转为小写
+pub fn canonicalize_cwd_with(cwd: &str, case_insensitive: bool) -> String {
+    let mut normalized = cwd.replace('\\', "/");
+    while normalized.len() > 1 && normalized.ends_with('/') {
+        normalized.pop();
+    }
+    if case_insensitive {
+        normalized.to_lowercase()
+    } else {
+        normalized
+    }
+}
+
+/// 规范化路径用于 Codex 项目分组（按当前平台决定路径风格）
+pub fn normalize_path(path: &str) -> String {
+    normalize_path_with(path, cfg!(windows))
+}
+
+/// Codex 项目 ID 中可读片段的最大长度（取路径末尾）
+const PROJECT_SLUG_MAX_LEN: usize = 48;
+
+/// Codex 项目 ID：规范化 cwd 的可读片段加短哈希，只含 ASCII 字母数字和 `-`，可直接用作文件名或键
+/// 按 canonicalize_cwd 视为同一目录的 cwd 总是得到同一 ID
+pub fn codex_project_id(cwd: &str) -> String {
+    let key = canonicalize_cwd(&normalize_path(cwd));
+    let hash = blake3::hash(key.as_bytes()).to_hex();
+    let slug: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    // 非 ASCII 字符已替换为 `-`，按字节截取是安全的
+    let slug = &slug[slug.len().saturating_sub(PROJECT_SLUG_MAX_LEN)..];
+    format!("{}-{}", slug, &hash.as_str()[..12])
+}
+
+/// `windows` 为 true 时统一使用反斜杠并大写盘符；否则保持 POSIX 正斜杠。
+/// 两种风格都去掉末尾分隔符，但保留根目录（`/`、`C:\`）
+pub fn normalize_path_with(path: &str, windows: bool) -> String {
+    if !windows {
+        let mut normalized = path.to_string();
+        while normalized.len() > 1 && normalized.ends_with('/') {
+            normalized.pop();
+        }
+        return normalized;
+    }
+
+    let mut normalized = path.replace('/', "\\");
+    let has_drive = normalized.as_bytes().get(1) == Some(&b':');
+    // 盘符大写
+    if has_drive {
+        normalized[..1].make_ascii_uppercase();
+    }
+    let root_len = if has_drive { 3 } else { 1 };
+    while normalized.len() > root_len && normalized.ends_with('\\') {
+        normalized.pop();
+    }
+    normalized
+}
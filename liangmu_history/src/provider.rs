@@ -1,7 +1,15 @@
 //! CLI Provider trait 定义 - 可扩展架构
 
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::trash::{self, TrashedSession};
 use crate::types::*;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::Arc;
+
+/// load_project 默认的最少用户轮次数：保留单轮会话，所有 Provider 一致
+pub const DEFAULT_MIN_TURNS: usize = 1;
 
 /// CLI 历史记录提供者 trait
 /// 实现此 trait 可支持新的 CLI 工具
@@ -15,38 +23,350 @@ pub trait CliHistoryProvider: Send + Sync {
     /// 列出所有项目（按最后修改时间倒序）
     fn list_projects(&self, limit: usize) -> Vec<Project>;
 
+    /// 分页列出项目：按 `sort` 排序后取从 `offset` 开始的 `count` 个，同时返回项目总数
+    /// 默认实现列出全部项目后排序切片（Codex 即对按 cwd 分组的结果分页）
+    fn list_projects_paged(&self, offset: usize, count: usize, sort: ProjectSort, ascending: bool) -> (Vec<Project>, usize) {
+        paginate_projects(self.list_projects(0), offset, count, sort, ascending)
+    }
+
     /// 根据工作目录查找项目
     fn find_project_by_cwd(&self, cwd: &str) -> Option<Project>;
 
     /// 加载项目的所有会话
-    fn load_project(&self, project_id: &str) -> Vec<SessionInfo>;
+    /// `name_glob` 不为空时，只解析文件名匹配该模式的会话文件
+    /// 只返回真实用户轮次数不少于 `min_turns` 的会话（默认 DEFAULT_MIN_TURNS）；
+    /// 0 轮会话始终被过滤，需要时用 all_session_infos
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo>;
+
+    /// 加载项目的所有会话，不做轮次和时间戳过滤（用于统计分析）
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo>;
+
+    /// 项目下的会话文件路径（用于核对缓存是否覆盖项目全部文件）
+    /// 默认解析全部会话取路径，能直接列目录的来源应覆盖
+    fn project_files(&self, project_id: &str) -> Vec<std::path::PathBuf> {
+        self.all_session_infos(project_id)
+            .into_iter()
+            .map(|s| std::path::PathBuf::from(s.file_path))
+            .collect()
+    }
 
     /// 加载单个会话的完整消息
     fn load_session(&self, file_path: &str) -> Option<Session>;
 
-    /// 分页加载会话消息
+    /// 会话关联的子任务（sub-agent）消息，已设置 `subagent` 标记；不支持子任务的 Provider 返回空
+    fn load_subagent_messages(&self, _file_path: &str) -> Vec<Message> {
+        Vec::new()
+    }
+
+    /// 从字节偏移处继续读取会话文件，只解析新增消息（用于跟踪进行中的会话）
+    /// 不支持增量读取的来源返回 None
+    fn load_messages_since(&self, _file_path: &str, _byte_offset: u64) -> Option<SessionTail> {
+        None
+    }
+
+    /// 统计会话中各工具的调用次数
+    fn tool_stats(&self, file_path: &str) -> HashMap<String, usize> {
+        self.load_session(file_path)
+            .map(|s| s.tool_usage())
+            .unwrap_or_default()
+    }
+
+    /// 分页加载会话消息（只返回开头和结尾若干轮）
     fn load_session_paginated(
         &self,
         file_path: &str,
         first_turns: usize,
         last_turns: usize,
-    ) -> Option<PaginatedMessages>;
+    ) -> Option<PaginatedMessages> {
+        let rounds = self.load_session(file_path)?.turns();
+
+        let total_turns = rounds.len();
+        let total_messages: usize = rounds.iter().map(|r| r.len()).sum();
+
+        if first_turns + last_turns >= total_turns {
+            // 全部显示
+            let all: Vec<Message> = rounds.into_iter().flatten().collect();
+            return Some(PaginatedMessages {
+                first: all,
+                last: Vec::new(),
+                has_middle: false,
+                total_turns,
+                total_messages,
+                middle_turn_count: 0,
+                first_turn_end: total_turns,
+                last_turn_start: total_turns,
+            });
+        }
+
+        let first: Vec<Message> = rounds[..first_turns].iter().flatten().cloned().collect();
+        let last: Vec<Message> = rounds[total_turns - last_turns..]
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        Some(PaginatedMessages {
+            first,
+            last,
+            has_middle: true,
+            total_turns,
+            total_messages,
+            middle_turn_count: total_turns - first_turns - last_turns,
+            first_turn_end: first_turns,
+            last_turn_start: total_turns - last_turns,
+        })
+    }
+
+    /// 会话的 (ID, cwd)，用于流式导出时先写出文档头
+    /// 默认实现加载整个会话
+    fn session_header(&self, file_path: &str) -> Option<(String, Option<String>)> {
+        let info = self.load_session(file_path)?.info;
+        Some((info.id, info.cwd))
+    }
+
+    /// 逐条产出会话消息，不在内存中保留整个会话（用于导出超大会话），`emit` 返回 false 时停止
+    /// 默认实现先加载整个会话再逐条产出
+    fn stream_messages(&self, file_path: &str, emit: &mut dyn FnMut(Message) -> bool) -> Result<(), HistoryError> {
+        let session = self
+            .load_session(file_path)
+            .ok_or_else(|| HistoryError::NotFound(format!("会话不存在: {}", file_path)))?;
+        for msg in session.messages {
+            if !emit(msg) {
+                break;
+            }
+        }
+        Ok(())
+    }
 
-    /// 搜索包含关键词的会话
-    fn search(&self, keyword: &str, limit: usize) -> Vec<SessionInfo>;
+    /// 搜索包含关键词的会话（按最后时间倒序，见 sort_by_last_activity）
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo>;
+
+    /// 只在单个项目内搜索（按最后时间倒序）
+    /// 默认实现先全局搜索，再保留属于该项目的会话
+    fn search_project(&self, project_id: &str, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let project_files: HashSet<String> = self
+            .all_session_infos(project_id)
+            .into_iter()
+            .map(|s| s.file_path)
+            .collect();
+        let mut sessions = self.search(keyword, usize::MAX);
+        sessions.retain(|s| project_files.contains(&s.file_path));
+        sessions.truncate(limit);
+        sessions
+    }
+
+    /// 统计包含关键词的会话文件数（只做行扫描，不解析会话，可能略多于 search 的结果数）
+    fn count_matches(&self, keyword: &KeywordMatcher) -> usize {
+        self.search(keyword, usize::MAX).len()
+    }
+
+    /// 流式搜索：每找到一个匹配会话调用一次 `emit`（不保证顺序），`emit` 返回 false 时尽快停止
+    /// 默认实现先完成整个搜索再逐个产出
+    fn search_each(&self, keyword: &KeywordMatcher, emit: &(dyn Fn(SessionInfo) -> bool + Sync)) {
+        for info in self.search(keyword, usize::MAX) {
+            if !emit(info) {
+                break;
+            }
+        }
+    }
+
+    /// 按项目统计包含关键词的会话数（按数量降序）
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)>;
+
+    /// 将会话文件移入回收站（不写回收站记录，由 delete_session / delete_sessions 统一写入）
+    fn move_to_trash(&self, file_path: &str) -> Result<TrashedSession, HistoryError>;
 
     /// 删除会话（移动到回收站）
-    fn delete_session(&self, file_path: &str) -> Result<(), String>;
+    fn delete_session(&self, file_path: &str) -> Result<(), HistoryError> {
+        let trashed = self.move_to_trash(file_path)?;
+        if let Err(e) = trash::record_item(self.cli_type(), &self.trash_dir(), &trashed.item) {
+            trashed.rollback();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// 批量删除会话：逐个移入回收站，最后在一个事务中写入全部回收站记录
+    /// 返回与 `file_paths` 一一对应的结果；写入记录失败时撤销所有移动，全部视为失败
+    fn delete_sessions(&self, file_paths: &[String]) -> Vec<Result<(), String>> {
+        let mut results: Vec<Result<(), String>> = Vec::with_capacity(file_paths.len());
+        let mut trashed = Vec::new();
+        for file_path in file_paths {
+            match self.move_to_trash(file_path) {
+                Ok(t) => {
+                    trashed.push(t);
+                    results.push(Ok(()));
+                }
+                Err(e) => results.push(Err(e.to_string())),
+            }
+        }
+
+        if trashed.is_empty() {
+            return results;
+        }
+        let items: Vec<TrashItem> = trashed.iter().map(|t| t.item.clone()).collect();
+        if let Err(e) = trash::record_items(self.cli_type(), &self.trash_dir(), &items) {
+            for t in trashed.iter().rev() {
+                t.rollback();
+            }
+            let msg = e.to_string();
+            for r in results.iter_mut().filter(|r| r.is_ok()) {
+                *r = Err(msg.clone());
+            }
+        }
+        results
+    }
 
-    /// 获取回收站目录
+    /// 会话是否为独立的本地文件，可按修改时间写入 SQLite 缓存
+    fn supports_cache(&self) -> bool {
+        false
+    }
+
+    /// 需要监听变化的会话目录，不支持监听的来源返回 None
+    fn watch_dir(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+
+    /// 获取回收站目录（优先使用 set_trash_dir 设置的位置，默认为 base_dir/trash）
     fn trash_dir(&self) -> std::path::PathBuf {
-        self.base_dir().join("trash")
+        crate::trash::dir_override(self.cli_type())
+            .unwrap_or_else(|| self.base_dir().join("trash"))
+    }
+}
+
+/// 会话排序：按解析后的最后时间倒序（无时间戳的排在最后），时间相同时按文件路径升序，保证结果稳定
+pub fn sort_by_last_activity(sessions: &mut [SessionInfo]) {
+    sessions.sort_by(|a, b| {
+        b.last_timestamp_unix
+            .cmp(&a.last_timestamp_unix)
+            .then_with(|| a.file_path.cmp(&b.file_path))
+    });
+}
+
+/// 会话列表排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionSort {
+    /// 按最后时间倒序（默认，同 sort_by_last_activity）
+    Recent,
+    /// 按最后时间正序
+    Oldest,
+    /// 按开始时间正序（无开始时间时用最后时间），便于按时间顺序回顾
+    StartAsc,
+}
+
+impl SessionSort {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "recent" => Ok(Self::Recent),
+            "oldest" => Ok(Self::Oldest),
+            "start_asc" => Ok(Self::StartAsc),
+            _ => Err(format!("不支持的排序方式: {}（可选 recent / oldest / start_asc）", value)),
+        }
+    }
+}
+
+/// 按指定方式排序会话：无时间戳的始终排在最后，时间相同时按文件路径升序
+pub fn sort_sessions(sessions: &mut [SessionInfo], sort: SessionSort) {
+    let key = |s: &SessionInfo| match sort {
+        SessionSort::Recent | SessionSort::Oldest => s.last_timestamp_unix,
+        SessionSort::StartAsc => s.first_timestamp_unix.or(s.last_timestamp_unix),
+    };
+    match sort {
+        SessionSort::Recent => sort_by_last_activity(sessions),
+        SessionSort::Oldest | SessionSort::StartAsc => sessions.sort_by(|a, b| {
+            let (a_key, b_key) = (key(a), key(b));
+            a_key
+                .is_none()
+                .cmp(&b_key.is_none())
+                .then_with(|| a_key.cmp(&b_key))
+                .then_with(|| a.file_path.cmp(&b.file_path))
+        }),
+    }
+}
+
+/// 项目列表排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectSort {
+    /// 按目录修改时间（默认）
+    Recent,
+    /// 按 cwd（无 cwd 时用项目 ID），不区分大小写
+    Name,
+    /// 按会话数
+    SessionCount,
+}
+
+impl ProjectSort {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "recent" => Ok(Self::Recent),
+            "name" => Ok(Self::Name),
+            "session_count" => Ok(Self::SessionCount),
+            _ => Err(format!("不支持的排序方式: {}（可选 recent / name / session_count）", value)),
+        }
+    }
+}
+
+/// 搜索范围：按消息角色限定关键词匹配
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScope {
+    /// 整个文件（默认，含工具调用等）
+    All,
+    /// 只匹配用户亲自输入的文本（不含工具结果和 CLI 注入的上下文）
+    User,
+    /// 只匹配助手回复的正文（不含思考和工具调用）
+    Assistant,
+}
+
+impl SearchScope {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "all" => Ok(Self::All),
+            "user" => Ok(Self::User),
+            "assistant" => Ok(Self::Assistant),
+            _ => Err(format!("不支持的搜索范围: {}（可选 all / user / assistant）", value)),
+        }
+    }
+
+    /// 消息是否属于该范围
+    pub fn includes(&self, msg: &Message) -> bool {
+        match self {
+            Self::All => true,
+            Self::User => msg.is_real_user && !msg.is_context,
+            Self::Assistant => msg.role == "assistant",
+        }
     }
 }
 
+/// 项目排序：`ascending` 为 false 时降序，相同时按项目 ID 升序保证结果稳定
+pub fn sort_projects(projects: &mut [Project], sort: ProjectSort, ascending: bool) {
+    fn name_key(p: &Project) -> String {
+        p.cwd.as_deref().unwrap_or(&p.id).to_lowercase()
+    }
+
+    projects.sort_by(|a, b| {
+        let ord = match sort {
+            ProjectSort::Recent => a
+                .last_modified
+                .partial_cmp(&b.last_modified)
+                .unwrap_or(std::cmp::Ordering::Equal),
+            ProjectSort::Name => name_key(a).cmp(&name_key(b)),
+            ProjectSort::SessionCount => a.session_count.cmp(&b.session_count),
+        };
+        let ord = if ascending { ord } else { ord.reverse() };
+        ord.then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+/// 排序后取从 `offset` 开始的 `count` 个项目，返回 (当前页, 项目总数)
+pub fn paginate_projects(mut projects: Vec<Project>, offset: usize, count: usize, sort: ProjectSort, ascending: bool) -> (Vec<Project>, usize) {
+    sort_projects(&mut projects, sort, ascending);
+    let total = projects.len();
+    (projects.into_iter().skip(offset).take(count).collect(), total)
+}
+
 /// Provider 注册表 - 管理所有 CLI 提供者
 pub struct ProviderRegistry {
-    providers: Vec<Box<dyn CliHistoryProvider>>,
+    providers: Vec<Arc<dyn CliHistoryProvider>>,
 }
 
 impl ProviderRegistry {
@@ -54,15 +374,24 @@ impl ProviderRegistry {
         Self { providers: Vec::new() }
     }
 
-    pub fn register(&mut self, provider: Box<dyn CliHistoryProvider>) {
-        self.providers.push(provider);
+    /// 注册 Provider，同名 CLI 类型已存在时替换
+    pub fn register(&mut self, provider: Arc<dyn CliHistoryProvider>) {
+        match self.providers.iter_mut().find(|p| p.cli_type() == provider.cli_type()) {
+            Some(existing) => *existing = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    /// 移除 Provider（不存在时忽略）
+    pub fn unregister(&mut self, cli_type: &str) {
+        self.providers.retain(|p| p.cli_type() != cli_type);
     }
 
-    pub fn get(&self, cli_type: &str) -> Option<&dyn CliHistoryProvider> {
+    pub fn get(&self, cli_type: &str) -> Option<Arc<dyn CliHistoryProvider>> {
         self.providers
             .iter()
             .find(|p| p.cli_type() == cli_type)
-            .map(|p| p.as_ref())
+            .cloned()
     }
 
     pub fn list_types(&self) -> Vec<&'static str> {
@@ -0,0 +1,382 @@
+//! Aider 历史记录提供者
+//!
+//! Aider 没有集中的历史目录，而是在每个仓库根目录写入 `.aider.chat.history.md`。
+//! 同一文件中每个 `# aider chat started at ...` 标题开启一次会话，
+//! 会话路径形如 `<历史文件路径>#<会话序号>`，项目 ID 为仓库根目录。
+
+use crate::paths::canonicalize_cwd;
+use crate::timestamp::parse_timestamp;
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::trash::TrashedSession;
+use crate::provider::{sort_by_last_activity, CliHistoryProvider};
+use crate::types::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Aider 聊天历史文件名
+const HISTORY_FILE: &str = ".aider.chat.history.md";
+
+/// 会话开始标题前缀
+const CHAT_START_PREFIX: &str = "# aider chat started at ";
+
+/// 在根目录下查找历史文件的最大深度
+const MAX_SCAN_DEPTH: usize = 4;
+
+/// 扫描时跳过的目录
+const SKIP_DIRS: &[&str] = &[".git", "node_modules", "target", ".venv", "venv"];
+
+pub struct AiderProvider {
+    base_dir: PathBuf,
+    roots: Vec<PathBuf>,
+}
+
+/// 历史文件中的一次会话
+struct AiderChat {
+    started_at: Option<String>,
+    messages: Vec<Message>,
+    byte_len: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockKind {
+    User,
+    Assistant,
+    Output,
+}
+
+impl AiderProvider {
+    /// 使用显式的根目录列表创建（每个根目录可以是仓库本身或包含多个仓库的目录）
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        let base_dir = roots.first().cloned().unwrap_or_default();
+        Self { base_dir, roots }
+    }
+
+    /// Aider 没有集中的历史目录，需通过 set_aider_roots 显式配置
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Option<Self> {
+        None
+    }
+
+    /// 查找所有根目录下的历史文件
+    fn history_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .roots
+            .iter()
+            .flat_map(|root| {
+                WalkDir::new(root)
+                    .max_depth(MAX_SCAN_DEPTH)
+                    .into_iter()
+                    .filter_entry(|e| {
+                        !(e.file_type().is_dir()
+                            && SKIP_DIRS.iter().any(|d| e.file_name() == *d))
+                    })
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file() && e.file_name() == HISTORY_FILE)
+                    .map(|e| e.path().to_path_buf())
+            })
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    fn history_file_for(project_id: &str) -> PathBuf {
+        Path::new(project_id).join(HISTORY_FILE)
+    }
+
+    fn project_id_of(history_file: &Path) -> Option<String> {
+        Some(history_file.parent()?.to_string_lossy().to_string())
+    }
+
+    /// 拆分会话路径为 (历史文件, 会话序号)
+    fn split_session_path(file_path: &str) -> Option<(&str, usize)> {
+        let (path, index) = file_path.rsplit_once('#')?;
+        Some((path, index.parse().ok()?))
+    }
+
+    /// 解析 Markdown 历史：`#### ` 开头为用户输入，`>` 开头为 Aider 输出，其余为助手回复
+    fn parse_history(content: &str) -> Vec<AiderChat> {
+        let mut chats: Vec<AiderChat> = Vec::new();
+        let mut current: Option<AiderChat> = None;
+        let mut chat_start: u64 = 0;
+        let mut block: Option<(BlockKind, u64, Vec<&str>)> = None;
+        let mut offset: u64 = 0;
+
+        fn flush(chat: &mut Option<AiderChat>, block: &mut Option<(BlockKind, u64, Vec<&str>)>) {
+            let (kind, block_offset, lines) = match block.take() {
+                Some(b) => b,
+                None => return,
+            };
+            let text = lines.join("\n").trim().to_string();
+            if text.is_empty() {
+                return;
+            }
+            let (msg_type, role) = match kind {
+                BlockKind::User => ("user", "user"),
+                BlockKind::Assistant => ("assistant", "assistant"),
+                BlockKind::Output => ("aider_output", "system"),
+            };
+            let chat = chat.get_or_insert_with(|| AiderChat {
+                started_at: None,
+                messages: Vec::new(),
+                byte_len: 0,
+            });
+            chat.messages.push(Message {
+                uuid: None,
+                timestamp: None,
+                msg_type: msg_type.to_string(),
+                role: role.to_string(),
+                content_blocks: vec![ContentBlock {
+                    block_type: "text".to_string(),
+                    text: Some(text),
+                    tool_name: None,
+                    tool_input: None,
+                    tool_result: None,
+                    tool_use_id: None,
+                }],
+                is_real_user: kind == BlockKind::User,
+                byte_offset: Some(block_offset),
+                is_context: kind == BlockKind::Output,
+                subagent: None,
+                parent_uuid: None,
+            });
+        }
+
+        for raw in content.split_inclusive('\n') {
+            let line_offset = offset;
+            offset += raw.len() as u64;
+            let line = raw.trim_end_matches(['\r', '\n']);
+
+            if let Some(ts) = line.strip_prefix(CHAT_START_PREFIX) {
+                flush(&mut current, &mut block);
+                if let Some(mut chat) = current.take() {
+                    chat.byte_len = line_offset - chat_start;
+                    chats.push(chat);
+                }
+                chat_start = line_offset;
+                current = Some(AiderChat {
+                    started_at: Some(ts.trim().to_string()),
+                    messages: Vec::new(),
+                    byte_len: 0,
+                });
+                continue;
+            }
+
+            let (kind, text) = if let Some(rest) = line.strip_prefix("####") {
+                (BlockKind::User, rest.strip_prefix(' ').unwrap_or(rest))
+            } else if let Some(rest) = line.strip_prefix('>') {
+                (BlockKind::Output, rest.strip_prefix(' ').unwrap_or(rest))
+            } else if line.trim().is_empty() {
+                // 空行归入当前块
+                if let Some((_, _, lines)) = block.as_mut() {
+                    lines.push("");
+                }
+                continue;
+            } else {
+                (BlockKind::Assistant, line)
+            };
+
+            match block.as_mut() {
+                Some((k, _, lines)) if *k == kind => lines.push(text),
+                _ => {
+                    flush(&mut current, &mut block);
+                    block = Some((kind, line_offset, vec![text]));
+                }
+            }
+        }
+
+        flush(&mut current, &mut block);
+        if let Some(mut chat) = current {
+            chat.byte_len = offset - chat_start;
+            chats.push(chat);
+        }
+        chats
+    }
+
+    fn read_chats(history_file: &Path) -> Vec<AiderChat> {
+        fs::read(history_file)
+            .map(|bytes| Self::parse_history(&String::from_utf8_lossy(&bytes)))
+            .unwrap_or_default()
+    }
+
+    fn chat_info(history_file: &Path, index: usize, chat: &AiderChat) -> SessionInfo {
+        let id = match &chat.started_at {
+            Some(ts) => format!("aider-{}", ts.chars().filter(|c| c.is_ascii_digit()).collect::<String>()),
+            None => format!("aider-{}", index),
+        };
+        let started_at_unix = chat.started_at.as_deref().and_then(parse_timestamp);
+        let (total_chars, word_count) = messages_text_stats(&chat.messages);
+        SessionInfo {
+            id,
+            file_path: format!("{}#{}", history_file.to_string_lossy(), index),
+            cwd: Self::project_id_of(history_file),
+            first_timestamp: chat.started_at.clone(),
+            last_timestamp: chat.started_at.clone(),
+            first_timestamp_unix: started_at_unix,
+            last_timestamp_unix: started_at_unix,
+            message_count: chat.messages.len(),
+            user_turn_count: chat.messages.iter().filter(|m| m.is_real_user).count(),
+            file_size: chat.byte_len,
+            input_tokens: 0,
+            output_tokens: 0,
+            model: Self::chat_model(chat),
+            total_chars,
+            word_count,
+            preview: first_user_preview(&chat.messages),
+        }
+    }
+
+    /// 从 aider 启动输出中取模型名（`Model: xxx with diff edit format` 或 `Main model: ...`）
+    fn chat_model(chat: &AiderChat) -> Option<String> {
+        chat.messages
+            .iter()
+            .filter(|m| m.msg_type == "aider_output")
+            .flat_map(|m| m.content_blocks.iter().filter_map(|b| b.text.as_deref()))
+            .flat_map(|text| text.lines())
+            .filter_map(|line| {
+                let line = line.trim();
+                let rest = line.strip_prefix("Main model: ").or_else(|| line.strip_prefix("Model: "))?;
+                rest.split(" with ").next().map(|m| m.trim().to_string())
+            })
+            .rfind(|m| !m.is_empty())
+    }
+
+    fn chat_contains_keyword(chat: &AiderChat, keyword: &KeywordMatcher) -> bool {
+        !keyword.is_cancelled() && chat.messages.iter().any(|m| keyword.message_matches(m))
+    }
+
+    /// 所有历史文件中包含关键词的会话，返回 (项目 ID, 会话信息)
+    fn matching_sessions(&self, keyword: &KeywordMatcher) -> Vec<(String, SessionInfo)> {
+        self.history_files()
+            .par_iter()
+            .flat_map_iter(|file| {
+                let project_id = Self::project_id_of(file).unwrap_or_default();
+                Self::read_chats(file)
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.messages.iter().any(|m| m.is_real_user))
+                    .filter(|(_, c)| Self::chat_contains_keyword(c, keyword))
+                    .map(|(i, c)| (project_id.clone(), Self::chat_info(file, i, c)))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+impl CliHistoryProvider for AiderProvider {
+    fn cli_type(&self) -> &'static str {
+        "aider"
+    }
+
+    fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    fn list_projects(&self, limit: usize) -> Vec<Project> {
+        let mut projects: Vec<Project> = self
+            .history_files()
+            .par_iter()
+            .filter_map(|file| {
+                let id = Self::project_id_of(file)?;
+                let mtime = fs::metadata(file)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs_f64())
+                    .unwrap_or(0.0);
+                let chats = Self::read_chats(file);
+                Some(Project {
+                    cwd: Some(id.clone()),
+                    id,
+                    last_modified: mtime,
+                    session_count: chats.len(),
+                    last_activity: chats.iter().rev().find_map(|c| c.started_at.clone()),
+                })
+            })
+            .collect();
+
+        projects.sort_by(|a, b| b.last_modified.partial_cmp(&a.last_modified).unwrap_or(std::cmp::Ordering::Equal));
+        if limit > 0 {
+            projects.truncate(limit);
+        }
+        projects
+    }
+
+    fn find_project_by_cwd(&self, cwd: &str) -> Option<Project> {
+        let cwd_normalized = canonicalize_cwd(cwd);
+        self.list_projects(0).into_iter().find(|p| {
+            p.cwd
+                .as_ref()
+                .map(|c| canonicalize_cwd(c) == cwd_normalized)
+                .unwrap_or(false)
+        })
+    }
+
+    /// `name_glob` 匹配会话 ID（如 `"aider-202405*"`）
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .all_session_infos(project_id)
+            .into_iter()
+            .filter(|s| s.user_turn_count >= min_turns.max(1))
+            .filter(|s| name_glob.map(|p| p.matches(&s.id)).unwrap_or(true))
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
+
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo> {
+        let history_file = Self::history_file_for(project_id);
+        let mut sessions: Vec<SessionInfo> = Self::read_chats(&history_file)
+            .iter()
+            .enumerate()
+            .map(|(i, chat)| Self::chat_info(&history_file, i, chat))
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
+
+    fn load_session(&self, file_path: &str) -> Option<Session> {
+        let (history_file, index) = Self::split_session_path(file_path)?;
+        let history_file = Path::new(history_file);
+        let chat = Self::read_chats(history_file).into_iter().nth(index)?;
+        Some(Session {
+            info: Self::chat_info(history_file, index, &chat),
+            messages: chat.messages,
+            context_messages: Vec::new(),
+        })
+    }
+
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .matching_sessions(keyword)
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (project_id, _) in self.matching_sessions(keyword) {
+            *counts.entry(project_id).or_insert(0) += 1;
+        }
+
+        let mut facets: Vec<_> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    fn move_to_trash(&self, _file_path: &str) -> Result<TrashedSession, HistoryError> {
+        Err(HistoryError::Unsupported("Aider 历史文件包含多个会话，不支持单独删除".to_string()))
+    }
+}
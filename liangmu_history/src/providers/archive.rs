@@ -0,0 +1,292 @@
+//! Zip 归档历史记录提供者（只读）
+//!
+//! 直接读取归档内的 `.jsonl` 条目，无需解压。文件名以 `rollout-` 开头的条目
+//! 按 Codex 格式解析，其余按 Claude 格式解析；条目所在目录即项目 ID。
+//! 会话路径形如 `<zip 路径>!<条目名>`。
+
+use crate::paths::canonicalize_cwd;
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::trash::TrashedSession;
+use crate::provider::{sort_by_last_activity, CliHistoryProvider};
+use crate::providers::{ClaudeProvider, CodexProvider};
+use crate::types::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// 会话路径中 zip 路径与条目名的分隔符
+const ENTRY_SEPARATOR: char = '!';
+
+/// 根目录下条目的项目 ID
+const ROOT_PROJECT_ID: &str = ".";
+
+pub struct ArchiveProvider {
+    zip_path: PathBuf,
+}
+
+/// 归档中的会话条目
+struct ArchiveEntry {
+    name: String,
+    project_id: String,
+    size: u64,
+    mtime: f64,
+}
+
+impl ArchiveEntry {
+    fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    fn is_codex(&self) -> bool {
+        self.file_name().starts_with("rollout-")
+    }
+}
+
+impl ArchiveProvider {
+    /// 打开归档（只校验能否读取目录，不解析条目）
+    pub fn open(zip_path: PathBuf) -> Result<Self, HistoryError> {
+        let file = File::open(&zip_path)?;
+        ZipArchive::new(file).map_err(|e| HistoryError::Corrupt(format!("无法读取归档: {}", e)))?;
+        Ok(Self { zip_path })
+    }
+
+    fn archive(&self) -> Option<ZipArchive<File>> {
+        let file = File::open(&self.zip_path).ok()?;
+        ZipArchive::new(file).ok()
+    }
+
+    /// 列出所有会话条目（按修改时间倒序）
+    fn entries(&self, archive: &mut ZipArchive<File>) -> Vec<ArchiveEntry> {
+        let mut entries: Vec<ArchiveEntry> = (0..archive.len())
+            .filter_map(|i| {
+                let file = archive.by_index_raw(i).ok()?;
+                if file.is_dir() || !file.name().ends_with(".jsonl") {
+                    return None;
+                }
+                let name = file.name().to_string();
+                let project_id = match name.rsplit_once('/') {
+                    Some((dir, _)) => dir.to_string(),
+                    None => ROOT_PROJECT_ID.to_string(),
+                };
+                let mtime = file.last_modified().and_then(zip_time_to_unix).unwrap_or(0.0);
+                Some(ArchiveEntry {
+                    name,
+                    project_id,
+                    size: file.size(),
+                    mtime,
+                })
+            })
+            .collect();
+
+        entries.sort_by(|a, b| b.mtime.partial_cmp(&a.mtime).unwrap_or(std::cmp::Ordering::Equal));
+        entries
+    }
+
+    fn session_path(&self, entry_name: &str) -> String {
+        format!("{}{}{}", self.zip_path.to_string_lossy(), ENTRY_SEPARATOR, entry_name)
+    }
+
+    /// 从会话路径中取出条目名（路径不属于本归档时返回 None）
+    fn entry_name<'a>(&self, file_path: &'a str) -> Option<&'a str> {
+        let zip_path = self.zip_path.to_string_lossy();
+        file_path
+            .strip_prefix(zip_path.as_ref())?
+            .strip_prefix(ENTRY_SEPARATOR)
+    }
+
+    fn parse_entry_info(&self, archive: &mut ZipArchive<File>, entry: &ArchiveEntry, filtered: bool) -> Option<SessionInfo> {
+        let file = archive.by_name(&entry.name).ok()?;
+        let path = self.session_path(&entry.name);
+        let path = Path::new(&path);
+        let reader = BufReader::new(file);
+        match (entry.is_codex(), filtered) {
+            (true, true) => CodexProvider::parse_session_info_reader(reader, path, entry.size),
+            (true, false) => CodexProvider::scan_session_info_reader(reader, path, entry.size),
+            (false, true) => ClaudeProvider::parse_session_info_reader(reader, path, entry.size),
+            (false, false) => ClaudeProvider::scan_session_info_reader(reader, path, entry.size),
+        }
+    }
+
+    /// 解析项目下的会话条目；`filtered` 为 false 时不做轮次和时间戳过滤
+    fn project_sessions(&self, project_id: &str, name_glob: Option<&glob::Pattern>, filtered: bool) -> Vec<SessionInfo> {
+        let mut archive = match self.archive() {
+            Some(a) => a,
+            None => return Vec::new(),
+        };
+
+        let entries: Vec<ArchiveEntry> = self
+            .entries(&mut archive)
+            .into_iter()
+            .filter(|e| e.project_id == project_id)
+            // 与 Claude 一致：过滤 agent- 开头的子任务文件
+            .filter(|e| e.is_codex() || !e.file_name().starts_with("agent-"))
+            .filter(|e| name_glob.map(|p| p.matches(e.file_name())).unwrap_or(true))
+            .collect();
+
+        let mut sessions: Vec<SessionInfo> = entries
+            .iter()
+            .filter_map(|e| self.parse_entry_info(&mut archive, e, filtered))
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
+
+    fn entry_contains_keyword(&self, archive: &mut ZipArchive<File>, entry: &ArchiveEntry, keyword: &KeywordMatcher) -> bool {
+        if keyword.is_cancelled() {
+            return false;
+        }
+        let file = match archive.by_name(&entry.name) {
+            Ok(f) => f,
+            Err(_) => return false,
+        };
+        let parse = if entry.is_codex() {
+            CodexProvider::parse_codex_message
+        } else {
+            ClaudeProvider::message_parser(Path::new(&self.session_path(&entry.name)))
+        };
+        crate::jsonl::JsonlLines::new(BufReader::new(file))
+            .any(|(_, line)| keyword.line_matches(&line, parse))
+    }
+
+    /// 解析包含关键词的会话条目
+    fn matching_sessions(&self, keyword: &KeywordMatcher) -> Vec<(String, SessionInfo)> {
+        let mut archive = match self.archive() {
+            Some(a) => a,
+            None => return Vec::new(),
+        };
+
+        let mut matches = Vec::new();
+        for entry in self.entries(&mut archive) {
+            if !self.entry_contains_keyword(&mut archive, &entry, keyword) {
+                continue;
+            }
+            if let Some(info) = self.parse_entry_info(&mut archive, &entry, true) {
+                matches.push((entry.project_id, info));
+            }
+        }
+        matches
+    }
+}
+
+/// zip 条目时间（本地时间，无时区）按 UTC 换算为 Unix 时间戳
+fn zip_time_to_unix(dt: zip::DateTime) -> Option<f64> {
+    let naive = chrono::NaiveDate::from_ymd_opt(dt.year() as i32, dt.month() as u32, dt.day() as u32)?
+        .and_hms_opt(dt.hour() as u32, dt.minute() as u32, dt.second() as u32)?;
+    Some(naive.and_utc().timestamp() as f64)
+}
+
+impl CliHistoryProvider for ArchiveProvider {
+    fn cli_type(&self) -> &'static str {
+        "archive"
+    }
+
+    fn base_dir(&self) -> &Path {
+        &self.zip_path
+    }
+
+    fn list_projects(&self, limit: usize) -> Vec<Project> {
+        let mut archive = match self.archive() {
+            Some(a) => a,
+            None => return Vec::new(),
+        };
+
+        // 条目已按时间倒序，分组后组内第一个即最新会话
+        let mut order: Vec<String> = Vec::new();
+        let mut groups: HashMap<String, Vec<ArchiveEntry>> = HashMap::new();
+        for entry in self.entries(&mut archive) {
+            if !groups.contains_key(&entry.project_id) {
+                order.push(entry.project_id.clone());
+            }
+            groups.entry(entry.project_id.clone()).or_default().push(entry);
+        }
+
+        if limit > 0 && order.len() > limit {
+            order.truncate(limit);
+        }
+
+        order
+            .into_iter()
+            .filter_map(|id| {
+                let entries = groups.remove(&id)?;
+                // 取第一个能解析的会话作为 cwd 与最后活动时间来源
+                let newest = entries
+                    .iter()
+                    .find_map(|e| self.parse_entry_info(&mut archive, e, true));
+                Some(Project {
+                    cwd: newest.as_ref().and_then(|s| s.cwd.clone()),
+                    last_modified: entries[0].mtime,
+                    session_count: entries.len(),
+                    last_activity: newest.and_then(|s| s.last_timestamp),
+                    id,
+                })
+            })
+            .collect()
+    }
+
+    fn find_project_by_cwd(&self, cwd: &str) -> Option<Project> {
+        let cwd_normalized = canonicalize_cwd(cwd);
+        self.list_projects(0).into_iter().find(|p| {
+            p.cwd
+                .as_ref()
+                .map(|c| canonicalize_cwd(c) == cwd_normalized)
+                .unwrap_or(false)
+        })
+    }
+
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo> {
+        let mut sessions = self.project_sessions(project_id, name_glob, true);
+        sessions.retain(|s| s.user_turn_count >= min_turns);
+        sessions
+    }
+
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo> {
+        self.project_sessions(project_id, None, false)
+    }
+
+    fn load_session(&self, file_path: &str) -> Option<Session> {
+        let entry_name = self.entry_name(file_path)?;
+        let mut archive = self.archive()?;
+        let file = archive.by_name(entry_name).ok()?;
+        let size = file.size();
+        let reader = BufReader::new(file);
+        let path = Path::new(file_path);
+
+        let is_codex = entry_name.rsplit('/').next().unwrap_or(entry_name).starts_with("rollout-");
+        if is_codex {
+            CodexProvider::parse_session_reader(reader, path, size)
+        } else {
+            ClaudeProvider::parse_session_reader(reader, path, size)
+        }
+    }
+
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .matching_sessions(keyword)
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (project_id, _) in self.matching_sessions(keyword) {
+            *counts.entry(project_id).or_insert(0) += 1;
+        }
+
+        let mut facets: Vec<_> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    fn move_to_trash(&self, _file_path: &str) -> Result<TrashedSession, HistoryError> {
+        Err(HistoryError::Unsupported("归档为只读，不支持删除".to_string()))
+    }
+}
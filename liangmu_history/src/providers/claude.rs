@@ -1,26 +1,130 @@
 //! Claude Code 历史记录提供者
 
-use crate::provider::CliHistoryProvider;
+use crate::jsonl::{find_cwd, is_session_file, open_session, read_last_timestamp, read_messages_since, session_stem, stream_messages, JsonlLines};
+use crate::paths::canonicalize_cwd;
+use crate::timestamp::parse_timestamp;
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::provider::{paginate_projects, sort_by_last_activity, CliHistoryProvider, ProjectSort};
+use crate::trash::{self, TrashedSession};
 use crate::types::*;
 use rayon::prelude::*;
+use parking_lot::Mutex;
 use serde_json::Value;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::SystemTime;
 
+/// Claude 注入上下文消息的文本前缀
+const CONTEXT_MARKERS: &[&str] = &[
+    "<system-reminder>",
+    "Caveat: The messages below were generated by the user while running local commands",
+    "<command-name>",
+    "<command-message>",
+    "<local-command-stdout>",
+    "<local-command-stderr>",
+];
+
+/// 主会话文件中侧链消息的 `subagent` 标记
+const SIDECHAIN_NAME: &str = "sidechain";
+
+/// 按文件大小降序并行处理：各线程从同一队列依次领取下一个文件，大文件最先开始，
+/// 避免少数大文件被分到最后才开始、拖长整体耗时；`visit` 返回 false 时所有线程尽快停止
+fn for_each_largest_first(files: Vec<PathBuf>, visit: impl Fn(&Path) -> bool + Sync) {
+    let files = sort_largest_first(files);
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+
+    let workers = rayon::current_num_threads().min(files.len());
+    (0..workers).into_par_iter().for_each(|_| {
+        while !stopped.load(Ordering::Relaxed) {
+            let file = match files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                Some(f) => f,
+                None => break,
+            };
+            if !visit(file) {
+                stopped.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+/// Claude 记录的 cwd 字段
+fn record_cwd(data: &Value) -> Option<String> {
+    data.get("cwd")?.as_str().map(String::from)
+}
+
+/// 按文件大小降序排列（无法读取大小的视为 0）
+fn sort_largest_first(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut sized: Vec<(u64, PathBuf)> = files
+        .into_iter()
+        .map(|f| (fs::metadata(&f).map(|m| m.len()).unwrap_or(0), f))
+        .collect();
+    sized.sort_by_key(|s| std::cmp::Reverse(s.0));
+    sized.into_iter().map(|(_, f)| f).collect()
+}
+
 pub struct ClaudeProvider {
     base_dir: PathBuf,
 }
 
+/// token 用量累加器
+/// 同一个 API 响应会被拆成多行记录且重复携带 usage，按 message.id 去重取最后一次
+#[derive(Default)]
+struct UsageTally {
+    by_id: HashMap<String, (i64, i64)>,
+    anonymous: (i64, i64),
+}
+
+impl UsageTally {
+    fn add(&mut self, data: &Value) {
+        let message = match data.get("message") {
+            Some(m) => m,
+            None => return,
+        };
+        let usage = match message.get("usage") {
+            Some(u) => u,
+            None => return,
+        };
+        let get = |k: &str| usage.get(k).and_then(|v| v.as_i64()).unwrap_or(0);
+        let input = get("input_tokens")
+            + get("cache_creation_input_tokens")
+            + get("cache_read_input_tokens");
+        let output = get("output_tokens");
+
+        match message.get("id").and_then(|v| v.as_str()) {
+            Some(id) => {
+                self.by_id.insert(id.to_string(), (input, output));
+            }
+            None => {
+                self.anonymous.0 += input;
+                self.anonymous.1 += output;
+            }
+        }
+    }
+
+    fn totals(&self) -> (i64, i64) {
+        self.by_id
+            .values()
+            .fold(self.anonymous, |acc, (i, o)| (acc.0 + i, acc.1 + o))
+    }
+}
+
 impl ClaudeProvider {
     pub fn new(base_dir: PathBuf) -> Self {
         Self { base_dir }
     }
 
+    /// 默认目录为 `~/.claude`，可用环境变量 `CLAUDE_HISTORY_DIR` 覆盖
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Option<Self> {
-        let home = dirs::home_dir()?;
-        let claude_dir = home.join(".claude");
+        let claude_dir = match std::env::var_os("CLAUDE_HISTORY_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::home_dir()?.join(".claude"),
+        };
         if claude_dir.exists() {
             Some(Self::new(claude_dir))
         } else {
@@ -42,26 +146,47 @@ impl ClaudeProvider {
                     text: Some(s.clone()),
                     tool_name: None,
                     tool_input: None,
+                    tool_result: None,
+                    tool_use_id: None,
                 });
             }
             Value::Array(arr) => {
                 for item in arr {
                     if let Value::Object(obj) = item {
-                        let block_type = obj
-                            .get("type")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("unknown")
-                            .to_string();
-
-                        let text = obj.get("text").and_then(|v| v.as_str()).map(String::from);
+                        let raw_type = obj.get("type").and_then(|v| v.as_str()).unwrap_or("unknown");
+                        // redacted_thinking 只有加密内容，同样归为思考过程
+                        let block_type = match raw_type {
+                            "redacted_thinking" => "thinking",
+                            other => other,
+                        }
+                        .to_string();
+
+                        let text = match raw_type {
+                            "thinking" => obj.get("thinking"),
+                            _ => obj.get("text"),
+                        }
+                        .and_then(|v| v.as_str())
+                        .map(String::from);
                         let tool_name = obj.get("name").and_then(|v| v.as_str()).map(String::from);
                         let tool_input = obj.get("input").map(|v| v.to_string());
+                        // tool_use 块的 id 与 tool_result 块的 tool_use_id 相互对应
+                        let tool_use_id = obj
+                            .get(if block_type == "tool_result" { "tool_use_id" } else { "id" })
+                            .and_then(|v| v.as_str())
+                            .map(String::from);
+                        let tool_result = if block_type == "tool_result" {
+                            obj.get("content").and_then(Self::tool_result_text)
+                        } else {
+                            None
+                        };
 
                         blocks.push(ContentBlock {
                             block_type,
                             text,
                             tool_name,
                             tool_input,
+                            tool_result,
+                            tool_use_id,
                         });
                     }
                 }
@@ -71,6 +196,21 @@ impl ClaudeProvider {
         blocks
     }
 
+    /// tool_result 的 content 可以是字符串，或 `{"type": "text", "text": ...}` 组成的数组
+    fn tool_result_text(content: &Value) -> Option<String> {
+        match content {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(parts) => {
+                let texts: Vec<&str> = parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(|v| v.as_str()))
+                    .collect();
+                (!texts.is_empty()).then(|| texts.join("\n"))
+            }
+            _ => None,
+        }
+    }
+
     /// 解析单条消息
     fn parse_message(data: &Value) -> Option<Message> {
         let msg_type = data.get("type")?.as_str()?;
@@ -86,9 +226,21 @@ impl ClaudeProvider {
         let content = message_data.get("content").unwrap_or(&Value::Null);
         let content_blocks = Self::parse_content_blocks(content);
 
-        // 判断是否为真实用户输入（没有 tool_result）
+        // 判断是否为 Claude 注入的上下文
+        let is_meta = Self::is_meta(data);
+
+        // 判断是否为真实用户输入（没有 tool_result，且不是元消息）
         let has_tool_result = content_blocks.iter().any(|b| b.block_type == "tool_result");
-        let is_real_user = msg_type == "user" && !has_tool_result;
+        let is_real_user = msg_type == "user" && !has_tool_result && !is_meta;
+
+        let is_context = msg_type == "user"
+            && (is_meta
+                || content_blocks.iter().any(|b| {
+                    b.text
+                        .as_deref()
+                        .map(|t| CONTEXT_MARKERS.iter().any(|m| t.trim_start().starts_with(m)))
+                        .unwrap_or(false)
+                }));
 
         Some(Message {
             uuid: data.get("uuid").and_then(|v| v.as_str()).map(String::from),
@@ -100,25 +252,84 @@ impl ClaudeProvider {
             role: role.to_string(),
             content_blocks,
             is_real_user,
+            byte_offset: None,
+            is_context,
+            subagent: None,
+            parent_uuid: data.get("parentUuid").and_then(|v| v.as_str()).map(String::from),
         })
     }
 
+    /// 会话摘要记录（`type: "summary"`），不属于对话
+    fn is_summary(data: &Value) -> bool {
+        data.get("type").and_then(|v| v.as_str()) == Some("summary")
+    }
+
+    /// CLI 注入的元消息（`isMeta`），作为上下文保留但不计入消息数和轮次
+    fn is_meta(data: &Value) -> bool {
+        data.get("isMeta").and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+
+    /// 侧链记录（`isSidechain`，子任务的对话）
+    fn is_sidechain(data: &Value) -> bool {
+        data.get("isSidechain").and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+
+    /// 子任务记录文件（agent-*.jsonl）：其中的记录都是侧链，需要保留
+    fn is_agent_file(path: &Path) -> bool {
+        session_stem(path).is_some_and(|s| s.starts_with("agent-"))
+    }
+
+    /// 解析主会话中的单条消息，跳过侧链记录
+    fn parse_conversation_message(data: &Value) -> Option<Message> {
+        if Self::is_sidechain(data) {
+            return None;
+        }
+        Self::parse_message(data)
+    }
+
+    /// 按文件类型选择消息解析函数：子任务文件保留侧链，主会话跳过
+    pub(crate) fn message_parser(path: &Path) -> fn(&Value) -> Option<Message> {
+        if Self::is_agent_file(path) {
+            Self::parse_message
+        } else {
+            Self::parse_conversation_message
+        }
+    }
+
+    /// 记录中的模型名（assistant 消息的 message.model，跳过 CLI 生成的 `<synthetic>` 消息）
+    fn record_model(data: &Value) -> Option<&str> {
+        data.get("message")?
+            .get("model")?
+            .as_str()
+            .filter(|m| !m.is_empty() && *m != "<synthetic>")
+    }
+
     /// 解析会话文件
     fn parse_session_file(&self, file_path: &Path) -> Option<Session> {
-        let file = File::open(file_path).ok()?;
-        let reader = BufReader::new(file);
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::parse_session_reader(reader, file_path, file_size)
+    }
+
+    /// 从任意读取器解析会话（`file_path` 仅用于生成 id 和记录来源）
+    pub(crate) fn parse_session_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<Session> {
 
         let mut messages = Vec::new();
         let mut first_ts: Option<String> = None;
         let mut last_ts: Option<String> = None;
         let mut cwd: Option<String> = None;
-
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) if !l.trim().is_empty() => l,
-                _ => continue,
-            };
-
+        let mut model: Option<String> = None;
+        let mut usage = UsageTally::default();
+        // 未解析为消息的记录（system、进度、侧链等）的 uuid -> parentUuid，用于接续消息间的父子链
+        let mut skipped_links: HashMap<String, Option<String>> = HashMap::new();
+        // 元消息的字节偏移：保留在消息列表中，但不计入消息数和正文统计
+        let mut meta_offsets: HashSet<u64> = HashSet::new();
+        let parse = Self::message_parser(file_path);
+
+        for (offset, line) in JsonlLines::new(reader) {
             let data: Value = match serde_json::from_str(&line) {
                 Ok(v) => v,
                 Err(_) => continue,
@@ -140,8 +351,20 @@ impl ClaudeProvider {
                 last_ts = Some(t.clone());
             }
 
-            if let Some(msg) = Self::parse_message(&data) {
+            usage.add(&data);
+            if let Some(m) = Self::record_model(&data) {
+                model = Some(m.to_string());
+            }
+
+            if let Some(mut msg) = parse(&data) {
+                if Self::is_meta(&data) {
+                    meta_offsets.insert(offset);
+                }
+                msg.byte_offset = Some(offset);
                 messages.push(msg);
+            } else if let Some(uuid) = data.get("uuid").and_then(|v| v.as_str()) {
+                let parent = data.get("parentUuid").and_then(|v| v.as_str()).map(String::from);
+                skipped_links.insert(uuid.to_string(), parent);
             }
         }
 
@@ -149,52 +372,114 @@ impl ClaudeProvider {
             return None;
         }
 
+        // parentUuid 指向被跳过的记录时，沿链上溯到最近的消息
+        if !skipped_links.is_empty() {
+            for msg in messages.iter_mut() {
+                let mut hops = 0;
+                while let Some(next) = msg.parent_uuid.as_ref().and_then(|p| skipped_links.get(p)) {
+                    msg.parent_uuid = next.clone();
+                    hops += 1;
+                    if hops > skipped_links.len() {
+                        break;
+                    }
+                }
+            }
+        }
+
         let user_turn_count = messages.iter().filter(|m| m.is_real_user).count();
-        let file_size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        let (input_tokens, output_tokens) = usage.totals();
+
+        let counted: Vec<&Message> = messages
+            .iter()
+            .filter(|m| m.byte_offset.is_none_or(|o| !meta_offsets.contains(&o)))
+            .collect();
+        let message_count = counted.len();
+        let (total_chars, word_count) = messages_text_stats(counted);
 
         Some(Session {
             info: SessionInfo {
-                id: file_path
-                    .file_stem()
-                    .and_then(|s| s.to_str())
+                id: session_stem(file_path)
                     .unwrap_or("unknown")
                     .to_string(),
                 file_path: file_path.to_string_lossy().to_string(),
                 cwd,
+                first_timestamp_unix: first_ts.as_deref().and_then(parse_timestamp),
+                last_timestamp_unix: last_ts.as_deref().and_then(parse_timestamp),
                 first_timestamp: first_ts,
                 last_timestamp: last_ts,
-                message_count: messages.len(),
+                message_count,
                 user_turn_count,
                 file_size,
+                input_tokens,
+                output_tokens,
+                model,
+                total_chars,
+                word_count,
+                preview: first_user_preview(&messages),
             },
             messages,
+            context_messages: Vec::new(),
         })
     }
 
     /// 快速解析会话信息（不加载全部消息）
     /// 复刻 DEV 版的完整过滤规则
     fn parse_session_info(&self, file_path: &Path) -> Option<SessionInfo> {
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::parse_session_info_reader(reader, file_path, file_size)
+    }
+
+    /// 扫描会话信息（不做时间戳与轮次过滤）
+    fn scan_session_info(&self, file_path: &Path) -> Option<SessionInfo> {
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::scan_session_info_reader(reader, file_path, file_size)
+    }
+
+    /// 从任意读取器快速解析会话信息，过滤规则同 parse_session_info
+    pub(crate) fn parse_session_info_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<SessionInfo> {
+        let info = Self::scan_session_info_reader(reader, file_path, file_size)?;
+
+        // [过滤4] 无有效时间戳过滤
+        if info.first_timestamp.is_none() && info.last_timestamp.is_none() {
+            return None;
+        }
+
+        // [过滤5] 用户消息为0过滤
+        if info.user_turn_count == 0 {
+            return None;
+        }
+
+        Some(info)
+    }
+
+    /// 扫描会话信息，只跳过空文件和无消息的文件，轮次数如实记录
+    pub(crate) fn scan_session_info_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<SessionInfo> {
         // [过滤1] 空文件过滤
-        let file_size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
         if file_size == 0 {
             return None;
         }
 
-        let file = File::open(file_path).ok()?;
-        let reader = BufReader::new(file);
-
         let mut msg_count = 0;
         let mut user_turn_count = 0;
+        let mut total_chars = 0;
+        let mut word_count = 0;
+        let mut preview: Option<String> = None;
         let mut first_ts: Option<String> = None;
         let mut last_ts: Option<String> = None;
         let mut cwd: Option<String> = None;
+        let mut model: Option<String> = None;
+        let mut usage = UsageTally::default();
+        let include_sidechain = Self::is_agent_file(file_path);
 
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) if !l.trim().is_empty() => l,
-                _ => continue,
-            };
-
+        for (_, line) in JsonlLines::new(reader) {
             // [过滤2] 系统中断消息过滤
             if line.contains("[Request interrupted by user") {
                 continue;
@@ -220,9 +505,27 @@ impl ClaudeProvider {
                 last_ts = Some(t.clone());
             }
 
+            usage.add(&data);
+            if let Some(m) = Self::record_model(&data) {
+                model = Some(m.to_string());
+            }
+
+            // 摘要、元消息和侧链（子任务文件除外）不计入消息数与轮次
+            if Self::is_summary(&data) || Self::is_meta(&data) || (Self::is_sidechain(&data) && !include_sidechain) {
+                continue;
+            }
+
             let msg_type = data.get("type").and_then(|v| v.as_str());
             if msg_type == Some("user") || msg_type == Some("assistant") {
                 msg_count += 1;
+                if let Some(msg) = Self::parse_message(&data) {
+                    let (chars, words) = msg.text_stats();
+                    total_chars += chars;
+                    word_count += words;
+                    if preview.is_none() {
+                        preview = msg.user_preview();
+                    }
+                }
                 if msg_type == Some("user") {
                     // 检查是否为真实用户输入（伪用户消息过滤）
                     let content = data.get("message").and_then(|m| m.get("content"));
@@ -246,29 +549,27 @@ impl ClaudeProvider {
             return None;
         }
 
-        // [过滤4] 无有效时间戳过滤
-        if first_ts.is_none() && last_ts.is_none() {
-            return None;
-        }
-
-        // [过滤5] 用户消息为0过滤
-        if user_turn_count == 0 {
-            return None;
-        }
+        let (input_tokens, output_tokens) = usage.totals();
 
         Some(SessionInfo {
-            id: file_path
-                .file_stem()
-                .and_then(|s| s.to_str())
+            id: session_stem(file_path)
                 .unwrap_or("unknown")
                 .to_string(),
             file_path: file_path.to_string_lossy().to_string(),
             cwd,
+            first_timestamp_unix: first_ts.as_deref().and_then(parse_timestamp),
+            last_timestamp_unix: last_ts.as_deref().and_then(parse_timestamp),
             first_timestamp: first_ts,
             last_timestamp: last_ts,
             message_count: msg_count,
             user_turn_count,
             file_size,
+            input_tokens,
+            output_tokens,
+            model,
+            total_chars,
+            word_count,
+            preview,
         })
     }
 }
@@ -283,128 +584,73 @@ impl CliHistoryProvider for ClaudeProvider {
     }
 
     fn list_projects(&self, limit: usize) -> Vec<Project> {
-        let projects_dir = self.projects_dir();
-        if !projects_dir.exists() {
-            return Vec::new();
-        }
-
-        let mut dirs: Vec<_> = fs::read_dir(&projects_dir)
-            .ok()
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
-            .collect();
-
-        // 按修改时间排序
-        dirs.sort_by(|a, b| {
-            let a_time = a
-                .metadata()
-                .and_then(|m| m.modified())
-                .unwrap_or(SystemTime::UNIX_EPOCH);
-            let b_time = b
-                .metadata()
-                .and_then(|m| m.modified())
-                .unwrap_or(SystemTime::UNIX_EPOCH);
-            b_time.cmp(&a_time)
-        });
-
+        let mut dirs = self.project_dirs();
         if limit > 0 && dirs.len() > limit {
             dirs.truncate(limit);
         }
 
         // 并行获取每个项目的 cwd
-        let mut projects: Vec<Project> = dirs
-            .par_iter()
-            .filter_map(|entry| {
-                let path = entry.path();
-                let id = path.file_name()?.to_str()?.to_string();
-                let mtime = entry
-                    .metadata()
-                    .and_then(|m| m.modified())
-                    .ok()
-                    .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
-                    .map(|d| d.as_secs_f64())
-                    .unwrap_or(0.0);
-
-                // 获取 cwd
-                let cwd = self.get_project_cwd(&path);
-                let session_count = fs::read_dir(&path)
-                    .ok()
-                    .map(|rd| {
-                        rd.filter(|e| {
-                            e.as_ref()
-                                .map(|e| {
-                                    e.path()
-                                        .extension()
-                                        .map(|ext| ext == "jsonl")
-                                        .unwrap_or(false)
-                                })
-                                .unwrap_or(false)
-                        })
-                        .count()
-                    })
-                    .unwrap_or(0);
-
-                Some(Project {
-                    id,
-                    cwd,
-                    last_modified: mtime,
-                    session_count,
-                    last_activity: None,
-                })
-            })
-            .collect();
+        let mut projects: Vec<Project> = dirs.par_iter().filter_map(|(entry, mtime)| self.build_project(entry, *mtime)).collect();
 
         // 并行处理后重新按修改时间排序（降序）
         projects.sort_by(|a, b| b.last_modified.partial_cmp(&a.last_modified).unwrap_or(std::cmp::Ordering::Equal));
         projects
     }
 
+    fn list_projects_paged(&self, offset: usize, count: usize, sort: ProjectSort, ascending: bool) -> (Vec<Project>, usize) {
+        if sort != ProjectSort::Recent {
+            return paginate_projects(self.list_projects(0), offset, count, sort, ascending);
+        }
+
+        // 按修改时间排序只需目录元数据：先对目录分页，只解析当前页的项目
+        let mut dirs = self.project_dirs();
+        if ascending {
+            dirs.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.file_name().cmp(&b.0.file_name())));
+        }
+        let total = dirs.len();
+        let page: Vec<_> = dirs.into_iter().skip(offset).take(count).collect();
+        let projects = page.par_iter().filter_map(|(entry, mtime)| self.build_project(entry, *mtime)).collect();
+        (projects, total)
+    }
+
     fn find_project_by_cwd(&self, cwd: &str) -> Option<Project> {
-        let cwd_normalized = cwd.replace('\\', "/").to_lowercase();
+        let cwd_normalized = canonicalize_cwd(cwd);
         self.list_projects(0).into_iter().find(|p| {
             p.cwd
                 .as_ref()
-                .map(|c| c.replace('\\', "/").to_lowercase() == cwd_normalized)
+                .map(|c| canonicalize_cwd(c) == cwd_normalized)
                 .unwrap_or(false)
         })
     }
 
-    fn load_project(&self, project_id: &str) -> Vec<SessionInfo> {
-        let project_dir = self.projects_dir().join(project_id);
-        if !project_dir.exists() {
-            return Vec::new();
-        }
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo> {
+        let files = self.project_session_files(project_id, name_glob);
 
-        let files: Vec<_> = fs::read_dir(&project_dir)
-            .ok()
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.path()
-                    .extension()
-                    .map(|ext| ext == "jsonl")
-                    .unwrap_or(false)
-            })
-            .filter(|e| {
-                // 复刻 DEV 版：过滤 agent- 开头的子任务文件
-                !e.file_name().to_string_lossy().starts_with("agent-")
-            })
-            .map(|e| e.path())
-            .collect();
-
-        // 并行解析，过滤掉 0 轮的无效会话
+        // 并行解析，过滤掉轮次不足的会话
         let mut sessions: Vec<SessionInfo> = files
             .par_iter()
             .filter_map(|f| self.parse_session_info(f))
-            .filter(|s| s.user_turn_count >= 1) // 至少有 1 轮对话
+            .filter(|s| s.user_turn_count >= min_turns)
             .collect();
 
         // 按最后时间戳排序
-        sessions.sort_by(|a, b| b.last_timestamp.as_ref().cmp(&a.last_timestamp.as_ref()));
+        sort_by_last_activity(&mut sessions);
+
+        sessions
+    }
+
+    fn project_files(&self, project_id: &str) -> Vec<PathBuf> {
+        self.project_session_files(project_id, None)
+    }
+
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, None)
+            .par_iter()
+            .filter_map(|f| self.scan_session_info(f))
+            .collect();
 
+        sort_by_last_activity(&mut sessions);
         sessions
     }
 
@@ -412,135 +658,124 @@ impl CliHistoryProvider for ClaudeProvider {
         self.parse_session_file(Path::new(file_path))
     }
 
-    fn load_session_paginated(
-        &self,
-        file_path: &str,
-        first_turns: usize,
-        last_turns: usize,
-    ) -> Option<PaginatedMessages> {
-        let session = self.load_session(file_path)?;
-        let messages = session.messages;
-
-        // 按轮次分组
-        let mut rounds: Vec<Vec<Message>> = Vec::new();
-        let mut current_round: Vec<Message> = Vec::new();
+    fn load_subagent_messages(&self, file_path: &str) -> Vec<Message> {
+        let path = Path::new(file_path);
+        let session_id = match session_stem(path) {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
 
-        for msg in messages {
-            if msg.is_real_user {
-                if !current_round.is_empty() {
-                    rounds.push(current_round);
-                }
-                current_round = vec![msg];
-            } else {
-                current_round.push(msg);
-            }
-        }
-        if !current_round.is_empty() {
-            rounds.push(current_round);
+        // 主会话文件中的侧链记录（加载会话时被跳过）按子任务消息返回
+        let mut messages: Vec<Message> = Self::sidechain_messages(path)
+            .into_iter()
+            .map(|mut m| {
+                m.is_real_user = false;
+                m.subagent = Some(SIDECHAIN_NAME.to_string());
+                m
+            })
+            .collect();
+        for agent_file in Self::subagent_files(path, session_id) {
+            let name = session_stem(&agent_file).unwrap_or("agent").to_string();
+            let session = match self.parse_session_file(&agent_file) {
+                Some(s) => s,
+                None => continue,
+            };
+            messages.extend(session.messages.into_iter().map(|mut m| {
+                // 子任务的 "用户" 消息由主代理发出，不算作真实用户轮次
+                m.is_real_user = false;
+                m.subagent = Some(name.clone());
+                m
+            }));
         }
+        messages
+    }
 
-        let total_turns = rounds.len();
-        let total_messages: usize = rounds.iter().map(|r| r.len()).sum();
+    fn load_messages_since(&self, file_path: &str, byte_offset: u64) -> Option<SessionTail> {
+        let path = Path::new(file_path);
+        read_messages_since(path, byte_offset, Self::message_parser(path))
+    }
 
-        if first_turns + last_turns >= total_turns {
-            // 全部显示
-            let all: Vec<Message> = rounds.into_iter().flatten().collect();
-            return Some(PaginatedMessages {
-                first: all,
-                last: Vec::new(),
-                has_middle: false,
-                total_turns,
-                total_messages,
-            });
+    fn session_header(&self, file_path: &str) -> Option<(String, Option<String>)> {
+        let path = Path::new(file_path);
+        if !path.is_file() {
+            return None;
         }
+        Some((session_stem(path)?.to_string(), find_cwd(path, record_cwd)))
+    }
 
-        let first: Vec<Message> = rounds[..first_turns].iter().flatten().cloned().collect();
-        let last: Vec<Message> = rounds[total_turns - last_turns..]
-            .iter()
-            .flatten()
-            .cloned()
-            .collect();
+    fn stream_messages(&self, file_path: &str, emit: &mut dyn FnMut(Message) -> bool) -> Result<(), HistoryError> {
+        let path = Path::new(file_path);
+        Ok(stream_messages(path, Self::message_parser(path), emit)?)
+    }
 
-        Some(PaginatedMessages {
-            first,
-            last,
-            has_middle: true,
-            total_turns,
-            total_messages,
-        })
+    fn watch_dir(&self) -> Option<PathBuf> {
+        Some(self.projects_dir())
     }
 
-    fn search(&self, keyword: &str, limit: usize) -> Vec<SessionInfo> {
-        let keyword_lower = keyword.to_lowercase();
-        let projects_dir = self.projects_dir();
+    fn supports_cache(&self) -> bool {
+        true
+    }
 
-        if !projects_dir.exists() {
-            return Vec::new();
-        }
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions = self.search_files(self.all_session_files(), keyword);
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
 
-        // 收集所有 jsonl 文件
-        let files: Vec<PathBuf> = fs::read_dir(&projects_dir)
-            .ok()
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
-            .flat_map(|dir| {
-                fs::read_dir(dir.path())
-                    .ok()
-                    .into_iter()
-                    .flatten()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| {
-                        e.path()
-                            .extension()
-                            .map(|ext| ext == "jsonl")
-                            .unwrap_or(false)
-                    })
-                    .map(|e| e.path())
-            })
-            .collect();
+    fn search_project(&self, project_id: &str, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions = self.search_files(self.project_session_files(project_id, None), keyword);
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
 
-        // 并行搜索
-        let results: Vec<SessionInfo> = files
-            .par_iter()
-            .filter_map(|file_path| {
-                let file = File::open(file_path).ok()?;
-                let reader = BufReader::new(file);
-
-                for line in reader.lines() {
-                    let line = match line {
-                        Ok(l) => l,
-                        Err(_) => continue,
-                    };
-                    // 跳过工具调用行
-                    if line.contains("\"tool_use\"") || line.contains("\"tool_result\"") {
-                        continue;
-                    }
-                    // 只在包含 "text" 字段的行中搜索
-                    if line.contains("\"text\"") && line.to_lowercase().contains(&keyword_lower) {
-                        return self.parse_session_info(file_path);
-                    }
-                }
-                None
-            })
-            .take_any(limit)
-            .collect();
+    fn count_matches(&self, keyword: &KeywordMatcher) -> usize {
+        let count = AtomicUsize::new(0);
+        for_each_largest_first(self.all_session_files(), |f| {
+            if Self::file_contains_keyword(f, keyword) {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+            true
+        });
+        count.into_inner()
+    }
 
-        results
+    fn search_each(&self, keyword: &KeywordMatcher, emit: &(dyn Fn(SessionInfo) -> bool + Sync)) {
+        for_each_largest_first(self.all_session_files(), |f| {
+            if !Self::file_contains_keyword(f, keyword) {
+                return true;
+            }
+            self.parse_session_info(f).map(emit).unwrap_or(true)
+        });
     }
 
-    fn delete_session(&self, file_path: &str) -> Result<(), String> {
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for info in self.search_files(self.all_session_files(), keyword) {
+            let project_id = Path::new(&info.file_path)
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().to_string());
+            if let Some(project_id) = project_id {
+                *counts.entry(project_id).or_insert(0) += 1;
+            }
+        }
+
+        let mut facets: Vec<_> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    fn move_to_trash(&self, file_path: &str) -> Result<TrashedSession, HistoryError> {
         use std::time::{SystemTime, UNIX_EPOCH};
 
         let path = Path::new(file_path);
         if !path.exists() {
-            return Err("文件不存在".to_string());
+            return Err(HistoryError::NotFound(format!("文件不存在: {}", file_path)));
         }
 
-        let session_id = path
-            .file_stem()
-            .and_then(|s| s.to_str())
+        let session_id = session_stem(path)
             .unwrap_or("unknown")
             .to_string();
 
@@ -551,23 +786,22 @@ impl CliHistoryProvider for ClaudeProvider {
             .unwrap_or("unknown")
             .to_string();
 
-        // 创建回收站目录
-        let trash_dir = self.trash_dir();
-        fs::create_dir_all(&trash_dir).map_err(|e| e.to_string())?;
-
-        // 创建带时间戳的子目录
+        // 在回收站下创建带时间戳的子目录
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
-        let item_dir = trash_dir.join(format!("{}_{}", session_id, timestamp));
-        fs::create_dir_all(&item_dir).map_err(|e| e.to_string())?;
+        let item_dir = trash::create_item_dir(&self.trash_dir(), &session_id, timestamp)?;
 
         // 移动会话文件
         let dest_file = item_dir.join(path.file_name().unwrap());
-        fs::rename(path, &dest_file).map_err(|e| e.to_string())?;
+        if let Err(e) = trash::move_path(path, &dest_file) {
+            fs::remove_dir_all(&item_dir).ok();
+            return Err(e.into());
+        }
+        let mut moved = vec![(path.to_path_buf(), dest_file)];
 
-        // 移动 file-history 目录（如果存在）
+        // 移动 file-history 目录（如果存在），失败时回滚会话文件
         let file_history_dir = path
             .parent()
             .unwrap()
@@ -575,60 +809,484 @@ impl CliHistoryProvider for ClaudeProvider {
             .join(&session_id);
         let original_file_history = if file_history_dir.exists() {
             let dest_fh = item_dir.join("file-history");
-            fs::rename(&file_history_dir, &dest_fh).ok();
+            if let Err(e) = trash::move_path(&file_history_dir, &dest_fh) {
+                trash::rollback(&moved, &item_dir);
+                return Err(e.into());
+            }
+            moved.push((file_history_dir.clone(), dest_fh));
             Some(file_history_dir.to_string_lossy().to_string())
         } else {
             None
         };
 
-        // 更新 manifest
-        let manifest_path = trash_dir.join("manifest.json");
-        let mut manifest: crate::types::TrashManifest = if manifest_path.exists() {
-            let content = fs::read_to_string(&manifest_path).unwrap_or_default();
-            serde_json::from_str(&content)
-                .unwrap_or(crate::types::TrashManifest { items: Vec::new() })
-        } else {
-            crate::types::TrashManifest { items: Vec::new() }
-        };
-
-        manifest.items.push(crate::types::TrashItem {
+        let item = crate::types::TrashItem {
             session_id,
             project_name,
             deleted_at: timestamp as i64,
             dir_name: item_dir.file_name().unwrap().to_string_lossy().to_string(),
             original_file: file_path.to_string(),
             original_file_history,
+            size_bytes: trash::dir_size(&item_dir),
+        };
+        Ok(TrashedSession { item, moved, item_dir })
+    }
+}
+
+impl ClaudeProvider {
+    /// 项目目录下的会话文件（排除 agent- 子任务文件，可按文件名过滤）
+    fn project_session_files(&self, project_id: &str, name_glob: Option<&glob::Pattern>) -> Vec<PathBuf> {
+        let project_dir = self.projects_dir().join(project_id);
+        if !project_dir.exists() {
+            return Vec::new();
+        }
+
+        fs::read_dir(&project_dir)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| is_session_file(&e.path()))
+            .filter(|e| {
+                // 复刻 DEV 版：过滤 agent- 开头的子任务文件
+                !e.file_name().to_string_lossy().starts_with("agent-")
+            })
+            .filter(|e| {
+                name_glob
+                    .map(|p| p.matches(&e.file_name().to_string_lossy()))
+                    .unwrap_or(true)
+            })
+            .map(|e| e.path())
+            .collect()
+    }
+
+    /// 会话的子任务记录文件：`<会话 ID>/subagents/agent-*.jsonl`，
+    /// 以及同一项目目录下 sessionId 指向该会话的 `agent-*.jsonl`
+    fn subagent_files(session_path: &Path, session_id: &str) -> Vec<PathBuf> {
+        let project_dir = match session_path.parent() {
+            Some(d) => d,
+            None => return Vec::new(),
+        };
+        let agent_files = |dir: &Path| -> Vec<PathBuf> {
+            fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| is_session_file(p))
+                .filter(|p| session_stem(p).map(|s| s.starts_with("agent-")).unwrap_or(false))
+                .collect()
+        };
+
+        let mut files = agent_files(&project_dir.join(session_id).join("subagents"));
+        files.extend(
+            agent_files(project_dir)
+                .into_iter()
+                .filter(|p| Self::agent_parent_session(p).as_deref() == Some(session_id)),
+        );
+        files.sort();
+        files
+    }
+
+    /// 主会话文件中的侧链消息（已设置字节偏移）
+    fn sidechain_messages(path: &Path) -> Vec<Message> {
+        let reader = match open_session(path) {
+            Ok((r, _)) => r,
+            Err(_) => return Vec::new(),
+        };
+        JsonlLines::new(reader)
+            .filter(|(_, line)| line.contains("\"isSidechain\":true"))
+            .filter_map(|(offset, line)| {
+                let data: Value = serde_json::from_str(&line).ok()?;
+                if !Self::is_sidechain(&data) {
+                    return None;
+                }
+                let mut msg = Self::parse_message(&data)?;
+                msg.byte_offset = Some(offset);
+                Some(msg)
+            })
+            .collect()
+    }
+
+    /// 子任务文件所属的主会话 ID（读取前几行的 sessionId）
+    fn agent_parent_session(path: &Path) -> Option<String> {
+        let (reader, _) = open_session(path).ok()?;
+        JsonlLines::new(reader).take(5).find_map(|(_, line)| {
+            let data: Value = serde_json::from_str(&line).ok()?;
+            data.get("sessionId")?.as_str().map(String::from)
+        })
+    }
+
+    /// 收集所有项目目录下的 jsonl 文件
+    fn all_session_files(&self) -> Vec<PathBuf> {
+        let projects_dir = self.projects_dir();
+        if !projects_dir.exists() {
+            return Vec::new();
+        }
+
+        fs::read_dir(&projects_dir)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .flat_map(|dir| {
+                fs::read_dir(dir.path())
+                    .ok()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| is_session_file(&e.path()))
+                    .map(|e| e.path())
+            })
+            .collect()
+    }
+
+    /// 并行搜索给定文件，返回包含关键词的会话（未排序）
+    fn search_files(&self, files: Vec<PathBuf>, keyword: &KeywordMatcher) -> Vec<SessionInfo> {
+        let matches = Mutex::new(Vec::new());
+        for_each_largest_first(files, |f| {
+            if Self::file_contains_keyword(f, keyword) {
+                if let Some(info) = self.parse_session_info(f) {
+                    matches.lock().push(info);
+                }
+            }
+            true
         });
+        matches.into_inner()
+    }
 
-        let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
-        fs::write(&manifest_path, manifest_json).map_err(|e| e.to_string())?;
+    /// 判断会话文件是否包含关键词（跳过工具调用行，只在包含 text 字段的行和用户消息行中匹配）
+    /// 用户直接输入的内容通常是字符串形式的 content，没有 text 字段
+    fn file_contains_keyword(file_path: &Path, keyword: &KeywordMatcher) -> bool {
+        if keyword.is_cancelled() {
+            return false;
+        }
+        let reader = match open_session(file_path) {
+            Ok((r, _)) => r,
+            Err(_) => return false,
+        };
+        let parse = Self::message_parser(file_path);
 
-        Ok(())
+        for (_, line) in JsonlLines::new(reader) {
+            // 跳过工具调用行
+            if line.contains("\"tool_use\"") || line.contains("\"tool_result\"") {
+                continue;
+            }
+            let has_text = line.contains("\"text\"") || line.contains("\"role\":\"user\"");
+            if has_text && keyword.line_matches(&line, parse) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 列出项目目录及其修改时间（按修改时间降序，相同时按目录名升序）
+    fn project_dirs(&self) -> Vec<(fs::DirEntry, SystemTime)> {
+        let mut dirs: Vec<_> = fs::read_dir(self.projects_dir())
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| {
+                let mtime = e.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                (e, mtime)
+            })
+            .collect();
+        dirs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.file_name().cmp(&b.0.file_name())));
+        dirs
+    }
+
+    /// 读取项目目录的 cwd、会话数与最后活动时间
+    fn build_project(&self, entry: &fs::DirEntry, modified: SystemTime) -> Option<Project> {
+        let path = entry.path();
+        let id = path.file_name()?.to_str()?.to_string();
+        let mtime = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+
+        // 获取 cwd
+        let cwd = self.get_project_cwd(&path);
+        let session_files: Vec<_> = fs::read_dir(&path)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| is_session_file(&e.path()))
+            .collect();
+
+        // 只读取最新会话文件的尾部来获取最后活动时间
+        let last_activity = session_files
+            .iter()
+            .max_by_key(|e| {
+                e.metadata()
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH)
+            })
+            .and_then(|e| read_last_timestamp(&e.path()));
+
+        // 只按文件名计数，不解析文件；与 load_project 一样跳过 agent- 子任务文件
+        let session_count = session_files
+            .iter()
+            .filter(|e| !e.file_name().to_string_lossy().starts_with("agent-"))
+            .count();
+
+        Some(Project {
+            id,
+            cwd,
+            last_modified: mtime,
+            session_count,
+            last_activity,
+        })
     }
-}
 
-impl ClaudeProvider {
     /// 快速获取项目的 cwd
     fn get_project_cwd(&self, project_dir: &Path) -> Option<String> {
         for entry in fs::read_dir(project_dir).ok()? {
             let entry = entry.ok()?;
-            if entry
-                .path()
-                .extension()
-                .map(|e| e == "jsonl")
-                .unwrap_or(false)
-            {
-                let file = File::open(entry.path()).ok()?;
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    let line = line.ok()?;
-                    if line.contains("\"cwd\"") {
-                        let data: Value = serde_json::from_str(&line).ok()?;
-                        return data.get("cwd").and_then(|v| v.as_str()).map(String::from);
-                    }
-                }
+            if is_session_file(&entry.path()) {
+                return find_cwd(&entry.path(), record_cwd);
             }
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::SearchScope;
+    use crate::test_util::TempHome;
+    use std::io::Write;
+    use std::time::Instant;
+
+    /// 临时 Claude 目录：一个项目下 `large` 个大文件和 `small` 个小文件，每个文件都在末尾提到关键词
+    struct SkewedCorpus {
+        home: TempHome,
+    }
+
+    impl SkewedCorpus {
+        fn new(name: &str, large: usize, large_lines: usize, small: usize) -> Self {
+            let home = TempHome::new(name);
+            let project = home.base.join("projects").join("-tmp-skewed");
+            fs::create_dir_all(&project).unwrap();
+            for i in 0..large {
+                Self::write_session(&project.join(format!("large-{}.jsonl", i)), large_lines);
+            }
+            for i in 0..small {
+                Self::write_session(&project.join(format!("small-{}.jsonl", i)), 1);
+            }
+            Self { home }
+        }
+
+        fn write_session(path: &Path, filler_lines: usize) {
+            let mut file = std::io::BufWriter::new(fs::File::create(path).unwrap());
+            let line = |role: &str, text: &str, ts: usize| {
+                format!(
+                    r#"{{"type":"{role}","cwd":"/tmp/skewed","timestamp":"2025-01-01T10:{:02}:{:02}.000Z","message":{{"role":"{role}","content":[{{"type":"text","text":"{text}"}}]}}}}"#,
+                    (ts / 60) % 60,
+                    ts % 60,
+                )
+            };
+            writeln!(file, "{}", line("user", "start the job", 0)).unwrap();
+            for i in 0..filler_lines {
+                writeln!(file, "{}", line("assistant", "working on it, nothing interesting yet", i + 1)).unwrap();
+            }
+            writeln!(file, "{}", line("user", "now find the needle", filler_lines + 1)).unwrap();
+        }
+
+        fn provider(&self) -> ClaudeProvider {
+            ClaudeProvider::new(self.home.base.clone())
+        }
+    }
+
+    /// 临时 `.claude` 目录：两个项目，其中一个另含子任务文件（不计入会话数）
+    fn fixture_home(name: &str) -> TempHome {
+        let home = TempHome::new(&format!("home_{}", name));
+        home.write_lines(
+            "projects/-home-me-proj/s1.jsonl",
+            &[
+                r#"{"type":"user","cwd":"/home/me/proj","uuid":"u1","timestamp":"2025-01-01T10:00:00.000Z","message":{"role":"user","content":"deploy the app"}}"#,
+                r#"{"type":"assistant","cwd":"/home/me/proj","uuid":"a1","parentUuid":"u1","timestamp":"2025-01-01T10:00:05.000Z","message":{"role":"assistant","model":"claude-sonnet-4","content":[{"type":"text","text":"Deployed."}]}}"#,
+                r#"{"type":"user","cwd":"/home/me/proj","uuid":"u2","parentUuid":"a1","timestamp":"2025-01-01T10:01:00.000Z","message":{"role":"user","content":"thanks"}}"#,
+            ],
+        );
+        home.write_lines(
+            "projects/-home-me-proj/agent-1.jsonl",
+            &[r#"{"type":"user","cwd":"/home/me/proj","timestamp":"2025-01-01T10:00:01.000Z","message":{"role":"user","content":"subtask"}}"#],
+        );
+        home.write_lines(
+            "projects/-home-me-other/s2.jsonl",
+            &[r#"{"type":"user","cwd":"/home/me/other","uuid":"u1","timestamp":"2025-01-02T08:00:00.000Z","message":{"role":"user","content":"hello"}}"#],
+        );
+        home
+    }
+
+    /// 调整前的做法：平铺文件列表直接 par_iter
+    fn flat_search(provider: &ClaudeProvider, keyword: &KeywordMatcher) -> Vec<SessionInfo> {
+        provider
+            .all_session_files()
+            .par_iter()
+            .filter(|f| ClaudeProvider::file_contains_keyword(f, keyword))
+            .filter_map(|f| provider.parse_session_info(f))
+            .collect()
+    }
+
+    fn sorted_ids(sessions: &[SessionInfo]) -> Vec<String> {
+        let mut ids: Vec<String> = sessions.iter().map(|s| s.id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn sort_largest_first_orders_by_size() {
+        let corpus = SkewedCorpus::new("sort", 2, 50, 3);
+        let files = sort_largest_first(corpus.provider().all_session_files());
+        let sizes: Vec<u64> = files.iter().map(|f| fs::metadata(f).unwrap().len()).collect();
+        assert_eq!(files.len(), 5);
+        assert!(sizes.windows(2).all(|w| w[0] >= w[1]));
+        assert!(files[0].to_string_lossy().contains("large-"));
+    }
+
+    #[test]
+    fn search_matches_flat_scan_on_skewed_corpus() {
+        let corpus = SkewedCorpus::new("search", 3, 200, 40);
+        let provider = corpus.provider();
+        let needle = KeywordMatcher::new("needle", false);
+
+        let results = provider.search(&needle, usize::MAX);
+        assert_eq!(results.len(), 43);
+        assert_eq!(sorted_ids(&results), sorted_ids(&flat_search(&provider, &needle)));
+        assert_eq!(provider.count_matches(&needle), 43);
+        assert_eq!(provider.search_facets(&needle), vec![("-tmp-skewed".to_string(), 43)]);
+        assert!(provider.search(&KeywordMatcher::new("absent", false), usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn search_each_stops_early() {
+        let corpus = SkewedCorpus::new("early", 2, 50, 30);
+        let emitted = AtomicUsize::new(0);
+        corpus.provider().search_each(&KeywordMatcher::new("needle", false), &|_| {
+            emitted.fetch_add(1, Ordering::Relaxed);
+            false
+        });
+        // 每个线程最多在看到停止标记前再产出一个
+        assert!(emitted.load(Ordering::Relaxed) <= rayon::current_num_threads());
+    }
+
+    /// 偏斜语料上的耗时对比（只输出耗时，不做计时断言）：
+    /// cargo test --release -- --ignored --nocapture bench_skewed
+    #[test]
+    #[ignore]
+    fn bench_skewed_corpus_largest_first() {
+        let corpus = SkewedCorpus::new("bench", 4, 400_000, 2_000);
+        let provider = corpus.provider();
+        let needle = KeywordMatcher::new("needle", false);
+        const ROUNDS: u32 = 3;
+
+        let started = Instant::now();
+        for _ in 0..ROUNDS {
+            assert_eq!(flat_search(&provider, &needle).len(), 2_004);
+        }
+        let flat = started.elapsed() / ROUNDS;
+
+        let started = Instant::now();
+        for _ in 0..ROUNDS {
+            assert_eq!(provider.search(&needle, usize::MAX).len(), 2_004);
+        }
+        let largest_first = started.elapsed() / ROUNDS;
+
+        eprintln!("flat par_iter: {:?}, largest first: {:?}", flat, largest_first);
+    }
+
+    #[test]
+    fn list_projects_reads_fixture_home() {
+        let home = fixture_home("projects");
+        let mut projects = ClaudeProvider::new(home.base.clone()).list_projects(0);
+        projects.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let summary: Vec<(&str, Option<&str>, usize)> = projects
+            .iter()
+            .map(|p| (p.id.as_str(), p.cwd.as_deref(), p.session_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("-home-me-other", Some("/home/me/other"), 1), ("-home-me-proj", Some("/home/me/proj"), 1)]
+        );
+    }
+
+    #[test]
+    fn load_session_reads_fixture_home() {
+        let home = fixture_home("session");
+        let provider = ClaudeProvider::new(home.base.clone());
+        let sessions = provider.load_project("-home-me-proj", None, crate::provider::DEFAULT_MIN_TURNS);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "s1");
+
+        let session = provider.load_session(&sessions[0].file_path).unwrap();
+        let roles: Vec<&str> = session.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert_eq!(session.messages[1].get_text(), "Deployed.");
+        assert_eq!(session.info.user_turn_count, 2);
+        assert_eq!(session.info.cwd.as_deref(), Some("/home/me/proj"));
+    }
+
+    #[test]
+    fn scoped_search_checks_line_role() {
+        let home = fixture_home("scope");
+        let provider = ClaudeProvider::new(home.base.clone());
+        let hits = |keyword: &str, scope: SearchScope| {
+            sorted_ids(&provider.search(&KeywordMatcher::new(keyword, false).with_scope(scope), usize::MAX))
+        };
+        assert_eq!(hits("the app", SearchScope::All), vec!["s1"]);
+        assert_eq!(hits("the app", SearchScope::User), vec!["s1"]);
+        assert!(hits("the app", SearchScope::Assistant).is_empty());
+        assert!(hits("thanks", SearchScope::Assistant).is_empty());
+        assert!(hits("deployed", SearchScope::User).is_empty());
+        assert_eq!(hits("deployed", SearchScope::Assistant), vec!["s1"]);
+    }
+
+    #[test]
+    fn summary_meta_and_sidechain_lines_are_not_counted() {
+        let home = fixture_home("records");
+        let path = home.base.join("projects").join("-home-me-proj").join("s3.jsonl");
+        let lines = [
+            r#"{"type":"summary","summary":"Deploy the app","leafUuid":"a2"}"#,
+            r#"{"type":"user","isMeta":true,"cwd":"/home/me/proj","uuid":"m1","timestamp":"2025-01-03T10:00:00.000Z","message":{"role":"user","content":"Caveat: The messages below were generated by the user while running local commands."}}"#,
+            r#"{"type":"user","cwd":"/home/me/proj","uuid":"u1","parentUuid":"m1","timestamp":"2025-01-03T10:00:01.000Z","message":{"role":"user","content":"deploy the app"}}"#,
+            r#"{"type":"assistant","cwd":"/home/me/proj","uuid":"a1","parentUuid":"u1","timestamp":"2025-01-03T10:00:02.000Z","message":{"role":"assistant","content":[{"type":"text","text":"Checking."}]}}"#,
+            r#"{"type":"user","isSidechain":true,"cwd":"/home/me/proj","uuid":"s1","timestamp":"2025-01-03T10:00:03.000Z","message":{"role":"user","content":"subtask prompt"}}"#,
+            r#"{"type":"assistant","isSidechain":true,"cwd":"/home/me/proj","uuid":"s2","parentUuid":"s1","timestamp":"2025-01-03T10:00:04.000Z","message":{"role":"assistant","content":[{"type":"text","text":"subtask done"}]}}"#,
+            r#"{"type":"user","cwd":"/home/me/proj","uuid":"u2","parentUuid":"a1","timestamp":"2025-01-03T10:00:05.000Z","message":{"role":"user","content":"thanks"}}"#,
+            r#"{"type":"assistant","cwd":"/home/me/proj","uuid":"a2","parentUuid":"u2","timestamp":"2025-01-03T10:00:06.000Z","message":{"role":"assistant","content":[{"type":"text","text":"Deployed."}]}}"#,
+        ];
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        let provider = ClaudeProvider::new(home.base.clone());
+
+        let info = provider.parse_session_info(&path).unwrap();
+        assert_eq!((info.message_count, info.user_turn_count), (4, 2));
+        assert_eq!(info.preview.as_deref(), Some("deploy the app"));
+
+        let file_path = path.to_string_lossy().to_string();
+        let session = provider.load_session(&file_path).unwrap();
+        assert_eq!((session.info.message_count, session.info.user_turn_count), (4, 2));
+        assert_eq!(session.info.total_chars, info.total_chars);
+        // 元消息作为上下文保留，侧链不出现在主会话中
+        let texts: Vec<String> = session.messages.iter().map(|m| m.get_text()).collect();
+        assert_eq!(texts.len(), 5);
+        assert!(session.messages[0].is_context && !session.messages[0].is_real_user);
+        assert!(!texts.iter().any(|t| t.starts_with("subtask")));
+
+        let sidechain = provider.load_subagent_messages(&file_path);
+        let sidechain: Vec<(String, Option<&str>)> =
+            sidechain.iter().map(|m| (m.get_text(), m.subagent.as_deref())).collect();
+        assert_eq!(
+            sidechain,
+            vec![
+                ("subtask prompt".to_string(), Some(SIDECHAIN_NAME)),
+                ("subtask done".to_string(), Some(SIDECHAIN_NAME)),
+            ]
+        );
+    }
+}
@@ -1,28 +1,84 @@
 //! Codex CLI 历史记录提供者
 
-use crate::provider::CliHistoryProvider;
+use crate::jsonl::{find_cwd, is_session_file, open_session, read_last_timestamp, read_messages_since, session_stem, stream_messages, JsonlLines};
+use crate::paths::{canonicalize_cwd, codex_project_id, normalize_path};
+use crate::timestamp::parse_timestamp;
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::provider::{sort_by_last_activity, CliHistoryProvider, DEFAULT_MIN_TURNS};
+use crate::trash::{self, TrashedSession};
 use crate::types::*;
 use rayon::prelude::*;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::io::BufRead;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use walkdir::WalkDir;
 
+/// Codex 注入上下文消息的文本前缀
+const CONTEXT_MARKERS: &[&str] = &["<environment_context>", "<user_instructions>"];
+
+/// 读不到 cwd 的会话归入的目录名
+const UNKNOWN_CWD: &str = "未知目录";
+
 pub struct CodexProvider {
     base_dir: PathBuf,
 }
 
+/// token 用量累加器
+/// 新版 token_count 事件携带累计值（info.total_token_usage），取最后一次；
+/// 旧版只有单轮增量（info.last_token_usage 或 payload 顶层字段），逐条累加
+#[derive(Default)]
+struct TokenCountTally {
+    cumulative: Option<(i64, i64)>,
+    delta: (i64, i64),
+}
+
+impl TokenCountTally {
+    fn add(&mut self, data: &Value) {
+        if data.get("type").and_then(|v| v.as_str()) != Some("event_msg") {
+            return;
+        }
+        let payload = match data.get("payload") {
+            Some(p) if p.get("type").and_then(|v| v.as_str()) == Some("token_count") => p,
+            _ => return,
+        };
+        let read = |usage: &Value| {
+            let get = |k: &str| usage.get(k).and_then(|v| v.as_i64()).unwrap_or(0);
+            (get("input_tokens"), get("output_tokens"))
+        };
+
+        let info = payload.get("info").filter(|v| !v.is_null());
+        if let Some(total) = info.and_then(|i| i.get("total_token_usage")) {
+            self.cumulative = Some(read(total));
+        } else if let Some(last) = info.and_then(|i| i.get("last_token_usage")) {
+            let (i, o) = read(last);
+            self.delta = (self.delta.0 + i, self.delta.1 + o);
+        } else if payload.get("input_tokens").is_some() || payload.get("output_tokens").is_some() {
+            let (i, o) = read(payload);
+            self.delta = (self.delta.0 + i, self.delta.1 + o);
+        }
+    }
+
+    fn totals(&self) -> (i64, i64) {
+        self.cumulative.unwrap_or(self.delta)
+    }
+}
+
 impl CodexProvider {
     pub fn new(base_dir: PathBuf) -> Self {
         Self { base_dir }
     }
 
+    /// 默认目录为 `~/.codex`，可用环境变量 `CODEX_HISTORY_DIR` 覆盖
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Option<Self> {
-        let home = dirs::home_dir()?;
-        let codex_dir = home.join(".codex");
+        let codex_dir = match std::env::var_os("CODEX_HISTORY_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::home_dir()?.join(".codex"),
+        };
         if codex_dir.exists() {
             Some(Self::new(codex_dir))
         } else {
@@ -34,52 +90,124 @@ impl CodexProvider {
         self.base_dir.join("sessions")
     }
 
-    /// 从文件快速提取 cwd
-    fn get_cwd_fast(file_path: &Path) -> Option<String> {
-        let file = File::open(file_path).ok()?;
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line.ok()?;
-            if line.contains("\"cwd\"") {
-                let data: Value = serde_json::from_str(&line).ok()?;
-                // Codex 格式：payload.cwd 或直接 cwd
-                if let Some(cwd) = data
-                    .get("payload")
-                    .and_then(|p| p.get("cwd"))
-                    .and_then(|v| v.as_str())
-                {
-                    return Some(cwd.to_string());
-                }
-                if let Some(cwd) = data.get("cwd").and_then(|v| v.as_str()) {
-                    return Some(cwd.to_string());
+    /// 递归收集 sessions 目录下的所有会话文件（含 `.jsonl.gz`）
+    fn session_files(&self) -> Vec<PathBuf> {
+        let sessions_dir = self.sessions_dir();
+        if !sessions_dir.exists() {
+            return Vec::new();
+        }
+
+        WalkDir::new(&sessions_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file() && is_session_file(e.path()))
+            .map(|e| e.path().to_path_buf())
+            .collect()
+    }
+
+    /// 属于项目的会话文件（可按文件名过滤）
+    fn project_session_files(&self, project_id: &str, name_glob: Option<&glob::Pattern>) -> Vec<PathBuf> {
+        let files: Vec<PathBuf> = self
+            .session_files()
+            .into_iter()
+            .filter(|f| {
+                let name = f.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                name_glob.map(|p| p.matches(&name)).unwrap_or(true)
+            })
+            .collect();
+
+        self.file_cwds(files)
+            .into_iter()
+            .filter(|(_, cwd)| cwd.as_deref().is_some_and(|c| Self::is_project_cwd(project_id, c)))
+            .map(|(f, _)| f)
+            .collect()
+    }
+
+    /// `cwd` 是否属于项目；也接受旧版以规范化 cwd 作为 ID 的写法
+    fn is_project_cwd(project_id: &str, cwd: &str) -> bool {
+        codex_project_id(cwd) == project_id || normalize_path(cwd) == normalize_path(project_id)
+    }
+
+    /// 读取各会话文件的 cwd：修改时间与缓存库记录一致的直接复用，其余并行读取后写回缓存库
+    fn file_cwds(&self, files: Vec<PathBuf>) -> Vec<(PathBuf, Option<String>)> {
+        let mut known = crate::cache::load_file_cwds(self.cli_type());
+
+        let resolved: Vec<(PathBuf, i64, Option<String>, bool)> = files
+            .into_par_iter()
+            .map(|f| {
+                let key = f.to_string_lossy().to_string();
+                let mtime = crate::cache::get_file_mtime(&key);
+                match known.get(&key) {
+                    Some((cached_mtime, cwd)) if *cached_mtime == mtime => (f, mtime, cwd.clone(), false),
+                    _ => {
+                        let cwd = Self::get_cwd_fast(&f);
+                        (f, mtime, cwd, true)
+                    }
                 }
-            }
+            })
+            .collect();
+
+        let updated: Vec<(String, i64, Option<String>)> = resolved
+            .iter()
+            .filter(|(_, _, _, changed)| *changed)
+            .map(|(f, mtime, cwd, _)| (f.to_string_lossy().to_string(), *mtime, cwd.clone()))
+            .collect();
+        // 只传入部分文件（如按文件名过滤）时，其余记录不能视为已删除，按文件是否存在判断
+        for (f, _, _, _) in &resolved {
+            known.remove(f.to_string_lossy().as_ref());
         }
-        None
+        let removed: Vec<String> = known.into_keys().filter(|f| !Path::new(f).exists()).collect();
+        if !updated.is_empty() || !removed.is_empty() {
+            crate::cache::save_file_cwds(self.cli_type(), &updated, &removed).ok();
+        }
+
+        resolved.into_iter().map(|(f, _, cwd, _)| (f, cwd)).collect()
     }
 
-    /// 规范化路径：统一使用反斜杠，盘符大写
-    fn normalize_path(path: &str) -> String {
-        let normalized = path.replace('/', "\\");
-        // 盘符大写
-        if normalized.len() >= 2 && normalized.chars().nth(1) == Some(':') {
-            let mut chars: Vec<char> = normalized.chars().collect();
-            chars[0] = chars[0].to_ascii_uppercase();
-            chars.into_iter().collect()
-        } else {
-            normalized
+    /// 判断会话文件是否包含关键词
+    fn file_contains_keyword(file_path: &Path, keyword: &KeywordMatcher) -> bool {
+        if keyword.is_cancelled() {
+            return false;
+        }
+        let reader = match open_session(file_path) {
+            Ok((r, _)) => r,
+            Err(_) => return false,
+        };
+
+        for (_, line) in JsonlLines::new(reader) {
+            if keyword.line_matches(&line, Self::parse_codex_message) {
+                return true;
+            }
         }
+        false
+    }
+
+    /// 从文件快速提取 cwd
+    fn get_cwd_fast(file_path: &Path) -> Option<String> {
+        find_cwd(file_path, |data| {
+            // Codex 格式：payload.cwd 或直接 cwd
+            data.get("payload")
+                .and_then(|p| p.get("cwd"))
+                .or_else(|| data.get("cwd"))
+                .and_then(|v| v.as_str())
+                .map(String::from)
+        })
     }
 
     /// 解析 Codex 消息
-    fn parse_codex_message(data: &Value) -> Option<Message> {
+    pub(crate) fn parse_codex_message(data: &Value) -> Option<Message> {
         let msg_type = data.get("type")?.as_str()?;
 
         match msg_type {
             "response_item" => {
                 let payload = data.get("payload")?;
-                if payload.get("type")?.as_str()? != "message" {
-                    return None;
+                match payload.get("type")?.as_str()? {
+                    "message" => {}
+                    "reasoning" => return Self::parse_reasoning(data, payload),
+                    "function_call" | "custom_tool_call" | "local_shell_call" => {
+                        return Self::parse_tool_call(data, payload)
+                    }
+                    _ => return None,
                 }
                 let role = payload
                     .get("role")
@@ -96,11 +224,22 @@ impl CodexProvider {
                                 text: Some(text.to_string()),
                                 tool_name: None,
                                 tool_input: None,
+                                tool_result: None,
+                                tool_use_id: None,
                             });
                         }
                     }
                 }
 
+                // Codex 以 user 角色注入环境和指令上下文
+                let is_context = role == "user"
+                    && blocks.iter().any(|b| {
+                        b.text
+                            .as_deref()
+                            .map(|t| CONTEXT_MARKERS.iter().any(|m| t.trim_start().starts_with(m)))
+                            .unwrap_or(false)
+                    });
+
                 Some(Message {
                     uuid: None,
                     timestamp: data
@@ -111,6 +250,10 @@ impl CodexProvider {
                     role: role.to_string(),
                     content_blocks: blocks,
                     is_real_user: false,
+                    byte_offset: None,
+                    is_context,
+                    subagent: None,
+                    parent_uuid: None,
                 })
             }
             "event_msg" => {
@@ -132,6 +275,8 @@ impl CodexProvider {
                     text: Some(message_text.to_string()),
                     tool_name: None,
                     tool_input: None,
+                    tool_result: None,
+                    tool_use_id: None,
                 }];
 
                 Some(Message {
@@ -144,28 +289,129 @@ impl CodexProvider {
                     role: role.to_string(),
                     content_blocks: blocks,
                     is_real_user,
+                    byte_offset: None,
+                    is_context: false,
+                    subagent: None,
+                    parent_uuid: None,
                 })
             }
             _ => None,
         }
     }
 
+    /// 记录中的模型名（turn_context / session_meta 的 payload.model）
+    fn record_model(data: &Value) -> Option<&str> {
+        match data.get("type")?.as_str()? {
+            "turn_context" | "session_meta" => data.get("payload")?.get("model")?.as_str().filter(|m| !m.is_empty()),
+            _ => None,
+        }
+    }
+
+    /// 解析工具调用条目为 tool_use 消息
+    /// function_call 的 arguments 本身是 JSON 字符串；custom_tool_call 的 input 为任意文本；
+    /// local_shell_call 没有工具名，取 action（含 command 等）作为输入
+    fn parse_tool_call(data: &Value, payload: &Value) -> Option<Message> {
+        let payload_type = payload.get("type")?.as_str()?;
+        let (tool_name, tool_input) = match payload_type {
+            "local_shell_call" => ("local_shell".to_string(), payload.get("action").map(|v| v.to_string())),
+            _ => {
+                let name = payload.get("name")?.as_str()?.to_string();
+                let input = payload.get("arguments").or_else(|| payload.get("input")).map(|v| match v.as_str() {
+                    // 合法 JSON 原样保留，其余文本编码为 JSON 字符串
+                    Some(s) if serde_json::from_str::<Value>(s).is_ok() => s.to_string(),
+                    _ => v.to_string(),
+                });
+                (name, input)
+            }
+        };
+
+        Some(Message {
+            uuid: payload.get("call_id").and_then(|v| v.as_str()).map(String::from),
+            timestamp: data
+                .get("timestamp")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            msg_type: payload_type.to_string(),
+            role: "assistant".to_string(),
+            content_blocks: vec![ContentBlock {
+                block_type: "tool_use".to_string(),
+                text: None,
+                tool_name: Some(tool_name),
+                tool_input,
+                tool_result: None,
+                tool_use_id: payload.get("call_id").and_then(|v| v.as_str()).map(String::from),
+            }],
+            is_real_user: false,
+            byte_offset: None,
+            is_context: false,
+            subagent: None,
+            parent_uuid: None,
+        })
+    }
+
+    /// 对话消息（不含思考过程与工具调用），与 scan_session_info 的消息计数口径一致
+    fn is_dialogue_message(msg: &Message) -> bool {
+        matches!(msg.msg_type.as_str(), "response_item" | "user_message" | "agent_message")
+    }
+
+    /// 解析 reasoning 条目为思考过程消息（取 summary 与 content 中的文本，加密内容忽略）
+    fn parse_reasoning(data: &Value, payload: &Value) -> Option<Message> {
+        let texts: Vec<&str> = ["summary", "content"]
+            .iter()
+            .filter_map(|key| payload.get(*key)?.as_array())
+            .flatten()
+            .filter_map(|item| item.get("text").and_then(|v| v.as_str()))
+            .filter(|t| !t.is_empty())
+            .collect();
+        if texts.is_empty() {
+            return None;
+        }
+
+        Some(Message {
+            uuid: None,
+            timestamp: data
+                .get("timestamp")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            msg_type: "reasoning".to_string(),
+            role: "assistant".to_string(),
+            content_blocks: vec![ContentBlock {
+                block_type: "thinking".to_string(),
+                text: Some(texts.join("\n\n")),
+                tool_name: None,
+                tool_input: None,
+                tool_result: None,
+                tool_use_id: None,
+            }],
+            is_real_user: false,
+            byte_offset: None,
+            is_context: false,
+            subagent: None,
+            parent_uuid: None,
+        })
+    }
+
     /// 解析会话文件
     fn parse_session_file(&self, file_path: &Path) -> Option<Session> {
-        let file = File::open(file_path).ok()?;
-        let reader = BufReader::new(file);
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::parse_session_reader(reader, file_path, file_size)
+    }
+
+    /// 从任意读取器解析会话（`file_path` 仅用于生成 id 和记录来源）
+    pub(crate) fn parse_session_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<Session> {
 
         let mut messages = Vec::new();
         let mut first_ts: Option<String> = None;
         let mut last_ts: Option<String> = None;
         let mut cwd: Option<String> = None;
+        let mut model: Option<String> = None;
+        let mut usage = TokenCountTally::default();
 
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) if !l.trim().is_empty() => l,
-                _ => continue,
-            };
-
+        for (offset, line) in JsonlLines::new(reader) {
             let data: Value = match serde_json::from_str(&line) {
                 Ok(v) => v,
                 Err(_) => continue,
@@ -193,7 +439,13 @@ impl CodexProvider {
                 last_ts = Some(t.clone());
             }
 
-            if let Some(msg) = Self::parse_codex_message(&data) {
+            usage.add(&data);
+            if let Some(m) = Self::record_model(&data) {
+                model = Some(m.to_string());
+            }
+
+            if let Some(mut msg) = Self::parse_codex_message(&data) {
+                msg.byte_offset = Some(offset);
                 messages.push(msg);
             }
         }
@@ -203,53 +455,96 @@ impl CodexProvider {
         }
 
         let user_turn_count = messages.iter().filter(|m| m.is_real_user).count();
-        let file_size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        let (input_tokens, output_tokens) = usage.totals();
 
-        let session_id = file_path
-            .file_stem()
-            .and_then(|s| s.to_str())
+        let session_id = session_stem(file_path)
             .unwrap_or("unknown")
             .replace("rollout-", "");
 
+        let (total_chars, word_count) = messages_text_stats(&messages);
+
         Some(Session {
             info: SessionInfo {
                 id: session_id,
                 file_path: file_path.to_string_lossy().to_string(),
                 cwd,
+                first_timestamp_unix: first_ts.as_deref().and_then(parse_timestamp),
+                last_timestamp_unix: last_ts.as_deref().and_then(parse_timestamp),
                 first_timestamp: first_ts,
                 last_timestamp: last_ts,
-                message_count: messages.len(),
+                // 与 scan_session_info 一致，思考过程和工具调用不计入消息数
+                message_count: messages.iter().filter(|m| Self::is_dialogue_message(m)).count(),
                 user_turn_count,
                 file_size,
+                input_tokens,
+                output_tokens,
+                model,
+                total_chars,
+                word_count,
+                preview: first_user_preview(&messages),
             },
             messages,
+            context_messages: Vec::new(),
         })
     }
 
     /// 快速解析会话信息
     /// 复刻 DEV 版的完整过滤规则
     fn parse_session_info(&self, file_path: &Path) -> Option<SessionInfo> {
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::parse_session_info_reader(reader, file_path, file_size)
+    }
+
+    /// 扫描会话信息（不做时间戳与轮次过滤）
+    fn scan_session_info(&self, file_path: &Path) -> Option<SessionInfo> {
+        let (reader, file_size) = open_session(file_path).ok()?;
+        Self::scan_session_info_reader(reader, file_path, file_size)
+    }
+
+    /// 从任意读取器快速解析会话信息，过滤规则同 parse_session_info
+    pub(crate) fn parse_session_info_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<SessionInfo> {
+        let info = Self::scan_session_info_reader(reader, file_path, file_size)?;
+
+        // [过滤3] 无有效时间戳过滤
+        if info.first_timestamp.is_none() && info.last_timestamp.is_none() {
+            return None;
+        }
+
+        // [过滤4] 用户消息为0过滤
+        if info.user_turn_count == 0 {
+            return None;
+        }
+
+        Some(info)
+    }
+
+    /// 扫描会话信息，只跳过空文件和无消息的文件，轮次数如实记录
+    pub(crate) fn scan_session_info_reader<R: BufRead>(
+        reader: R,
+        file_path: &Path,
+        file_size: u64,
+    ) -> Option<SessionInfo> {
         // [过滤1] 空文件过滤
-        let file_size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
         if file_size == 0 {
             return None;
         }
 
-        let file = File::open(file_path).ok()?;
-        let reader = BufReader::new(file);
-
         let mut msg_count = 0;
         let mut user_turn_count = 0;
+        let mut total_chars = 0;
+        let mut word_count = 0;
+        let mut preview: Option<String> = None;
         let mut first_ts: Option<String> = None;
         let mut last_ts: Option<String> = None;
         let mut cwd: Option<String> = None;
+        let mut model: Option<String> = None;
+        let mut usage = TokenCountTally::default();
 
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) if !l.trim().is_empty() => l,
-                _ => continue,
-            };
-
+        for (_, line) in JsonlLines::new(reader) {
             let data: Value = match serde_json::from_str(&line) {
                 Ok(v) => v,
                 Err(_) => continue,
@@ -276,17 +571,30 @@ impl CodexProvider {
                 last_ts = Some(t.clone());
             }
 
+            usage.add(&data);
+            if let Some(m) = Self::record_model(&data) {
+                model = Some(m.to_string());
+            }
+
+            if let Some(msg) = Self::parse_codex_message(&data) {
+                let (chars, words) = msg.text_stats();
+                total_chars += chars;
+                word_count += words;
+                if preview.is_none() {
+                    preview = msg.user_preview();
+                }
+            }
+
             let msg_type = data.get("type").and_then(|v| v.as_str());
             match msg_type {
-                Some("response_item") => {
+                Some("response_item")
                     if data
                         .get("payload")
                         .and_then(|p| p.get("type"))
                         .and_then(|v| v.as_str())
-                        == Some("message")
-                    {
-                        msg_count += 1;
-                    }
+                        == Some("message") =>
+                {
+                    msg_count += 1;
                 }
                 Some("event_msg") => {
                     let event_type = data
@@ -309,93 +617,130 @@ impl CodexProvider {
             return None;
         }
 
-        // [过滤3] 无有效时间戳过滤
-        if first_ts.is_none() && last_ts.is_none() {
-            return None;
-        }
-
-        // [过滤4] 用户消息为0过滤
-        if user_turn_count == 0 {
-            return None;
-        }
-
-        let session_id = file_path
-            .file_stem()
-            .and_then(|s| s.to_str())
+        let session_id = session_stem(file_path)
             .unwrap_or("unknown")
             .replace("rollout-", "");
+        let (input_tokens, output_tokens) = usage.totals();
 
         Some(SessionInfo {
             id: session_id,
             file_path: file_path.to_string_lossy().to_string(),
             cwd,
+            first_timestamp_unix: first_ts.as_deref().and_then(parse_timestamp),
+            last_timestamp_unix: last_ts.as_deref().and_then(parse_timestamp),
             first_timestamp: first_ts,
             last_timestamp: last_ts,
             message_count: msg_count,
             user_turn_count,
             file_size,
+            input_tokens,
+            output_tokens,
+            model,
+            total_chars,
+            word_count,
+            preview,
         })
     }
 
-    /// 扫描所有会话文件，按 cwd 分组
-    fn scan_sessions_by_cwd(&self, limit: usize) -> HashMap<String, f64> {
-        let sessions_dir = self.sessions_dir();
-        if !sessions_dir.exists() {
-            return HashMap::new();
-        }
+    /// 扫描所有会话文件，按项目 ID 分组
+    fn scan_sessions_by_cwd(&self, limit: usize) -> HashMap<String, CwdGroup> {
+        let cwd_map = self.group_by_cwd(self.file_cwds(self.session_files()));
 
-        let files: Vec<PathBuf> = WalkDir::new(&sessions_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_type().is_file()
-                    && e.path()
-                        .extension()
-                        .map(|ext| ext == "jsonl")
-                        .unwrap_or(false)
-            })
-            .map(|e| e.path().to_path_buf())
-            .collect();
+        if limit > 0 {
+            let mut sorted: Vec<_> = cwd_map.into_iter().collect();
+            sorted.sort_by(|a, b| {
+                b.1.last_modified
+                    .partial_cmp(&a.1.last_modified)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            sorted.truncate(limit);
+            sorted.into_iter().collect()
+        } else {
+            cwd_map
+        }
+    }
 
+    /// 解析会话文件并按项目 ID 汇总
+    fn group_by_cwd(&self, files: Vec<(PathBuf, Option<String>)>) -> HashMap<String, CwdGroup> {
         // 并行扫描
-        let cwd_map: HashMap<String, f64> = files
+        files
             .par_iter()
-            .filter_map(|file_path| {
-                let cwd = Self::get_cwd_fast(file_path).unwrap_or_else(|| "未知目录".to_string());
-                // 规范化路径：统一使用反斜杠，首字母大写
-                let cwd_normalized = Self::normalize_path(&cwd);
+            .map(|(file_path, cwd)| {
+                let cwd = cwd.clone().unwrap_or_else(|| UNKNOWN_CWD.to_string());
+                let project_id = codex_project_id(&cwd);
                 let mtime = fs::metadata(file_path)
                     .and_then(|m| m.modified())
                     .ok()
                     .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
                     .map(|d| d.as_secs_f64())
                     .unwrap_or(0.0);
-                Some((cwd_normalized, mtime))
+                // 与 load_project 使用同一过滤规则计数
+                let info = self.parse_session_info(file_path);
+                let counted = info.as_ref().is_some_and(|s| s.user_turn_count >= DEFAULT_MIN_TURNS);
+                let group = CwdGroup {
+                    // 按平台规范化路径，作为展示用的 cwd
+                    cwd: normalize_path(&cwd),
+                    last_modified: mtime,
+                    newest_file: file_path.clone(),
+                    session_count: usize::from(counted),
+                    last_activity: info.and_then(|s| Some((s.last_timestamp_unix?, s.last_timestamp?))),
+                };
+                (project_id, group)
             })
-            .fold(HashMap::new, |mut acc, (cwd, mtime)| {
-                let entry = acc.entry(cwd).or_insert(0.0);
-                if mtime > *entry {
-                    *entry = mtime;
-                }
+            .fold(HashMap::new, |mut acc, (cwd, group)| {
+                CwdGroup::merge_into(&mut acc, cwd, group);
                 acc
             })
             .reduce(HashMap::new, |mut a, b| {
-                for (k, v) in b {
-                    let entry = a.entry(k).or_insert(0.0);
-                    if v > *entry {
-                        *entry = v;
-                    }
+                for (cwd, group) in b {
+                    CwdGroup::merge_into(&mut a, cwd, group);
                 }
                 a
-            });
+            })
+    }
+}
 
-        if limit > 0 {
-            let mut sorted: Vec<_> = cwd_map.into_iter().collect();
-            sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            sorted.truncate(limit);
-            sorted.into_iter().collect()
-        } else {
-            cwd_map
+/// 同一 cwd 下会话文件的汇总
+struct CwdGroup {
+    /// 最新会话的规范化 cwd
+    cwd: String,
+    last_modified: f64,
+    newest_file: PathBuf,
+    /// 通过 load_project 过滤的会话数
+    session_count: usize,
+    /// 各会话最后时间的最大值（Unix 时间戳, 原始字符串）
+    last_activity: Option<(i64, String)>,
+}
+
+impl CwdGroup {
+    /// 最后活动时间取扫描时记录的最大会话时间，没有可解析的会话时读取最新文件的尾部
+    fn into_project(self, id: String) -> Project {
+        Project {
+            id,
+            cwd: Some(self.cwd),
+            last_modified: self.last_modified,
+            session_count: self.session_count,
+            last_activity: match self.last_activity {
+                Some((_, ts)) => Some(ts),
+                None => read_last_timestamp(&self.newest_file),
+            },
+        }
+    }
+
+    fn merge_into(map: &mut HashMap<String, CwdGroup>, project_id: String, group: CwdGroup) {
+        match map.get_mut(&project_id) {
+            Some(existing) => {
+                let session_count = existing.session_count + group.session_count;
+                let last_activity = existing.last_activity.take().max(group.last_activity.clone());
+                if group.last_modified > existing.last_modified {
+                    *existing = group;
+                }
+                existing.session_count = session_count;
+                existing.last_activity = last_activity;
+            }
+            None => {
+                map.insert(project_id, group);
+            }
         }
     }
 }
@@ -413,14 +758,8 @@ impl CliHistoryProvider for CodexProvider {
         let cwd_map = self.scan_sessions_by_cwd(limit);
 
         let mut projects: Vec<_> = cwd_map
-            .into_iter()
-            .map(|(cwd, mtime)| Project {
-                id: cwd.clone(),
-                cwd: Some(cwd),
-                last_modified: mtime,
-                session_count: 0, // 会在 load_project 时填充
-                last_activity: None,
-            })
+            .into_par_iter()
+            .map(|(id, group)| group.into_project(id))
             .collect();
 
         projects.sort_by(|a, b| {
@@ -432,51 +771,46 @@ impl CliHistoryProvider for CodexProvider {
     }
 
     fn find_project_by_cwd(&self, cwd: &str) -> Option<Project> {
-        let cwd_normalized = cwd.replace('\\', "/").to_lowercase();
-        self.list_projects(0).into_iter().find(|p| {
-            p.cwd
-                .as_ref()
-                .map(|c| c.replace('\\', "/").to_lowercase() == cwd_normalized)
-                .unwrap_or(false)
-        })
+        // 与项目 ID 使用同一规范形式，再按平台决定是否忽略大小写
+        // cwd 来自缓存库中的文件 -> cwd 记录，只有匹配的文件才需要解析
+        let cwd_normalized = canonicalize_cwd(&normalize_path(cwd));
+        let files: Vec<(PathBuf, Option<String>)> = self
+            .file_cwds(self.session_files())
+            .into_iter()
+            .filter(|(_, c)| c.as_deref().is_some_and(|c| canonicalize_cwd(&normalize_path(c)) == cwd_normalized))
+            .collect();
+
+        self.group_by_cwd(files)
+            .into_iter()
+            .map(|(id, group)| group.into_project(id))
+            .max_by(|a, b| a.session_count.cmp(&b.session_count))
     }
 
-    fn load_project(&self, cwd_path: &str) -> Vec<SessionInfo> {
-        let sessions_dir = self.sessions_dir();
-        if !sessions_dir.exists() {
-            return Vec::new();
-        }
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo> {
+        // 并行解析，过滤掉轮次不足的会话
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, name_glob)
+            .par_iter()
+            .filter_map(|f| self.parse_session_info(f))
+            .filter(|s| s.user_turn_count >= min_turns)
+            .collect();
 
-        // 使用规范化路径进行比较
-        let cwd_normalized = Self::normalize_path(cwd_path);
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
 
-        let files: Vec<PathBuf> = WalkDir::new(&sessions_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_type().is_file()
-                    && e.path()
-                        .extension()
-                        .map(|ext| ext == "jsonl")
-                        .unwrap_or(false)
-            })
-            .map(|e| e.path().to_path_buf())
-            .collect();
+    fn project_files(&self, project_id: &str) -> Vec<PathBuf> {
+        self.project_session_files(project_id, None)
+    }
 
-        // 并行过滤和解析，过滤掉 <=1 轮的无效会话
-        let mut sessions: Vec<SessionInfo> = files
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, None)
             .par_iter()
-            .filter_map(|file_path| {
-                let cwd = Self::get_cwd_fast(file_path)?;
-                if Self::normalize_path(&cwd) != cwd_normalized {
-                    return None;
-                }
-                self.parse_session_info(file_path)
-            })
-            .filter(|s| s.user_turn_count >= 1) // 保留至少 1 轮对话的会话
+            .filter_map(|f| self.scan_session_info(f))
             .collect();
 
-        sessions.sort_by(|a, b| b.last_timestamp.as_ref().cmp(&a.last_timestamp.as_ref()));
+        sort_by_last_activity(&mut sessions);
         sessions
     }
 
@@ -484,159 +818,196 @@ impl CliHistoryProvider for CodexProvider {
         self.parse_session_file(Path::new(file_path))
     }
 
-    fn load_session_paginated(
-        &self,
-        file_path: &str,
-        first_turns: usize,
-        last_turns: usize,
-    ) -> Option<PaginatedMessages> {
-        let session = self.load_session(file_path)?;
-        let messages = session.messages;
+    fn load_messages_since(&self, file_path: &str, byte_offset: u64) -> Option<SessionTail> {
+        read_messages_since(Path::new(file_path), byte_offset, Self::parse_codex_message)
+    }
 
-        // 按轮次分组
-        let mut rounds: Vec<Vec<Message>> = Vec::new();
-        let mut current_round: Vec<Message> = Vec::new();
+    fn session_header(&self, file_path: &str) -> Option<(String, Option<String>)> {
+        let path = Path::new(file_path);
+        let id = session_stem(path)?.replace("rollout-", "");
+        Some((id, Self::get_cwd_fast(path)))
+    }
 
-        for msg in messages {
-            if msg.is_real_user {
-                if !current_round.is_empty() {
-                    rounds.push(current_round);
-                }
-                current_round = vec![msg];
-            } else {
-                current_round.push(msg);
-            }
-        }
-        if !current_round.is_empty() {
-            rounds.push(current_round);
-        }
+    fn stream_messages(&self, file_path: &str, emit: &mut dyn FnMut(Message) -> bool) -> Result<(), HistoryError> {
+        Ok(stream_messages(Path::new(file_path), Self::parse_codex_message, emit)?)
+    }
 
-        let total_turns = rounds.len();
-        let total_messages: usize = rounds.iter().map(|r| r.len()).sum();
-
-        if first_turns + last_turns >= total_turns {
-            let all: Vec<Message> = rounds.into_iter().flatten().collect();
-            return Some(PaginatedMessages {
-                first: all,
-                last: Vec::new(),
-                has_middle: false,
-                total_turns,
-                total_messages,
-            });
-        }
+    fn watch_dir(&self) -> Option<PathBuf> {
+        Some(self.sessions_dir())
+    }
 
-        let first: Vec<Message> = rounds[..first_turns].iter().flatten().cloned().collect();
-        let last: Vec<Message> = rounds[total_turns - last_turns..]
-            .iter()
-            .flatten()
-            .cloned()
+    fn supports_cache(&self) -> bool {
+        true
+    }
+
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .session_files()
+            .par_iter()
+            .filter(|f| Self::file_contains_keyword(f, keyword))
+            .filter_map(|f| self.parse_session_info(f))
             .collect();
 
-        Some(PaginatedMessages {
-            first,
-            last,
-            has_middle: true,
-            total_turns,
-            total_messages,
-        })
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
     }
 
-    fn search(&self, keyword: &str, limit: usize) -> Vec<SessionInfo> {
-        let keyword_lower = keyword.to_lowercase();
-        let sessions_dir = self.sessions_dir();
+    fn search_project(&self, project_id: &str, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, None)
+            .par_iter()
+            .filter(|f| Self::file_contains_keyword(f, keyword))
+            .filter_map(|f| self.parse_session_info(f))
+            .collect();
 
-        if !sessions_dir.exists() {
-            return Vec::new();
-        }
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
 
-        let files: Vec<PathBuf> = WalkDir::new(&sessions_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_type().is_file()
-                    && e.path()
-                        .extension()
-                        .map(|ext| ext == "jsonl")
-                        .unwrap_or(false)
-            })
-            .map(|e| e.path().to_path_buf())
-            .collect();
+    fn count_matches(&self, keyword: &KeywordMatcher) -> usize {
+        self.session_files()
+            .par_iter()
+            .filter(|f| Self::file_contains_keyword(f, keyword))
+            .count()
+    }
 
-        files
+    fn search_each(&self, keyword: &KeywordMatcher, emit: &(dyn Fn(SessionInfo) -> bool + Sync)) {
+        let _ = self
+            .session_files()
             .par_iter()
-            .filter_map(|file_path| {
-                let file = File::open(file_path).ok()?;
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    let line = match line {
-                        Ok(l) => l,
-                        Err(_) => continue,
-                    };
-                    if line.to_lowercase().contains(&keyword_lower) {
-                        return self.parse_session_info(file_path);
-                    }
-                }
-                None
+            .filter(|f| Self::file_contains_keyword(f, keyword))
+            .filter_map(|f| self.parse_session_info(f))
+            .try_for_each(|info| emit(info).then_some(()));
+    }
+
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)> {
+        let counts: HashMap<String, usize> = self
+            .session_files()
+            .par_iter()
+            .filter(|f| Self::file_contains_keyword(f, keyword))
+            .filter(|f| self.parse_session_info(f).is_some())
+            .map(|f| codex_project_id(&Self::get_cwd_fast(f).unwrap_or_else(|| UNKNOWN_CWD.to_string())))
+            .fold(HashMap::new, |mut acc, project_id| {
+                *acc.entry(project_id).or_insert(0) += 1;
+                acc
             })
-            .take_any(limit)
-            .collect()
+            .reduce(HashMap::new, |mut a, b| {
+                for (k, v) in b {
+                    *a.entry(k).or_insert(0) += v;
+                }
+                a
+            });
+
+        let mut facets: Vec<_> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
     }
 
-    fn delete_session(&self, file_path: &str) -> Result<(), String> {
+    fn move_to_trash(&self, file_path: &str) -> Result<TrashedSession, HistoryError> {
         use std::time::{SystemTime, UNIX_EPOCH};
 
         let path = Path::new(file_path);
         if !path.exists() {
-            return Err("文件不存在".to_string());
+            return Err(HistoryError::NotFound(format!("文件不存在: {}", file_path)));
         }
 
-        let session_id = path
-            .file_stem()
-            .and_then(|s| s.to_str())
+        let session_id = session_stem(path)
             .unwrap_or("unknown")
             .replace("rollout-", "");
 
-        // 获取 cwd 作为项目名
-        let project_name = Self::get_cwd_fast(path).unwrap_or_else(|| "未知目录".to_string());
-
-        // 创建回收站目录
-        let trash_dir = self.trash_dir();
-        fs::create_dir_all(&trash_dir).map_err(|e| e.to_string())?;
+        // 与 Claude 一致，项目名记录项目 ID（恢复时据此重新写入缓存）
+        let project_name = codex_project_id(&Self::get_cwd_fast(path).unwrap_or_else(|| UNKNOWN_CWD.to_string()));
 
-        // 创建带时间戳的子目录
+        // 在回收站下创建带时间戳的子目录
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
-        let item_dir = trash_dir.join(format!("{}_{}", session_id, timestamp));
-        fs::create_dir_all(&item_dir).map_err(|e| e.to_string())?;
+        let item_dir = trash::create_item_dir(&self.trash_dir(), &session_id, timestamp)?;
 
         // 移动会话文件
         let dest_file = item_dir.join(path.file_name().unwrap());
-        fs::rename(path, &dest_file).map_err(|e| e.to_string())?;
-
-        // 更新 manifest
-        let manifest_path = trash_dir.join("manifest.json");
-        let mut manifest: crate::types::TrashManifest = if manifest_path.exists() {
-            let content = fs::read_to_string(&manifest_path).unwrap_or_default();
-            serde_json::from_str(&content)
-                .unwrap_or(crate::types::TrashManifest { items: Vec::new() })
-        } else {
-            crate::types::TrashManifest { items: Vec::new() }
-        };
-
-        manifest.items.push(crate::types::TrashItem {
+        if let Err(e) = trash::move_path(path, &dest_file) {
+            fs::remove_dir_all(&item_dir).ok();
+            return Err(e.into());
+        }
+        let item = crate::types::TrashItem {
             session_id,
             project_name,
             deleted_at: timestamp as i64,
             dir_name: item_dir.file_name().unwrap().to_string_lossy().to_string(),
             original_file: file_path.to_string(),
             original_file_history: None,
-        });
+            size_bytes: trash::dir_size(&item_dir),
+        };
+        Ok(TrashedSession {
+            item,
+            moved: vec![(path.to_path_buf(), dest_file)],
+            item_dir,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempHome;
+
+    /// 临时 `.codex` 目录：同一 cwd 下两个会话，另一 cwd 下一个会话
+    fn fixture_home(name: &str) -> TempHome {
+        let home = TempHome::new(&format!("codex_home_{}", name));
+        let write = |id: &str, cwd: &str, prompt: &str| {
+            home.write_lines(
+                format!("sessions/2025/01/01/rollout-{}.jsonl", id),
+                &[
+                    format!(r#"{{"timestamp":"2025-01-01T09:00:00.000Z","type":"session_meta","payload":{{"id":"{id}","cwd":"{cwd}"}}}}"#),
+                    format!(r#"{{"timestamp":"2025-01-01T09:00:01.000Z","type":"event_msg","payload":{{"type":"user_message","message":"{prompt}"}}}}"#),
+                    r#"{"timestamp":"2025-01-01T09:00:02.000Z","type":"event_msg","payload":{"type":"agent_message","message":"Done."}}"#.to_string(),
+                ],
+            );
+        };
+        write("a1", "/home/me/cx", "list files");
+        write("a2", "/home/me/cx", "run tests");
+        write("b1", "/home/me/other", "hello");
+        home
+    }
 
-        let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
-        fs::write(&manifest_path, manifest_json).map_err(|e| e.to_string())?;
+    #[test]
+    fn list_projects_groups_fixture_home_by_cwd() {
+        let home = fixture_home("projects");
+        let mut projects = CodexProvider::new(home.base.clone()).list_projects(0);
+        projects.sort_by(|a, b| a.cwd.cmp(&b.cwd));
+
+        let summary: Vec<(String, Option<&str>, usize)> = projects
+            .iter()
+            .map(|p| (p.id.clone(), p.cwd.as_deref(), p.session_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (codex_project_id("/home/me/cx"), Some("/home/me/cx"), 2),
+                (codex_project_id("/home/me/other"), Some("/home/me/other"), 1),
+            ]
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn load_session_reads_fixture_home() {
+        let home = fixture_home("session");
+        let provider = CodexProvider::new(home.base.clone());
+        let mut sessions = provider.load_project(&codex_project_id("/home/me/cx"), None, DEFAULT_MIN_TURNS);
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        let ids: Vec<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["a1", "a2"]);
+
+        let session = provider.load_session(&sessions[0].file_path).unwrap();
+        let texts: Vec<(&str, String)> = session.messages.iter().map(|m| (m.role.as_str(), m.get_text())).collect();
+        assert_eq!(
+            texts,
+            vec![("user", "list files".to_string()), ("assistant", "Done.".to_string())]
+        );
+        assert_eq!(session.info.cwd.as_deref(), Some("/home/me/cx"));
     }
 }
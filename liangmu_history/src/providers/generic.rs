@@ -0,0 +1,425 @@
+//! 通用 JSONL 历史记录提供者
+//!
+//! 用于接入自定义 Agent 的日志：每个 `.jsonl` 文件为一个会话，每行一条记录，
+//! 由 FieldMap 指定角色、文本、时间戳、工作目录所在的字段（支持 `a.b` 形式的嵌套路径）。
+//! 会话所在目录（相对 base_dir，根目录为 `.`）即项目 ID。
+
+use crate::jsonl::{is_session_file, open_session, session_stem, JsonlLines};
+use crate::paths::canonicalize_cwd;
+use crate::error::HistoryError;
+use crate::matcher::KeywordMatcher;
+use crate::provider::{sort_by_last_activity, CliHistoryProvider};
+use crate::timestamp::parse_timestamp;
+use crate::trash::{self, TrashedSession};
+use crate::types::*;
+use rayon::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// 根目录下会话的项目 ID
+const ROOT_PROJECT_ID: &str = ".";
+
+/// 记录字段映射
+#[derive(Debug, Clone)]
+pub struct FieldMap {
+    pub role: String,
+    pub text: String,
+    pub timestamp: Option<String>,
+    pub cwd: Option<String>,
+}
+
+impl FieldMap {
+    /// 从 Python 传入的映射构建，`role` 和 `text` 必填
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, String> {
+        let required = |key: &str| {
+            map.get(key)
+                .cloned()
+                .ok_or_else(|| format!("field_map 缺少必填字段: {}", key))
+        };
+        Ok(Self {
+            role: required("role")?,
+            text: required("text")?,
+            timestamp: map.get("timestamp").cloned(),
+            cwd: map.get("cwd").cloned(),
+        })
+    }
+}
+
+/// 按 `a.b.c` 路径取嵌套字段
+fn field<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(data, |v, key| v.get(key))
+}
+
+/// 文本字段可以是字符串，或字符串 / `{"text": ...}` 对象组成的数组
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(items) => {
+            let parts: Vec<&str> = items
+                .iter()
+                .filter_map(|item| item.as_str().or_else(|| item.get("text")?.as_str()))
+                .collect();
+            (!parts.is_empty()).then(|| parts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+/// 时间戳字段可以是字符串或 Unix 时间（秒或毫秒），统一为 RFC3339 字符串
+fn value_timestamp(value: &Value) -> Option<String> {
+    if let Some(s) = value.as_str() {
+        return Some(s.to_string());
+    }
+    let n = value.as_f64()?;
+    let secs = if n > 1e12 { n / 1000.0 } else { n };
+    chrono::DateTime::from_timestamp(secs as i64, 0).map(|dt| dt.to_rfc3339())
+}
+
+pub struct GenericJsonlProvider {
+    name: &'static str,
+    base_dir: PathBuf,
+    fields: FieldMap,
+}
+
+impl GenericJsonlProvider {
+    pub fn new(name: &'static str, base_dir: PathBuf, fields: FieldMap) -> Self {
+        Self { name, base_dir, fields }
+    }
+
+    /// 收集 base_dir 下的所有会话文件（跳过回收站）
+    fn session_files(&self) -> Vec<PathBuf> {
+        let trash_dir = self.trash_dir();
+        WalkDir::new(&self.base_dir)
+            .into_iter()
+            .filter_entry(|e| e.path() != trash_dir)
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| is_session_file(e.path()))
+            .map(|e| e.path().to_path_buf())
+            .collect()
+    }
+
+    fn project_id_of(&self, file_path: &Path) -> String {
+        let dir = file_path.parent().unwrap_or(&self.base_dir);
+        match dir.strip_prefix(&self.base_dir) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.to_string_lossy().replace('\\', "/"),
+            _ => ROOT_PROJECT_ID.to_string(),
+        }
+    }
+
+    fn project_session_files(&self, project_id: &str, name_glob: Option<&glob::Pattern>) -> Vec<PathBuf> {
+        self.session_files()
+            .into_iter()
+            .filter(|f| self.project_id_of(f) == project_id)
+            .filter(|f| {
+                name_glob
+                    .map(|p| f.file_name().and_then(|n| n.to_str()).is_some_and(|n| p.matches(n)))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
+    fn parse_message(&self, data: &Value) -> Option<Message> {
+        let role = field(data, &self.fields.role)?.as_str()?.to_string();
+        let text = value_text(field(data, &self.fields.text)?)?;
+        let timestamp = self
+            .fields
+            .timestamp
+            .as_deref()
+            .and_then(|path| field(data, path))
+            .and_then(value_timestamp);
+
+        Some(Message {
+            uuid: None,
+            timestamp,
+            msg_type: role.clone(),
+            is_real_user: role == "user",
+            role,
+            content_blocks: vec![ContentBlock {
+                block_type: "text".to_string(),
+                text: Some(text),
+                tool_name: None,
+                tool_input: None,
+                tool_result: None,
+                tool_use_id: None,
+            }],
+            byte_offset: None,
+            is_context: false,
+            subagent: None,
+            parent_uuid: None,
+        })
+    }
+
+    fn parse_session_reader<R: BufRead>(&self, reader: R, file_path: &Path, file_size: u64) -> Option<Session> {
+        let mut messages = Vec::new();
+        let mut cwd: Option<String> = None;
+
+        for (offset, line) in JsonlLines::new(reader) {
+            let data: Value = match serde_json::from_str(&line) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if cwd.is_none() {
+                cwd = self
+                    .fields
+                    .cwd
+                    .as_deref()
+                    .and_then(|path| field(&data, path))
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+            }
+            if let Some(mut msg) = self.parse_message(&data) {
+                msg.byte_offset = Some(offset);
+                messages.push(msg);
+            }
+        }
+
+        if messages.is_empty() {
+            return None;
+        }
+
+        let (total_chars, word_count) = messages_text_stats(&messages);
+        let first_ts = messages.iter().find_map(|m| m.timestamp.clone());
+        let last_ts = messages.iter().rev().find_map(|m| m.timestamp.clone());
+        Some(Session {
+            info: SessionInfo {
+                id: session_stem(file_path)
+                    .unwrap_or("unknown")
+                    .to_string(),
+                file_path: file_path.to_string_lossy().to_string(),
+                cwd,
+                first_timestamp_unix: first_ts.as_deref().and_then(parse_timestamp),
+                last_timestamp_unix: last_ts.as_deref().and_then(parse_timestamp),
+                first_timestamp: first_ts,
+                last_timestamp: last_ts,
+                message_count: messages.len(),
+                user_turn_count: messages.iter().filter(|m| m.is_real_user).count(),
+                file_size,
+                input_tokens: 0,
+                output_tokens: 0,
+                model: None,
+                total_chars,
+                word_count,
+                preview: first_user_preview(&messages),
+            },
+            messages,
+            context_messages: Vec::new(),
+        })
+    }
+
+    fn parse_session_file(&self, file_path: &Path) -> Option<Session> {
+        let (reader, file_size) = open_session(file_path).ok()?;
+        self.parse_session_reader(reader, file_path, file_size)
+    }
+
+    fn scan_session_info(&self, file_path: &Path) -> Option<SessionInfo> {
+        self.parse_session_file(file_path).map(|s| s.info)
+    }
+
+    fn file_contains_keyword(&self, file_path: &Path, keyword: &KeywordMatcher) -> bool {
+        if keyword.is_cancelled() {
+            return false;
+        }
+        match open_session(file_path) {
+            Ok((reader, _)) => JsonlLines::new(reader).any(|(_, line)| keyword.line_matches(&line, |data| self.parse_message(data))),
+            Err(_) => false,
+        }
+    }
+
+    /// 包含关键词且至少有 1 轮对话的会话
+    fn matching_sessions(&self, keyword: &KeywordMatcher) -> Vec<SessionInfo> {
+        self.session_files()
+            .par_iter()
+            .filter(|f| self.file_contains_keyword(f, keyword))
+            .filter_map(|f| self.scan_session_info(f))
+            .filter(|s| s.user_turn_count >= 1)
+            .collect()
+    }
+}
+
+fn file_mtime(path: &Path) -> f64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+impl CliHistoryProvider for GenericJsonlProvider {
+    fn cli_type(&self) -> &'static str {
+        self.name
+    }
+
+    fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    fn list_projects(&self, limit: usize) -> Vec<Project> {
+        let mut groups: HashMap<String, Vec<(PathBuf, f64)>> = HashMap::new();
+        for file in self.session_files() {
+            let mtime = file_mtime(&file);
+            groups.entry(self.project_id_of(&file)).or_default().push((file, mtime));
+        }
+
+        let mut groups: Vec<(String, Vec<(PathBuf, f64)>)> = groups.into_iter().collect();
+        for (_, files) in groups.iter_mut() {
+            files.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        groups.sort_by(|a, b| b.1[0].1.partial_cmp(&a.1[0].1).unwrap_or(std::cmp::Ordering::Equal));
+        if limit > 0 {
+            groups.truncate(limit);
+        }
+
+        groups
+            .into_par_iter()
+            .map(|(id, files)| {
+                let newest = files.iter().find_map(|(f, _)| self.scan_session_info(f));
+                Project {
+                    cwd: newest.as_ref().and_then(|s| s.cwd.clone()),
+                    last_modified: files[0].1,
+                    session_count: files.len(),
+                    last_activity: newest.and_then(|s| s.last_timestamp),
+                    id,
+                }
+            })
+            .collect()
+    }
+
+    fn find_project_by_cwd(&self, cwd: &str) -> Option<Project> {
+        let cwd_normalized = canonicalize_cwd(cwd);
+        self.list_projects(0).into_iter().find(|p| {
+            p.cwd
+                .as_ref()
+                .map(|c| canonicalize_cwd(c) == cwd_normalized)
+                .unwrap_or(false)
+        })
+    }
+
+    fn load_project(&self, project_id: &str, name_glob: Option<&glob::Pattern>, min_turns: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, name_glob)
+            .par_iter()
+            .filter_map(|f| self.scan_session_info(f))
+            .filter(|s| s.user_turn_count >= min_turns.max(1))
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
+
+    fn project_files(&self, project_id: &str) -> Vec<PathBuf> {
+        self.project_session_files(project_id, None)
+    }
+
+    fn all_session_infos(&self, project_id: &str) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, None)
+            .par_iter()
+            .filter_map(|f| self.scan_session_info(f))
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions
+    }
+
+    fn load_session(&self, file_path: &str) -> Option<Session> {
+        self.parse_session_file(Path::new(file_path))
+    }
+
+    fn supports_cache(&self) -> bool {
+        true
+    }
+
+    fn search(&self, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions = self.matching_sessions(keyword);
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn search_project(&self, project_id: &str, keyword: &KeywordMatcher, limit: usize) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .project_session_files(project_id, None)
+            .par_iter()
+            .filter(|f| self.file_contains_keyword(f, keyword))
+            .filter_map(|f| self.scan_session_info(f))
+            .filter(|s| s.user_turn_count >= 1)
+            .collect();
+
+        sort_by_last_activity(&mut sessions);
+        sessions.truncate(limit);
+        sessions
+    }
+
+    fn count_matches(&self, keyword: &KeywordMatcher) -> usize {
+        self.session_files()
+            .par_iter()
+            .filter(|f| self.file_contains_keyword(f, keyword))
+            .count()
+    }
+
+    fn search_each(&self, keyword: &KeywordMatcher, emit: &(dyn Fn(SessionInfo) -> bool + Sync)) {
+        let _ = self
+            .session_files()
+            .par_iter()
+            .filter(|f| self.file_contains_keyword(f, keyword))
+            .filter_map(|f| self.scan_session_info(f))
+            .filter(|s| s.user_turn_count >= 1)
+            .try_for_each(|info| emit(info).then_some(()));
+    }
+
+    fn search_facets(&self, keyword: &KeywordMatcher) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for info in self.matching_sessions(keyword) {
+            *counts.entry(self.project_id_of(Path::new(&info.file_path))).or_insert(0) += 1;
+        }
+
+        let mut facets: Vec<_> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    fn move_to_trash(&self, file_path: &str) -> Result<TrashedSession, HistoryError> {
+        let path = Path::new(file_path);
+        if !path.exists() {
+            return Err(HistoryError::NotFound(format!("文件不存在: {}", file_path)));
+        }
+
+        let session_id = session_stem(path)
+            .unwrap_or("unknown")
+            .to_string();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let item_dir = trash::create_item_dir(&self.trash_dir(), &session_id, timestamp)?;
+
+        let dest_file = item_dir.join(path.file_name().unwrap());
+        if let Err(e) = trash::move_path(path, &dest_file) {
+            fs::remove_dir_all(&item_dir).ok();
+            return Err(e.into());
+        }
+
+        let item = TrashItem {
+            session_id,
+            project_name: self.project_id_of(path),
+            deleted_at: timestamp as i64,
+            dir_name: item_dir.file_name().unwrap().to_string_lossy().to_string(),
+            original_file: file_path.to_string(),
+            original_file_history: None,
+            size_bytes: trash::dir_size(&item_dir),
+        };
+        Ok(TrashedSession {
+            item,
+            moved: vec![(path.to_path_buf(), dest_file)],
+            item_dir,
+        })
+    }
+}
@@ -1,7 +1,13 @@
 //! Providers 模块
 
+pub mod aider;
+pub mod archive;
 pub mod claude;
 pub mod codex;
+pub mod generic;
 
+pub use aider::AiderProvider;
+pub use archive::ArchiveProvider;
 pub use claude::ClaudeProvider;
 pub use codex::CodexProvider;
+pub use generic::{FieldMap, GenericJsonlProvider};
@@ -0,0 +1,190 @@
+//! 搜索结果摘录
+
+use crate::matcher::normalize;
+use unicode_normalization::char::is_combining_mark;
+
+/// 截断处使用的省略号
+const ELLIPSIS: &str = "…";
+
+/// 在文本中查找关键词（与搜索一致：NFC 规范化后忽略大小写，`fold_diacritics` 时忽略变音符号），
+/// 返回带上下文的摘录及匹配位置
+/// 匹配位置为摘录内的 (起始, 结束) 字符偏移（结束不含），可直接用于 Python 切片
+pub fn extract_snippets(
+    text: &str,
+    keyword: &str,
+    fold_diacritics: bool,
+    context_chars: usize,
+    max_snippets: usize,
+) -> Vec<(String, (usize, usize))> {
+    let chars: Vec<char> = text.chars().collect();
+    let (folded, spans) = normalized_chars(&chars, fold_diacritics);
+    let needle: Vec<char> = normalize(keyword, fold_diacritics).chars().collect();
+    if needle.is_empty() || needle.len() > folded.len() {
+        return Vec::new();
+    }
+
+    let mut snippets = Vec::new();
+    let mut pos = 0;
+    while pos + needle.len() <= folded.len() && snippets.len() < max_snippets {
+        if folded[pos..pos + needle.len()] != needle[..] {
+            pos += 1;
+            continue;
+        }
+
+        // 换算回原文字符区间
+        let match_start = spans[pos].0;
+        let match_end = spans[pos + needle.len() - 1].1;
+        let start = match_start.saturating_sub(context_chars);
+        let end = (match_end + context_chars).min(chars.len());
+
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push_str(ELLIPSIS);
+        }
+        let prefix_len = snippet.chars().count();
+        // 换行替换为空格，摘录保持单行
+        snippet.extend(chars[start..end].iter().map(|c| if *c == '\n' || *c == '\r' { ' ' } else { *c }));
+        if end < chars.len() {
+            snippet.push_str(ELLIPSIS);
+        }
+
+        let highlight_start = prefix_len + match_start - start;
+        snippets.push((snippet, (highlight_start, highlight_start + match_end - match_start)));
+        // 跳过已包含在本段摘录中的后续匹配
+        while pos < folded.len() && spans[pos].0 < end {
+            pos += 1;
+        }
+    }
+    snippets
+}
+
+/// 按“基字符 + 其后的组合用字符”分段规范化，返回规范化后的字符及每个字符对应的原文字符区间
+fn normalized_chars(chars: &[char], fold_diacritics: bool) -> (Vec<char>, Vec<(usize, usize)>) {
+    let mut folded = Vec::with_capacity(chars.len());
+    let mut spans = Vec::with_capacity(chars.len());
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start + 1;
+        while end < chars.len() && is_combining_mark(chars[end]) {
+            end += 1;
+        }
+        let cluster: String = chars[start..end].iter().collect();
+        for c in normalize(&cluster, fold_diacritics).chars() {
+            folded.push(c);
+            spans.push((start, end));
+        }
+        start = end;
+    }
+    (folded, spans)
+}
+
+/// 会话预览的最大字符数（不含省略号）
+pub const PREVIEW_CHARS: usize = 200;
+
+/// 生成单行预览：空白折叠为单个空格，超出 PREVIEW_CHARS 时截断并加省略号；无内容时返回 None
+pub fn preview_text(text: &str) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        return None;
+    }
+    match collapsed.char_indices().nth(PREVIEW_CHARS) {
+        Some((cut, _)) => Some(format!("{}{}", &collapsed[..cut], ELLIPSIS)),
+        None => Some(collapsed),
+    }
+}
+
+/// 会话标题最多保留的词数（中日韩字符逐字计为一个词）
+const TITLE_MAX_WORDS: usize = 12;
+
+/// 会话标题的最大字符数（不含省略号）
+const TITLE_MAX_CHARS: usize = 60;
+
+/// 从首条用户输入生成一行标题（纯启发式，结果确定）
+/// 去掉 ``` 代码块和开头的 `/命令`，取第一行有内容的文本的第一句，
+/// 再按词数和字符数截断；没有可用文本时返回 None
+pub fn heuristic_title(text: &str) -> Option<String> {
+    let mut in_fence = false;
+    let line = text
+        .lines()
+        .filter(|line| {
+            let is_fence = line.trim_start().starts_with("```");
+            if is_fence {
+                in_fence = !in_fence;
+            }
+            !is_fence && !in_fence
+        })
+        .map(|line| {
+            let mut words = line.split_whitespace().peekable();
+            while words.next_if(|w| w.starts_with('/') && w.len() > 1).is_some() {}
+            words.collect::<Vec<_>>().join(" ")
+        })
+        .find(|line| !line.is_empty())?;
+
+    let sentence = first_sentence(&line);
+
+    let mut title = String::new();
+    let mut words = 0;
+    let mut truncated = false;
+    for word in sentence.split(' ') {
+        let cjk = word.chars().filter(|c| crate::tokens::is_cjk(*c)).count();
+        let cost = if cjk > 0 { cjk } else { 1 };
+        if words + cost > TITLE_MAX_WORDS && words > 0 {
+            truncated = true;
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+        words += cost;
+    }
+    if let Some((cut, _)) = title.char_indices().nth(TITLE_MAX_CHARS) {
+        title.truncate(cut);
+        truncated = true;
+    }
+
+    let title = title.trim_end_matches([' ', ',', '，', ':', '：', ';', '；']).to_string();
+    if truncated {
+        Some(format!("{}{}", title, ELLIPSIS))
+    } else {
+        Some(title)
+    }
+}
+
+/// 第一句：在中文句末标点处，或后面跟空格的 `.`、`!`、`?` 处截止（不含句末标点）
+fn first_sentence(line: &str) -> &str {
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '。' | '！' | '？' => return &line[..i],
+            '.' | '!' | '?' if chars.peek().map(|(_, n)| *n == ' ').unwrap_or(true) => {
+                return &line[..i];
+            }
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_match_decomposed_text() {
+        let text = "order a cafe\u{301} now";
+        let hits = extract_snippets(text, "caf\u{e9}", false, 3, 5);
+        assert_eq!(hits.len(), 1);
+        let (snippet, (start, end)) = &hits[0];
+        assert_eq!(snippet, "… a cafe\u{301} no…");
+        let highlighted: String = snippet.chars().skip(*start).take(end - start).collect();
+        assert_eq!(highlighted, "cafe\u{301}");
+    }
+
+    #[test]
+    fn snippets_fold_diacritics() {
+        assert!(extract_snippets("Caf\u{e9}", "cafe", false, 10, 5).is_empty());
+        let hits = extract_snippets("Caf\u{e9}", "cafe", true, 10, 5);
+        assert_eq!(hits, vec![("Caf\u{e9}".to_string(), (0, 4))]);
+    }
+}
@@ -0,0 +1,50 @@
+//! 流式搜索
+//!
+//! 后台线程执行 Provider 的并行扫描，匹配结果经有界通道逐个交给 Python 迭代器。
+
+use crate::matcher::KeywordMatcher;
+use crate::provider::CliHistoryProvider;
+use crate::types::SessionInfo;
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+/// 通道容量：Python 侧消费较慢时扫描线程在此阻塞，避免结果无限堆积
+const CHANNEL_CAPACITY: usize = 64;
+
+/// 搜索结果迭代器，扫描结束后停止迭代
+/// 迭代器被回收时通道关闭，后台扫描在下一次产出结果时停止
+#[pyclass]
+pub struct SearchIterator {
+    receiver: Mutex<Receiver<SessionInfo>>,
+}
+
+impl SearchIterator {
+    pub fn start(provider: Arc<dyn CliHistoryProvider>, keyword: KeywordMatcher) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        std::thread::spawn(move || {
+            provider.search_each(&keyword, &|info| sender.send(info).is_ok());
+        });
+        Self {
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+#[pymethods]
+impl SearchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// 等待下一个结果时释放 GIL
+    fn __next__(&self, py: Python<'_>) -> Option<SessionInfo> {
+        let receiver = &self.receiver;
+        py.allow_threads(|| receiver.lock().recv().ok())
+    }
+
+    fn __repr__(&self) -> String {
+        "SearchIterator()".to_string()
+    }
+}
@@ -0,0 +1,92 @@
+//! 测试用临时目录与会话构造
+
+use crate::types::{ContentBlock, Message, Session, SessionInfo};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 临时目录，离开作用域时删除；目录名带进程号，避免并行运行的测试互相干扰
+pub struct TempHome {
+    pub base: PathBuf,
+}
+
+impl TempHome {
+    pub fn new(name: &str) -> Self {
+        let base = std::env::temp_dir().join(format!("liangmu_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&base).ok();
+        fs::create_dir_all(&base).unwrap();
+        Self { base }
+    }
+
+    /// 按行写入相对于临时目录的文件，自动创建上级目录
+    pub fn write_lines<S: AsRef<str>>(&self, relative: impl AsRef<Path>, lines: &[S]) -> PathBuf {
+        let path = self.base.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let content: String = lines.iter().map(|l| format!("{}\n", l.as_ref())).collect();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempHome {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.base).ok();
+    }
+}
+
+/// 只有类型、其余字段为空的内容块
+pub fn block(block_type: &str) -> ContentBlock {
+    ContentBlock {
+        block_type: block_type.to_string(),
+        text: None,
+        tool_name: None,
+        tool_input: None,
+        tool_result: None,
+        tool_use_id: None,
+    }
+}
+
+pub fn text(t: &str) -> ContentBlock {
+    ContentBlock { text: Some(t.to_string()), ..block("text") }
+}
+
+/// 消息；user 消息视为真实用户输入
+pub fn msg(role: &str, content_blocks: Vec<ContentBlock>) -> Message {
+    Message {
+        uuid: None,
+        timestamp: None,
+        msg_type: role.to_string(),
+        role: role.to_string(),
+        content_blocks,
+        is_real_user: role == "user",
+        byte_offset: None,
+        is_context: false,
+        subagent: None,
+        parent_uuid: None,
+    }
+}
+
+/// 会话，文件路径为 `<id>.jsonl`，统计字段只填消息数
+pub fn session(id: &str, messages: Vec<Message>) -> Session {
+    Session {
+        info: SessionInfo {
+            id: id.to_string(),
+            file_path: format!("{}.jsonl", id),
+            cwd: None,
+            first_timestamp: None,
+            last_timestamp: None,
+            first_timestamp_unix: None,
+            last_timestamp_unix: None,
+            message_count: messages.len(),
+            user_turn_count: 0,
+            file_size: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            model: None,
+            total_chars: 0,
+            word_count: 0,
+            preview: None,
+        },
+        messages,
+        context_messages: Vec::new(),
+    }
+}
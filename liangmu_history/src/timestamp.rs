@@ -0,0 +1,34 @@
+//! 时间戳解析
+
+use crate::types::SessionInfo;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+/// 解析会话记录中的时间戳为 Unix 秒
+/// 支持 RFC3339（Claude、Codex 常见格式）、不带时区的 ISO-8601 以及纯日期（均按 UTC 处理）
+pub fn parse_timestamp(ts: &str) -> Option<i64> {
+    let ts = ts.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(ts) {
+        return Some(dt.timestamp());
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(ts, fmt).ok())
+        .or_else(|| NaiveDate::parse_from_str(ts, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+/// 判断会话的时间跨度是否与 [after, before] 有交集（Unix 秒，None 表示不限）
+/// 指定了范围时，没有可解析时间戳的会话视为不在范围内
+pub fn session_in_range(info: &SessionInfo, after: Option<i64>, before: Option<i64>) -> bool {
+    if after.is_none() && before.is_none() {
+        return true;
+    }
+    let first = info.first_timestamp.as_deref().and_then(parse_timestamp);
+    let last = info.last_timestamp.as_deref().and_then(parse_timestamp);
+    let (first, last) = match (first, last) {
+        (Some(f), Some(l)) => (f, l),
+        (Some(t), None) | (None, Some(t)) => (t, t),
+        (None, None) => return false,
+    };
+    after.is_none_or(|a| last >= a) && before.is_none_or(|b| first <= b)
+}
@@ -0,0 +1,43 @@
+//! 近似 token 估算
+//!
+//! 不依赖具体分词器，只求快速且前后一致：中日韩字符大多单独成 token，
+//! 其余文本按约 4 个字符一个 token 计算。
+
+/// 是否为中日韩文字或全角符号
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x303F      // CJK 符号和标点
+        | 0x3040..=0x30FF    // 平假名、片假名
+        | 0x3400..=0x4DBF    // 扩展 A
+        | 0x4E00..=0x9FFF    // 基本汉字
+        | 0xAC00..=0xD7AF    // 韩文音节
+        | 0xF900..=0xFAFF    // 兼容汉字
+        | 0xFF00..=0xFFEF    // 全角字符
+        | 0x20000..=0x2FA1F) // 扩展 B 及以后
+}
+
+/// 估算文本的 token 数
+pub fn estimate_text_tokens(text: &str) -> usize {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| {
+        if is_cjk(c) {
+            (cjk + 1, other)
+        } else if c.is_whitespace() {
+            (cjk, other)
+        } else {
+            (cjk, other + 1)
+        }
+    });
+    cjk + other.div_ceil(4)
+}
+
+/// 统计词数：按空白切分，中日韩字符每个计为一个词
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|word| {
+            let cjk = word.chars().filter(|c| is_cjk(*c)).count();
+            // 去掉中日韩字符后剩余部分（如夹在中文里的英文单词）
+            let rest = word.split(is_cjk).filter(|p| !p.is_empty()).count();
+            cjk + rest
+        })
+        .sum()
+}
@@ -0,0 +1,428 @@
+//! 回收站文件操作
+//!
+//! 回收站记录保存在缓存数据库的 `trash` 表中；旧版写在回收站目录下的
+//! `manifest.json` 会在首次访问时导入数据库并改名为 `manifest.json.migrated`。
+
+use crate::error::HistoryError;
+use crate::types::{TrashItem, TrashManifest};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+lazy_static::lazy_static! {
+    /// 按 CLI 类型自定义的回收站目录
+    static ref TRASH_DIR_OVERRIDES: RwLock<HashMap<String, PathBuf>> = RwLock::new(HashMap::new());
+}
+
+/// 获取自定义回收站目录（未设置时返回 None）
+pub fn dir_override(cli_type: &str) -> Option<PathBuf> {
+    TRASH_DIR_OVERRIDES.read().get(cli_type).cloned()
+}
+
+/// 设置或清除（传 None）自定义回收站目录
+pub fn set_dir_override(cli_type: &str, dir: Option<PathBuf>) {
+    let mut overrides = TRASH_DIR_OVERRIDES.write();
+    match dir {
+        Some(d) => {
+            overrides.insert(cli_type.to_string(), d);
+        }
+        None => {
+            overrides.remove(cli_type);
+        }
+    }
+}
+
+/// 默认保留天数
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+const RETENTION_DAYS_KEY: &str = "trash_retention_days";
+const AUTO_CLEANUP_KEY: &str = "trash_auto_cleanup";
+
+/// 回收站保留策略（保存在缓存数据库的 kv_store 中）
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionSettings {
+    pub retention_days: i64,
+    pub auto_cleanup: bool,
+}
+
+/// 读取保留策略，未配置时为默认天数且不自动清理
+pub fn retention_settings(cli_type: &str) -> RetentionSettings {
+    let retention_days = crate::cache::get_kv(cli_type, RETENTION_DAYS_KEY)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    let auto_cleanup = crate::cache::get_kv(cli_type, AUTO_CLEANUP_KEY)
+        .map(|v| v == "1")
+        .unwrap_or(false);
+    RetentionSettings { retention_days, auto_cleanup }
+}
+
+/// 保存保留策略
+pub fn save_retention_settings(cli_type: &str, settings: RetentionSettings) -> Result<(), HistoryError> {
+    crate::cache::set_kv(cli_type, RETENTION_DAYS_KEY, &settings.retention_days.to_string())?;
+    crate::cache::set_kv(cli_type, AUTO_CLEANUP_KEY, if settings.auto_cleanup { "1" } else { "0" })?;
+    Ok(())
+}
+
+/// 移动文件或目录，跨文件系统时回退为先复制再删除
+pub fn move_path(src: &Path, dst: &Path) -> io::Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            if src.is_dir() {
+                copy_dir_all(src, dst)?;
+                fs::remove_dir_all(src)
+            } else {
+                fs::copy(src, dst)?;
+                fs::remove_file(src)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry.map_err(io::Error::other)?;
+        let rel = entry.path().strip_prefix(src).map_err(io::Error::other)?;
+        let target = dst.join(rel);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// 为已被占用的恢复路径生成不冲突的新路径：`<stem>-restored-<n><扩展名>`
+pub fn deduplicated_path(original: &Path) -> PathBuf {
+    let stem = crate::jsonl::session_stem(original).unwrap_or("session");
+    let name = original.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let ext = &name[stem.len().min(name.len())..];
+    (1..)
+        .map(|n| original.with_file_name(format!("{}-restored-{}{}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+/// 统计目录下所有文件的总字节数
+pub fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// 已移入回收站、尚未写入回收站记录的会话
+pub struct TrashedSession {
+    pub item: TrashItem,
+    /// 已完成的移动（原路径, 回收站路径）
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    pub item_dir: PathBuf,
+}
+
+impl TrashedSession {
+    /// 撤销移动，文件回到原位
+    pub fn rollback(&self) {
+        rollback(&self.moved, &self.item_dir);
+    }
+}
+
+/// 在回收站下创建 `<session_id>_<timestamp>` 子目录；同名目录已存在时追加序号，
+/// 避免同一秒内删除同名会话时互相覆盖
+pub fn create_item_dir(trash_dir: &Path, session_id: &str, timestamp: u64) -> io::Result<PathBuf> {
+    fs::create_dir_all(trash_dir)?;
+    let base = format!("{}_{}", session_id, timestamp);
+    for n in 0.. {
+        let name = if n == 0 { base.clone() } else { format!("{}_{}", base, n) };
+        let dir = trash_dir.join(name);
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// 撤销已完成的移动（按相反顺序移回原位），并删除回收站子目录
+pub fn rollback(moved: &[(PathBuf, PathBuf)], item_dir: &Path) {
+    for (original, dest) in moved.iter().rev() {
+        move_path(dest, original).ok();
+    }
+    fs::remove_dir_all(item_dir).ok();
+}
+
+/// 数据库中标识回收站目录的键
+pub fn dir_key(trash_dir: &Path) -> String {
+    trash_dir.to_string_lossy().to_string()
+}
+
+/// 将回收站目录下旧版 `manifest.json` 导入数据库（一次性）
+/// 导入成功后改名为 `manifest.json.migrated`；manifest 无法解析时返回 Corrupt 且保留原文件
+pub fn migrate_manifest(cli_type: &str, trash_dir: &Path) -> Result<(), HistoryError> {
+    let manifest_path = trash_dir.join("manifest.json");
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&manifest_path)?;
+    let manifest: TrashManifest = serde_json::from_str(&content)
+        .map_err(|e| HistoryError::Corrupt(format!("回收站清单已损坏: {}", e)))?;
+    crate::cache::import_trash_items(cli_type, &dir_key(trash_dir), &manifest.items)?;
+    fs::rename(&manifest_path, trash_dir.join("manifest.json.migrated"))?;
+    Ok(())
+}
+
+/// 从回收站恢复会话，返回 (项目 ID, 恢复后的会话路径)；回收站项不存在时返回 None
+/// 在写事务中取出记录，文件全部移回后才删除记录；任一步失败时已移动的文件移回回收站，记录保留
+pub fn restore_item(
+    cli_type: &str,
+    trash_dir: &Path,
+    dir_name: &str,
+    rename_on_conflict: bool,
+) -> Result<Option<(String, PathBuf)>, HistoryError> {
+    migrate_manifest(cli_type, trash_dir)?;
+
+    let mut restored = None;
+    let found = crate::cache::take_trash_item(cli_type, &dir_key(trash_dir), dir_name, |item| {
+        let item_dir = trash_dir.join(&item.dir_name);
+        if !item_dir.exists() {
+            return Err(HistoryError::NotFound("回收站目录不存在".to_string()));
+        }
+
+        // 先确定所有恢复路径（原路径已存在时不覆盖），再开始移动
+        let session_src = fs::read_dir(&item_dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .find(|p| crate::jsonl::is_session_file(p))
+            .ok_or_else(|| HistoryError::NotFound("回收站目录中没有会话文件".to_string()))?;
+        let target = restore_target(Path::new(&item.original_file), rename_on_conflict)?;
+        let file_history = match &item.original_file_history {
+            Some(fh_path) if item_dir.join("file-history").exists() => {
+                Some((item_dir.join("file-history"), restore_target(Path::new(fh_path), rename_on_conflict)?))
+            }
+            _ => None,
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_path(&session_src, &target)?;
+
+        // 恢复 file-history；失败时把会话文件移回回收站，保持回收站项完整
+        if let Some((fh_src, fh_target)) = &file_history {
+            let moved = fh_target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| move_path(fh_src, fh_target));
+            if let Err(e) = moved {
+                move_path(&target, &session_src).ok();
+                return Err(e.into());
+            }
+        }
+        restored = Some((item.project_name.clone(), target));
+
+        // 删除回收站目录
+        fs::remove_dir_all(&item_dir).ok();
+        Ok(())
+    })?;
+
+    Ok(if found { restored } else { None })
+}
+
+/// 永久删除回收站项：先删除目录，成功后再删除记录
+pub fn delete_item(cli_type: &str, trash_dir: &Path, dir_name: &str) -> Result<(), HistoryError> {
+    migrate_manifest(cli_type, trash_dir)?;
+    let item_dir = trash_dir.join(dir_name);
+    if item_dir.exists() {
+        fs::remove_dir_all(&item_dir)?;
+    }
+    crate::cache::remove_trash_items(cli_type, &dir_key(trash_dir), &[dir_name.to_string()])?;
+    Ok(())
+}
+
+/// 恢复目标路径：原路径未被占用时直接使用；被占用时 `rename_on_conflict` 为 true 则换用不冲突的新路径，
+/// 否则返回 AlreadyExists 错误
+fn restore_target(original: &Path, rename_on_conflict: bool) -> Result<PathBuf, HistoryError> {
+    if !original.exists() {
+        return Ok(original.to_path_buf());
+    }
+    if !rename_on_conflict {
+        return Err(HistoryError::Io(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("原路径已存在，未恢复: {}", original.display()),
+        )));
+    }
+    Ok(deduplicated_path(original))
+}
+
+/// 列出回收站项（先导入旧版 manifest），并统计各项占用的字节数
+pub fn list_items(cli_type: &str, trash_dir: &Path) -> Result<Vec<TrashItem>, HistoryError> {
+    migrate_manifest(cli_type, trash_dir)?;
+    let mut items = crate::cache::list_trash_items(cli_type, &dir_key(trash_dir))?;
+    for item in &mut items {
+        item.size_bytes = dir_size(&trash_dir.join(&item.dir_name));
+    }
+    Ok(items)
+}
+
+/// 记录新的回收站项
+pub fn record_item(cli_type: &str, trash_dir: &Path, item: &TrashItem) -> Result<(), HistoryError> {
+    migrate_manifest(cli_type, trash_dir)?;
+    Ok(crate::cache::insert_trash_item(cli_type, &dir_key(trash_dir), item)?)
+}
+
+/// 在同一事务中记录多个回收站项
+pub fn record_items(cli_type: &str, trash_dir: &Path, items: &[TrashItem]) -> Result<(), HistoryError> {
+    migrate_manifest(cli_type, trash_dir)?;
+    crate::cache::import_trash_items(cli_type, &dir_key(trash_dir), items)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempHome;
+
+    /// 测试用的缓存库，放在测试可执行文件旁的 data 目录；各测试的回收站目录不同，记录互不影响
+    const CLI: &str = "trash_test";
+
+    /// 临时目录，并清掉同名回收站目录以前运行残留的记录
+    fn trash_home(name: &str) -> TempHome {
+        let home = TempHome::new(&format!("trash_{}", name));
+        crate::cache::clear_trash_items(CLI, &dir_key(&home.base.join("trash"))).unwrap();
+        home
+    }
+
+    /// 回收站中的一项：`trash/<dir_name>/<session>.jsonl`，原路径位于 `projects/p/`
+    fn trashed(home: &TempHome, dir_name: &str, with_file_history: bool) -> TrashItem {
+        home.write_lines(format!("trash/{}/s1.jsonl", dir_name), &[r#"{"type":"user"}"#]);
+        if with_file_history {
+            home.write_lines(format!("trash/{}/file-history/edit.txt", dir_name), &["v1"]);
+        }
+        TrashItem {
+            session_id: "s1".to_string(),
+            project_name: "p".to_string(),
+            deleted_at: 1_700_000_000,
+            dir_name: dir_name.to_string(),
+            original_file: home.base.join("projects/p/s1.jsonl").to_string_lossy().to_string(),
+            original_file_history: with_file_history
+                .then(|| home.base.join("file-history/s1").to_string_lossy().to_string()),
+            size_bytes: 0,
+        }
+    }
+
+    fn listed(home: &TempHome) -> Vec<String> {
+        list_items(CLI, &home.base.join("trash"))
+            .unwrap()
+            .into_iter()
+            .map(|i| i.dir_name)
+            .collect()
+    }
+
+    #[test]
+    fn legacy_manifest_is_imported_once() {
+        let home = trash_home("manifest");
+        let trash_dir = home.base.join("trash");
+        let item = trashed(&home, "s1_1", false);
+        let manifest = TrashManifest { items: vec![item] };
+        fs::write(trash_dir.join("manifest.json"), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        assert_eq!(listed(&home), vec!["s1_1"]);
+        assert!(!trash_dir.join("manifest.json").exists());
+        assert!(trash_dir.join("manifest.json.migrated").exists());
+        migrate_manifest(CLI, &trash_dir).unwrap();
+        assert_eq!(listed(&home), vec!["s1_1"]);
+    }
+
+    #[test]
+    fn corrupt_manifest_is_kept() {
+        let home = trash_home("corrupt");
+        let trash_dir = home.base.join("trash");
+        home.write_lines("trash/manifest.json", &["{not json"]);
+        assert!(matches!(migrate_manifest(CLI, &trash_dir), Err(HistoryError::Corrupt(_))));
+        assert!(trash_dir.join("manifest.json").exists());
+    }
+
+    #[test]
+    fn restore_moves_session_and_file_history_back() {
+        let home = trash_home("restore");
+        let trash_dir = home.base.join("trash");
+        record_item(CLI, &trash_dir, &trashed(&home, "s1_1", true)).unwrap();
+
+        let (project, path) = restore_item(CLI, &trash_dir, "s1_1", false).unwrap().unwrap();
+        assert_eq!(project, "p");
+        assert_eq!(path, home.base.join("projects/p/s1.jsonl"));
+        assert!(path.exists());
+        assert!(home.base.join("file-history/s1/edit.txt").exists());
+        assert!(!trash_dir.join("s1_1").exists());
+        assert!(listed(&home).is_empty());
+        assert!(restore_item(CLI, &trash_dir, "s1_1", false).unwrap().is_none());
+    }
+
+    #[test]
+    fn restore_conflict_keeps_item_unless_renaming() {
+        let home = trash_home("conflict");
+        let trash_dir = home.base.join("trash");
+        record_item(CLI, &trash_dir, &trashed(&home, "s1_1", true)).unwrap();
+        home.write_lines("projects/p/s1.jsonl", &["existing"]);
+        home.write_lines("file-history/s1/edit.txt", &["existing"]);
+
+        let err = restore_item(CLI, &trash_dir, "s1_1", false).unwrap_err();
+        assert!(matches!(err, HistoryError::Io(ref e) if e.kind() == io::ErrorKind::AlreadyExists));
+        assert_eq!(listed(&home), vec!["s1_1"]);
+        assert!(trash_dir.join("s1_1/s1.jsonl").exists());
+
+        let (_, path) = restore_item(CLI, &trash_dir, "s1_1", true).unwrap().unwrap();
+        assert_eq!(path, home.base.join("projects/p/s1-restored-1.jsonl"));
+        assert_eq!(fs::read_to_string(home.base.join("projects/p/s1.jsonl")).unwrap(), "existing\n");
+        assert_eq!(fs::read_to_string(home.base.join("file-history/s1/edit.txt")).unwrap(), "existing\n");
+        assert!(home.base.join("file-history/s1-restored-1/edit.txt").exists());
+        assert!(listed(&home).is_empty());
+    }
+
+    #[test]
+    fn failed_move_rolls_back_restore() {
+        let home = trash_home("rollback");
+        let trash_dir = home.base.join("trash");
+        record_item(CLI, &trash_dir, &trashed(&home, "s1_1", true)).unwrap();
+        // file-history 的上级路径是普通文件，移动 file-history 时失败
+        home.write_lines("file-history", &["not a directory"]);
+
+        assert!(restore_item(CLI, &trash_dir, "s1_1", false).is_err());
+        assert!(!home.base.join("projects/p/s1.jsonl").exists());
+        assert!(trash_dir.join("s1_1/s1.jsonl").exists());
+        assert!(trash_dir.join("s1_1/file-history/edit.txt").exists());
+        assert_eq!(listed(&home), vec!["s1_1"]);
+    }
+
+    #[test]
+    fn restore_without_session_file_is_not_found() {
+        let home = trash_home("empty");
+        let trash_dir = home.base.join("trash");
+        let item = trashed(&home, "s1_1", false);
+        fs::remove_file(trash_dir.join("s1_1/s1.jsonl")).unwrap();
+        record_item(CLI, &trash_dir, &item).unwrap();
+
+        assert!(matches!(restore_item(CLI, &trash_dir, "s1_1", false), Err(HistoryError::NotFound(_))));
+        assert_eq!(listed(&home), vec!["s1_1"]);
+    }
+
+    #[test]
+    fn delete_item_removes_directory_and_record() {
+        let home = trash_home("delete");
+        let trash_dir = home.base.join("trash");
+        record_item(CLI, &trash_dir, &trashed(&home, "s1_1", false)).unwrap();
+
+        delete_item(CLI, &trash_dir, "s1_1").unwrap();
+        assert!(!trash_dir.join("s1_1").exists());
+        assert!(listed(&home).is_empty());
+    }
+}
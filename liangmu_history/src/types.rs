@@ -27,6 +27,36 @@ impl Project {
     }
 }
 
+/// 项目分页结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct ProjectPage {
+    #[pyo3(get)]
+    pub projects: Vec<Project>,
+    #[pyo3(get)]
+    pub offset: usize,
+    #[pyo3(get)]
+    pub total_projects: usize,
+}
+
+#[pymethods]
+impl ProjectPage {
+    /// 当前页之后是否还有项目
+    #[getter]
+    fn has_more(&self) -> bool {
+        self.offset + self.projects.len() < self.total_projects
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ProjectPage(offset={}, count={}, total={})",
+            self.offset,
+            self.projects.len(),
+            self.total_projects
+        )
+    }
+}
+
 /// 会话信息
 #[pyclass]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,12 +71,47 @@ pub struct SessionInfo {
     pub first_timestamp: Option<String>,
     #[pyo3(get)]
     pub last_timestamp: Option<String>,
+    /// first_timestamp 解析后的 Unix 秒（无法解析时为 None）
+    #[pyo3(get)]
+    pub first_timestamp_unix: Option<i64>,
+    /// last_timestamp 解析后的 Unix 秒（无法解析时为 None）
+    #[pyo3(get)]
+    pub last_timestamp_unix: Option<i64>,
     #[pyo3(get)]
     pub message_count: usize,
     #[pyo3(get)]
     pub user_turn_count: usize,
     #[pyo3(get)]
     pub file_size: u64,
+    #[pyo3(get)]
+    pub input_tokens: i64,
+    #[pyo3(get)]
+    pub output_tokens: i64,
+    /// 会话使用的模型（使用过多个模型时为最后出现的）
+    #[pyo3(get)]
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 消息正文的字符数（不含工具调用参数与结果）
+    #[pyo3(get)]
+    #[serde(default)]
+    pub total_chars: usize,
+    /// 消息正文的词数（中日韩字符逐字计）
+    #[pyo3(get)]
+    #[serde(default)]
+    pub word_count: usize,
+    /// 首条真实用户输入的预览（跳过注入的上下文，最多约 200 字符）
+    #[pyo3(get)]
+    #[serde(default)]
+    pub preview: Option<String>,
+}
+
+impl SessionInfo {
+    /// 模型名是否包含 `pattern`（不区分大小写，如 `"sonnet"` 匹配 `claude-3-5-sonnet`）
+    pub fn model_matches(&self, pattern: &str) -> bool {
+        self.model
+            .as_deref()
+            .is_some_and(|m| m.to_lowercase().contains(&pattern.to_lowercase()))
+    }
 }
 
 #[pymethods]
@@ -68,10 +133,62 @@ pub struct ContentBlock {
     pub tool_name: Option<String>,
     #[pyo3(get)]
     pub tool_input: Option<String>,
+    /// tool_result 块的工具输出文本
+    #[pyo3(get)]
+    #[serde(default)]
+    pub tool_result: Option<String>,
+    /// 工具调用 ID：tool_use 块为调用自身的 ID，tool_result 块为其对应的调用 ID
+    #[pyo3(get)]
+    #[serde(default)]
+    pub tool_use_id: Option<String>,
+}
+
+/// 把 JSON 值转换为对应的 Python 对象（dict / list / str / int / float / bool / None）
+fn json_to_py(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    use pyo3::types::{PyDict, PyList};
+    use serde_json::Value;
+
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_py(py),
+            (None, Some(u)) => u.into_py(py),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_py(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (k, v) in map {
+                dict.set_item(k, json_to_py(py, v)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
 }
 
 #[pymethods]
 impl ContentBlock {
+    /// 工具输入解析为 Python 对象（通常为 dict），没有工具输入时返回 None
+    /// 无法解析为 JSON 时原样返回字符串
+    fn tool_input_json(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let input = match &self.tool_input {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        match serde_json::from_str::<serde_json::Value>(input) {
+            Ok(value) => json_to_py(py, &value).map(Some),
+            Err(_) => Ok(Some(input.into_py(py))),
+        }
+    }
+
     fn __repr__(&self) -> String {
         format!("ContentBlock(type={})", self.block_type)
     }
@@ -93,14 +210,63 @@ pub struct Message {
     pub content_blocks: Vec<ContentBlock>,
     #[pyo3(get)]
     pub is_real_user: bool,
+    /// 该消息所在行在源文件中的字节偏移
+    #[pyo3(get)]
+    pub byte_offset: Option<u64>,
+    /// 是否为 CLI 注入的上下文消息（system-reminder、命令展开等）
+    #[pyo3(get)]
+    pub is_context: bool,
+    /// 来自子任务（sub-agent）记录时为子任务文件名（不含扩展名），主会话消息为 None
+    #[pyo3(get)]
+    #[serde(default)]
+    pub subagent: Option<String>,
+    /// 父消息的 uuid（Claude 的 parentUuid，回退或编辑后会形成分支）
+    #[pyo3(get)]
+    #[serde(default)]
+    pub parent_uuid: Option<String>,
+}
+
+impl Message {
+    /// 正文（text 块）的 (字符数, 词数)
+    pub fn text_stats(&self) -> (usize, usize) {
+        self.content_blocks
+            .iter()
+            .filter(|b| b.block_type == "text")
+            .filter_map(|b| b.text.as_deref())
+            .fold((0, 0), |(chars, words), text| {
+                (chars + text.chars().count(), words + crate::tokens::count_words(text))
+            })
+    }
+
+    /// 作为会话预览的文本：真实用户输入且非注入上下文时返回预览，否则 None
+    pub fn user_preview(&self) -> Option<String> {
+        if !self.is_real_user || self.is_context {
+            return None;
+        }
+        crate::snippet::preview_text(&self.get_text())
+    }
+}
+
+/// 首条真实用户输入的预览
+pub fn first_user_preview<'a>(messages: impl IntoIterator<Item = &'a Message>) -> Option<String> {
+    messages.into_iter().find_map(|m| m.user_preview())
+}
+
+/// 累加多条消息的正文 (字符数, 词数)
+pub fn messages_text_stats<'a>(messages: impl IntoIterator<Item = &'a Message>) -> (usize, usize) {
+    messages.into_iter().fold((0, 0), |(chars, words), msg| {
+        let (c, w) = msg.text_stats();
+        (chars + c, words + w)
+    })
 }
 
 #[pymethods]
 impl Message {
-    /// 获取纯文本内容
+    /// 获取纯文本内容（不含思考过程）
     pub fn get_text(&self) -> String {
         self.content_blocks
             .iter()
+            .filter(|b| b.block_type != "thinking")
             .filter_map(|b| b.text.as_ref())
             .cloned()
             .collect::<Vec<_>>()
@@ -112,7 +278,7 @@ impl Message {
         self.content_blocks
             .iter()
             .filter(|b| b.block_type == "tool_use")
-            .filter_map(|b| b.tool_name.as_ref().map(|n| n.clone()))
+            .filter_map(|b| b.tool_name.clone())
             .collect()
     }
 
@@ -129,17 +295,113 @@ pub struct Session {
     pub info: SessionInfo,
     #[pyo3(get)]
     pub messages: Vec<Message>,
+    /// 分离出的上下文消息（仅在 load_session 指定 separate_context 时填充）
+    #[pyo3(get)]
+    pub context_messages: Vec<Message>,
+}
+
+impl Session {
+    /// 取从第 `offset` 条消息开始的 `count` 条消息
+    pub fn message_range(&self, offset: usize, count: usize) -> MessageRange {
+        let total_messages = self.messages.len();
+        let offset = offset.min(total_messages);
+        let end = offset.saturating_add(count).min(total_messages);
+        MessageRange {
+            messages: self.messages[offset..end].to_vec(),
+            offset,
+            total_messages,
+        }
+    }
+
+    /// 去掉思考过程块，只剩思考过程的消息整条移除
+    pub fn strip_thinking(&mut self) {
+        for msg in self.messages.iter_mut() {
+            msg.content_blocks.retain(|b| b.block_type != "thinking");
+        }
+        self.messages.retain(|m| !m.content_blocks.is_empty());
+    }
+
+    /// 按时间戳把子任务消息插入主会话消息之间（时间相同时主会话在前，缺少时间戳的主会话消息保持原位）
+    pub fn merge_subagent_messages(&mut self, mut extra: Vec<Message>) {
+        if extra.is_empty() {
+            return;
+        }
+        extra.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+        let mut merged = Vec::with_capacity(self.messages.len() + extra.len());
+        let mut extra = extra.into_iter().peekable();
+        for msg in std::mem::take(&mut self.messages) {
+            if let Some(ts) = msg.timestamp.as_deref() {
+                while let Some(e) = extra.next_if(|e| e.timestamp.as_deref().map(|t| t < ts).unwrap_or(true)) {
+                    merged.push(e);
+                }
+            }
+            merged.push(msg);
+        }
+        merged.extend(extra);
+        self.messages = merged;
+    }
+
+    /// 将上下文消息从 messages 移到 context_messages
+    pub fn separate_context(&mut self) {
+        let (context, dialogue): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.messages).into_iter().partition(|m| m.is_context);
+        self.messages = dialogue;
+        self.context_messages.extend(context);
+    }
 }
 
 #[pymethods]
 impl Session {
+    /// 会话使用的模型，同 info.model
+    #[getter]
+    fn model(&self) -> Option<String> {
+        self.info.model.clone()
+    }
+
+    /// 当前分支：从最后一条主会话消息沿 parent_uuid 上溯到根，按从根到叶的顺序返回
+    /// 回退（rewind）或编辑后被放弃的分支不包含在内；消息没有父子信息时原样返回，子任务消息按时间插回
+    pub fn active_branch(&self) -> Vec<Message> {
+        let (main, subagent): (Vec<&Message>, Vec<&Message>) =
+            self.messages.iter().partition(|m| m.subagent.is_none());
+        if main.iter().all(|m| m.parent_uuid.is_none()) {
+            return self.messages.clone();
+        }
+
+        let by_uuid: HashMap<&str, usize> = main
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| Some((m.uuid.as_deref()?, i)))
+            .collect();
+
+        let mut chain = Vec::new();
+        let mut current = main.iter().rposition(|m| m.uuid.is_some());
+        while let Some(i) = current {
+            // 有环时停止，避免死循环
+            if chain.len() > main.len() {
+                break;
+            }
+            chain.push(i);
+            current = main[i].parent_uuid.as_deref().and_then(|p| by_uuid.get(p).copied());
+        }
+        chain.reverse();
+
+        let mut branch = Session {
+            info: self.info.clone(),
+            messages: chain.into_iter().map(|i| main[i].clone()).collect(),
+            context_messages: Vec::new(),
+        };
+        branch.merge_subagent_messages(subagent.into_iter().cloned().collect());
+        branch.messages
+    }
+
     /// 获取真实用户轮次数
     fn real_turn_count(&self) -> usize {
         self.messages.iter().filter(|m| m.is_real_user).count()
     }
 
     /// 获取工具使用统计
-    fn tool_usage(&self) -> HashMap<String, usize> {
+    pub fn tool_usage(&self) -> HashMap<String, usize> {
         let mut usage = HashMap::new();
         for msg in &self.messages {
             for block in &msg.content_blocks {
@@ -153,11 +415,96 @@ impl Session {
         usage
     }
 
+    /// 按轮次分组消息（每条真实用户消息开启新的一轮）
+    pub fn turns(&self) -> Vec<Vec<Message>> {
+        let mut rounds: Vec<Vec<Message>> = Vec::new();
+        let mut current_round: Vec<Message> = Vec::new();
+
+        for msg in &self.messages {
+            if msg.is_real_user && !current_round.is_empty() {
+                rounds.push(std::mem::take(&mut current_round));
+            }
+            current_round.push(msg.clone());
+        }
+        if !current_round.is_empty() {
+            rounds.push(current_round);
+        }
+        rounds
+    }
+
+    /// 按角色估算 token 数（只统计消息文本，不含工具调用参数）
+    pub fn estimate_tokens(&self) -> TokenEstimate {
+        let mut estimate = TokenEstimate::default();
+        for msg in &self.messages {
+            let tokens = crate::tokens::estimate_text_tokens(&msg.get_text());
+            match msg.role.as_str() {
+                "user" => estimate.user_tokens += tokens,
+                "assistant" => estimate.assistant_tokens += tokens,
+                _ => continue,
+            }
+            estimate.total += tokens;
+        }
+        estimate
+    }
+
+    /// 会话指纹：`<用户输入哈希>-<消息数>`
+    /// 只对真实用户输入做哈希，忽略时间戳等易变字段；用户输入相同的分叉会话共享前缀
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for msg in self.messages.iter().filter(|m| m.is_real_user) {
+            hasher.update(msg.get_text().trim().as_bytes());
+            hasher.update(b"\0");
+        }
+        let hash = hasher.finalize().to_hex();
+        format!("{}-{}", &hash[..16], self.messages.len())
+    }
+
+    /// 渲染为纯文本对话记录（无 Markdown 修饰，跳过工具调用）
+    #[pyo3(signature = (user_label="User", assistant_label="Assistant"))]
+    pub fn to_transcript_text(&self, user_label: &str, assistant_label: &str) -> String {
+        let mut out = String::new();
+        for msg in &self.messages {
+            let label = match msg.role.as_str() {
+                "user" => user_label,
+                "assistant" => assistant_label,
+                _ => continue,
+            };
+            let text = msg.get_text();
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("{}: {}\n\n", label, text));
+        }
+        out
+    }
+
     fn __repr__(&self) -> String {
         format!("Session(id={}, messages={})", self.info.id, self.messages.len())
     }
 }
 
+/// 带摘录的搜索结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    #[pyo3(get)]
+    pub info: SessionInfo,
+    /// 命中位置附近的文本摘录
+    #[pyo3(get)]
+    pub snippets: Vec<String>,
+    /// 与 snippets 一一对应，关键词在摘录中的 (起始, 结束) 字符偏移
+    #[pyo3(get)]
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[pymethods]
+impl SearchHit {
+    fn __repr__(&self) -> String {
+        format!("SearchHit(id={}, snippets={})", self.info.id, self.snippets.len())
+    }
+}
+
 /// 分页消息结果
 #[pyclass]
 #[derive(Debug, Clone)]
@@ -172,16 +519,178 @@ pub struct PaginatedMessages {
     pub total_turns: usize,
     #[pyo3(get)]
     pub total_messages: usize,
+    /// 中间被省略的轮次数
+    #[pyo3(get)]
+    pub middle_turn_count: usize,
+    /// `first` 覆盖的轮次为 [0, first_turn_end)
+    #[pyo3(get)]
+    pub first_turn_end: usize,
+    /// `last` 覆盖的轮次为 [last_turn_start, total_turns)，中间轮次可用 load_turn 按索引取回
+    #[pyo3(get)]
+    pub last_turn_start: usize,
 }
 
 #[pymethods]
 impl PaginatedMessages {
     fn __repr__(&self) -> String {
         format!(
-            "PaginatedMessages(first={}, last={}, has_middle={})",
+            "PaginatedMessages(first={}, last={}, has_middle={}, middle_turns={})",
             self.first.len(),
             self.last.len(),
-            self.has_middle
+            self.has_middle,
+            self.middle_turn_count
+        )
+    }
+}
+
+/// 按消息偏移分页的结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct MessageRange {
+    #[pyo3(get)]
+    pub messages: Vec<Message>,
+    /// 实际起始位置（超出范围时截断到消息总数）
+    #[pyo3(get)]
+    pub offset: usize,
+    #[pyo3(get)]
+    pub total_messages: usize,
+}
+
+#[pymethods]
+impl MessageRange {
+    fn __repr__(&self) -> String {
+        format!(
+            "MessageRange(offset={}, count={}, total={})",
+            self.offset,
+            self.messages.len(),
+            self.total_messages
+        )
+    }
+}
+
+/// 增量读取会话的结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct SessionTail {
+    /// 自上次偏移以来新增的消息
+    #[pyo3(get)]
+    pub messages: Vec<Message>,
+    /// 下次调用时传入的字节偏移
+    #[pyo3(get)]
+    pub end_offset: u64,
+    /// 文件被截断或重写，需要重新加载整个会话
+    #[pyo3(get)]
+    pub needs_reload: bool,
+}
+
+#[pymethods]
+impl SessionTail {
+    fn __repr__(&self) -> String {
+        format!(
+            "SessionTail(messages={}, end_offset={}, needs_reload={})",
+            self.messages.len(),
+            self.end_offset,
+            self.needs_reload
+        )
+    }
+}
+
+/// 缓存概况
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct CacheStats {
+    #[pyo3(get)]
+    pub row_count: usize,
+    #[pyo3(get)]
+    pub project_count: usize,
+    /// 最早写入的缓存行时间（UTC，SQLite CURRENT_TIMESTAMP 格式），缓存为空时为 None
+    #[pyo3(get)]
+    pub oldest_cached_at: Option<String>,
+    #[pyo3(get)]
+    pub newest_cached_at: Option<String>,
+    /// 已缓存会话的源文件总大小（字节，旧版本写入的行按 0 计）
+    #[pyo3(get)]
+    pub total_file_size: u64,
+}
+
+#[pymethods]
+impl CacheStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "CacheStats(rows={}, projects={}, size={})",
+            self.row_count, self.project_count, self.total_file_size
+        )
+    }
+}
+
+/// 近似 token 数（估算规则见 tokens 模块）
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct TokenEstimate {
+    #[pyo3(get)]
+    pub user_tokens: usize,
+    #[pyo3(get)]
+    pub assistant_tokens: usize,
+    #[pyo3(get)]
+    pub total: usize,
+}
+
+#[pymethods]
+impl TokenEstimate {
+    fn __repr__(&self) -> String {
+        format!(
+            "TokenEstimate(user={}, assistant={}, total={})",
+            self.user_tokens, self.assistant_tokens, self.total
+        )
+    }
+}
+
+/// 项目汇总统计
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct ProjectStats {
+    #[pyo3(get)]
+    pub session_count: usize,
+    #[pyo3(get)]
+    pub user_turn_count: usize,
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// 会话文件总大小（字节）
+    #[pyo3(get)]
+    pub total_bytes: u64,
+    #[pyo3(get)]
+    pub input_tokens: i64,
+    #[pyo3(get)]
+    pub output_tokens: i64,
+    /// 工具名 -> 调用次数
+    #[pyo3(get)]
+    pub tool_usage: HashMap<String, usize>,
+}
+
+impl ProjectStats {
+    /// 累加一个会话的计数（不含工具统计）
+    pub fn add_session(&mut self, info: &SessionInfo) {
+        self.session_count += 1;
+        self.user_turn_count += info.user_turn_count;
+        self.message_count += info.message_count;
+        self.total_bytes += info.file_size;
+        self.input_tokens += info.input_tokens;
+        self.output_tokens += info.output_tokens;
+    }
+
+    pub fn add_tool_usage(&mut self, usage: HashMap<String, usize>) {
+        for (name, count) in usage {
+            *self.tool_usage.entry(name).or_insert(0) += count;
+        }
+    }
+}
+
+#[pymethods]
+impl ProjectStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "ProjectStats(sessions={}, turns={}, messages={}, bytes={})",
+            self.session_count, self.user_turn_count, self.message_count, self.total_bytes
         )
     }
 }
@@ -202,6 +711,10 @@ pub struct TrashItem {
     pub original_file: String,
     #[pyo3(get)]
     pub original_file_history: Option<String>,
+    /// 回收站项目录下所有文件的总字节数（列出时计算，不持久化）
+    #[pyo3(get)]
+    #[serde(default, skip_serializing)]
+    pub size_bytes: u64,
 }
 
 #[pymethods]
@@ -211,6 +724,99 @@ impl TrashItem {
     }
 }
 
+/// 刷新并加载会话的结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct RefreshResult {
+    #[pyo3(get)]
+    pub sessions: Vec<SessionInfo>,
+    /// 本次重新写入缓存的会话数
+    #[pyo3(get)]
+    pub refreshed_count: usize,
+    /// 缓存仍然有效、无需刷新的会话数
+    #[pyo3(get)]
+    pub from_cache_count: usize,
+}
+
+#[pymethods]
+impl RefreshResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "RefreshResult(sessions={}, refreshed={}, from_cache={})",
+            self.sessions.len(),
+            self.refreshed_count,
+            self.from_cache_count
+        )
+    }
+}
+
+/// 缓存一致性检查与修复报告
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct RepairReport {
+    /// 删除的孤立行（对应文件已不存在）
+    #[pyo3(get)]
+    pub pruned_count: usize,
+    /// 因修改时间不一致而重新解析的行
+    #[pyo3(get)]
+    pub reparsed_count: usize,
+    /// 新增的未缓存会话
+    #[pyo3(get)]
+    pub added_count: usize,
+}
+
+#[pymethods]
+impl RepairReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "RepairReport(pruned={}, reparsed={}, added={})",
+            self.pruned_count, self.reparsed_count, self.added_count
+        )
+    }
+}
+
+/// 过期回收站清理报告
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct TrashCleanupReport {
+    #[pyo3(get)]
+    pub removed_count: usize,
+    #[pyo3(get)]
+    pub bytes_reclaimed: u64,
+    #[pyo3(get)]
+    pub removed_sessions: Vec<String>,
+}
+
+#[pymethods]
+impl TrashCleanupReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "TrashCleanupReport(removed={}, bytes={})",
+            self.removed_count, self.bytes_reclaimed
+        )
+    }
+}
+
+/// 批量删除中单个会话的结果
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct DeleteResult {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub success: bool,
+    /// 失败原因（成功时为 None）
+    #[pyo3(get)]
+    pub error: Option<String>,
+}
+
+#[pymethods]
+impl DeleteResult {
+    fn __repr__(&self) -> String {
+        format!("DeleteResult(file={}, success={})", self.file_path, self.success)
+    }
+}
+
 /// 回收站清单
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrashManifest {
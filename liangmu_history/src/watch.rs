@@ -0,0 +1,82 @@
+//! 会话目录监听
+//!
+//! 基于 notify 监听 Provider 的会话目录，`.jsonl` 文件创建或修改时回调 Python。
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use pyo3::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// 监听句柄，调用 `stop()` 或被回收时停止监听
+#[pyclass]
+pub struct WatchHandle {
+    #[pyo3(get)]
+    pub path: String,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+#[pymethods]
+impl WatchHandle {
+    /// 停止监听（可重复调用）
+    /// 释放 GIL 后再关闭，避免与正在等待 GIL 的回调线程互相等待
+    fn stop(&self, py: Python<'_>) {
+        let watcher = self.watcher.lock().take();
+        py.allow_threads(move || drop(watcher));
+    }
+
+    /// 是否仍在监听
+    #[getter]
+    fn active(&self) -> bool {
+        self.watcher.lock().is_some()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("WatchHandle(path={}, active={})", self.path, self.active())
+    }
+}
+
+/// 是否为需要通知的事件：创建或内容修改（忽略仅元数据变化）
+fn is_relevant(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+/// 递归监听目录，`.jsonl` 文件创建或修改时以文件路径调用 `callback`
+/// 回调在监听线程中执行，抛出的异常只打印不中断监听
+pub fn start(dir: &Path, callback: PyObject) -> notify::Result<WatchHandle> {
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let event = match res {
+            Ok(e) if is_relevant(&e.kind) => e,
+            _ => return,
+        };
+
+        let mut paths: Vec<PathBuf> = event
+            .paths
+            .into_iter()
+            .filter(|p| crate::jsonl::is_session_file(p))
+            .collect();
+        paths.dedup();
+        if paths.is_empty() {
+            return;
+        }
+
+        Python::with_gil(|py| {
+            for path in paths {
+                if let Err(e) = callback.call1(py, (path.to_string_lossy().to_string(),)) {
+                    e.print(py);
+                }
+            }
+        });
+    })?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+
+    Ok(WatchHandle {
+        path: dir.to_string_lossy().to_string(),
+        watcher: Mutex::new(Some(watcher)),
+    })
+}
@@ -423,7 +423,7 @@ class RustTrashManager:
         lh.permanently_delete(self.cli_type, item['dir_name'])
 
     def cleanup_expired(self) -> int:
-        return lh.cleanup_expired_trash(self.cli_type, TRASH_RETENTION_DAYS)
+        return lh.cleanup_expired_trash(self.cli_type, TRASH_RETENTION_DAYS).removed_count
 
 
 class HistoryManager:
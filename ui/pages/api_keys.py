@@ -325,7 +325,7 @@ def create_api_page(state):
             if lh is not None:
                 if force_refresh:
                     # 强制刷新：扫描文件系统 -> 更新缓存 -> 返回会话列表
-                    raw_sessions = lh.refresh_and_load_sessions(cli_type, cwd)
+                    raw_sessions = lh.refresh_and_load_sessions(cli_type, cwd).sessions
                 else:
                     # 快速模式：优先从缓存查找
                     project = lh.find_project_by_cwd_cached(cli_type, cwd)
@@ -340,7 +340,7 @@ def create_api_page(state):
                         project = lh.find_project_by_cwd(cli_type, cwd)
                         if project:
                             state._current_project = project.id
-                            raw_sessions = lh.refresh_and_load_sessions(cli_type, cwd)
+                            raw_sessions = lh.refresh_and_load_sessions(cli_type, cwd).sessions
             else:
                 # 回退到 Python 实现
                 hm = codex_history_manager if cli_type == 'codex' else history_manager